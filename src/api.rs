@@ -15,7 +15,8 @@ pub mod device {
     /// ```rust
     /// use vcore::device::char::CharacterDevice;
     ///
-    /// let dev = CharacterDevice::new();
+    /// // 连接到实例标识为"vm0"的vcore（使用`--instance vm0`启动）
+    /// let dev = CharacterDevice::new("vm0");
     /// let dev = Arc::new(RwLock::new(dev));
     /// let devrec = Arc::clone(&dev);
     /// let (tx, rx) = mpsc::channel();
@@ -60,8 +61,8 @@ pub mod device {
     ///
     /// 如显示器和磁盘等，需要传输的画面和磁盘数据块直接写入dma的内存。
     ///
-    /// `块设备`抽象层在使用`BlockDevice::new()`后自动连接io端口，而dma内存区域需要
-    /// 驱动程序与设备通信确定并连接。
+    /// `块设备`抽象层在使用`BlockDevice::new(instance)`后自动连接实例`instance`的io端口，
+    /// 而dma内存区域需要驱动程序与设备通信确定并连接。
     pub mod block {
         pub use crate::vrisc::vcore::iocontroller::BlockDevice;
     }
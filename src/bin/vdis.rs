@@ -0,0 +1,59 @@
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    process,
+};
+
+use clap::Parser;
+use vcore::{utils::rdxparse::RadixParse, vrisc::listing::Listing};
+
+/// vrisc ROM镜像反汇编器
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// ROM镜像文件
+    rom: String,
+
+    /// 镜像的加载地址
+    #[arg(short, long, default_value = "0", value_parser = parse_address)]
+    base: u64,
+
+    /// 不输出调用图
+    #[arg(long, default_value_t = false)]
+    no_call_graph: bool,
+}
+
+fn parse_address(s: &str) -> Result<u64, String> {
+    s.rdxparse().map_err(|_| format!("\"{}\"不是数字", s))
+}
+
+fn write(listing: &Listing, call_graph: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    listing.write(&mut out)?;
+    if call_graph {
+        writeln!(out)?;
+        writeln!(out, "; 调用图")?;
+        listing.write_call_graph(&mut out)?;
+    }
+    out.flush()
+}
+
+fn main() {
+    let args = Args::parse();
+    let rom = match fs::read(&args.rom) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}: {}", args.rom, err);
+            process::exit(1);
+        }
+    };
+    let listing = Listing::new(&rom, args.base);
+    match write(&listing, !args.no_call_graph) {
+        // 输出到head等提前退出的管道
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        _ => (),
+    }
+}
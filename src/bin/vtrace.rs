@@ -0,0 +1,79 @@
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Write},
+    process,
+};
+
+use clap::Parser;
+use vcore::machine::trace::{TraceReader, TraceRecord};
+
+/// 以文本形式输出vcore --trace --trace-binary写入的二进制跟踪文件
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// 二进制跟踪文件
+    trace: String,
+
+    /// 只输出此核心执行的指令
+    #[arg(short, long)]
+    core: Option<usize>,
+
+    /// 只输出最后的若干条指令
+    #[arg(short, long)]
+    tail: Option<usize>,
+}
+
+fn write(records: impl Iterator<Item = TraceRecord>) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for record in records {
+        writeln!(out, "{}", record)?;
+    }
+    out.flush()
+}
+
+fn main() {
+    let args = Args::parse();
+    let reader = match TraceReader::open(&args.trace) {
+        Ok(reader) => reader,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    let mut records = VecDeque::new();
+    let mut error = None;
+    let mut invalid = false;
+    for record in reader {
+        match record {
+            Ok(record) if args.core.is_none_or(|core| core == record.core) => {
+                records.push_back(record);
+                if args.tail.is_some_and(|tail| records.len() > tail) {
+                    records.pop_front();
+                }
+                // 不限制条数时边读边输出
+                if args.tail.is_none() {
+                    if let Err(err) = write(records.drain(..)) {
+                        error = Some(err);
+                        break;
+                    }
+                }
+            }
+            Ok(_) => (),
+            // 虚拟机被强行结束时最后一条记录可能不完整
+            Err(err) => {
+                eprintln!("{}: {}", args.trace, err);
+                invalid = true;
+                break;
+            }
+        }
+    }
+    match error.map_or_else(|| write(records.into_iter()), Err) {
+        // 输出到head等提前退出的管道
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        _ if invalid => process::exit(1),
+        _ => (),
+    }
+}
@@ -1,5 +1,7 @@
 use clap::Parser;
 
+use crate::utils::shared;
+
 /// 基于vrisc指令集的虚拟机
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -9,22 +11,88 @@ pub struct Config {
     pub cores: usize,
 
     /// 内存大小
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present_any = ["cleanup", "restore"], default_value_t = 0)]
     pub memory: usize,
 
     /// 虚拟ROM文件
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present_any = ["cleanup", "restore", "asm"], default_value_t = String::new())]
     pub vrom: String,
 
+    /// 代替vrom, 汇编源文件后从地址0启动
+    ///                 (语法见vcore::vrisc::asm::assemble)
+    #[arg(long, conflicts_with = "vrom")]
+    pub asm: Option<String>,
+
+    /// 实例标识(作为所有共享内存名的前缀,
+    ///                 使同一主机上的多个vcore实例互不干扰,
+    ///                 不能包含'/', 不超过200字节;
+    ///                 不指定时使用不带前缀的共享内存名,
+    ///                 同一时间只能运行一个这样的实例)
+    #[arg(long, default_value_t = String::new(), value_parser = parse_instance)]
+    pub instance: String,
+
+    /// 删除此实例之前崩溃时残留的共享内存后退出
+    #[arg(long, default_value_t = false)]
+    pub cleanup: bool,
+
     /// 是否开启调试（在开启了debugger特性的版本中有效）
     #[arg(short, long, default_value_t = false)]
     pub debug: bool,
 
+    /// 在调试模式下用gdb远程串行协议代替调试终端
+    ///                 (端口号表示监听127.0.0.1上的tcp端口, 否则为unix socket路径)
+    #[arg(long, requires = "debug")]
+    pub gdb: Option<String>,
+
     /// 是否开启外部时钟(若不开启外部时钟
-    ///                 则使用周期为4ms的内部时钟)
+    ///                 则定时器初始为周期4ms, 否则初始为停止,
+    ///                 都可以由客户机通过固定端口4重新编程)
     #[arg(short, long, default_value_t = false)]
     pub external_clock: bool,
 
+    /// 确定性模式(所有核心在同一线程中以固定顺序轮流运行,
+    ///                 定时器使用虚拟时间, 不接受外部设备, 不支持调试;
+    ///                 虚拟时间由核心主循环的轮数换算(100轮为1微秒),
+    ///                 nop状态中等待的轮次也计入, 所以等待时钟中断时时间不会停止)
+    #[arg(long, default_value_t = false)]
+    pub deterministic: bool,
+
+    /// 确定性模式下注入的定时事件文件
+    ///                 (格式见vcore::machine::event::TimedEvent)
+    #[arg(long, requires = "deterministic")]
+    pub input: Option<String>,
+
+    /// 把运行时的所有非确定性事件记录到文件
+    ///                 (核心在同一线程中轮流运行, 格式与--input相同)
+    #[arg(long, conflicts_with = "deterministic")]
+    pub record: Option<String>,
+
+    /// 把每个核心执行的每条指令写入跟踪文件
+    ///                 (核心在同一线程中轮流运行, 格式见vcore::machine::trace::TraceRecord)
+    #[arg(long)]
+    pub trace: Option<String>,
+
+    /// 以二进制格式写入跟踪文件(可以用vtrace读取)
+    #[arg(long, requires = "trace", default_value_t = false)]
+    pub trace_binary: bool,
+
+    /// 在确定性模式下重放--record记录的事件文件
+    #[arg(long, conflicts_with = "record")]
+    pub replay: Option<String>,
+
+    /// 从快照文件恢复运行(内存大小和核心数与快照相同,
+    ///                 不再读取vrom)
+    #[arg(long)]
+    pub restore: Option<String>,
+
+    /// 所有核心共执行--snapshot-after条指令后把虚拟机的完整状态保存到快照文件
+    #[arg(long, requires = "snapshot_after")]
+    pub snapshot: Option<String>,
+
+    /// 保存快照之前执行的指令数
+    #[arg(long, requires = "snapshot", default_value_t = 0)]
+    pub snapshot_after: u64,
+
     #[cfg(target_os = "windows")]
     /// 在windows下有效
     ///                 表示此进程是由父进程创建的core进程
@@ -37,3 +105,9 @@ pub struct Config {
     #[arg(short, long, default_value_t = 1)]
     pub id_core: usize,
 }
+
+/// 拒绝不能作为共享内存名前缀的实例标识
+fn parse_instance(instance: &str) -> Result<String, String> {
+    shared::check_instance(instance)?;
+    Ok(instance.to_string())
+}
@@ -1,4 +1,8 @@
+pub mod breakpoint;
 pub mod corehack;
 pub mod debug;
+pub mod gdb;
+pub mod history;
 pub mod memhack;
 pub mod terminal;
+pub mod watchpoint;
@@ -0,0 +1,109 @@
+use crate::vrisc::vcore::regs_flags::{FloatRegisters, Registers};
+
+use super::debug::Regs;
+
+/// 每个核心最多设置的断点数量
+pub const MAX_BREAKPOINTS: usize = 16;
+
+/// ## 断点地址
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BreakAddress {
+    /// 虚拟地址，与`ip`比较
+    Virtual(u64),
+    /// 物理地址，与寻址后的`hot_ip`比较
+    Physical(u64),
+}
+
+/// 条件断点使用的比较
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "==" => Some(Self::Eq),
+            "!=" => Some(Self::Ne),
+            "<" => Some(Self::Lt),
+            "<=" => Some(Self::Le),
+            ">" => Some(Self::Gt),
+            ">=" => Some(Self::Ge),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+
+    /// 按无符号数比较
+    pub fn holds(&self, left: u64, right: u64) -> bool {
+        match self {
+            Self::Eq => left == right,
+            Self::Ne => left != right,
+            Self::Lt => left < right,
+            Self::Le => left <= right,
+            Self::Gt => left > right,
+            Self::Ge => left >= right,
+        }
+    }
+}
+
+/// ## 断点条件
+///
+/// 寄存器`register`的值与`value`比较成立时断点才生效
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Condition {
+    pub register: Regs,
+    pub compare: Compare,
+    pub value: u64,
+}
+
+/// ## 指令断点
+///
+/// 核心即将执行位于`address`的指令且条件成立时，debugger后端把核心切换到step模式并暂停，
+/// 同时增加命中次数
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Breakpoint {
+    /// 由debugger后端分配
+    pub id: u32,
+    pub address: BreakAddress,
+    pub condition: Option<Condition>,
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    pub const fn new(address: BreakAddress, condition: Option<Condition>) -> Self {
+        Self {
+            id: 0,
+            address,
+            condition,
+            hits: 0,
+        }
+    }
+
+    /// 核心在虚拟地址`ip`、物理地址`hot_ip`处时断点是否生效
+    pub fn matches(&self, ip: u64, hot_ip: u64, regs: &Registers, fregs: &FloatRegisters) -> bool {
+        let at = match self.address {
+            BreakAddress::Virtual(address) => address == ip,
+            BreakAddress::Physical(address) => address == hot_ip,
+        };
+        at && self.condition.is_none_or(|condition| {
+            let value = condition.register.read(regs, fregs);
+            condition.compare.holds(value, condition.value)
+        })
+    }
+}
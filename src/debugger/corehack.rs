@@ -11,10 +11,14 @@ use crate::{
         rdxparse::RadixParse,
         shared::{Addressable, SharedPointer},
     },
-    vrisc::vcore::DebugMode,
+    vrisc::{
+        disasm::{Instruction, MAX_LENGTH},
+        vcore::{intcontroller::InterruptId, DebugMode},
+    },
 };
 
 use super::{
+    breakpoint::{BreakAddress, Breakpoint, Compare, Condition, MAX_BREAKPOINTS},
     debug::{Regs, VdbApi},
     terminal::Terminal,
 };
@@ -97,10 +101,26 @@ pub fn run(
             cmd.remove(0);
             continue_instruction(stdout, debugging_core, debug_ports);
         }
+        "break" => {
+            cmd.remove(0);
+            breakpoint(cmd, stdout, debugging_core, debug_ports);
+        }
+        "reverse-step" => {
+            cmd.remove(0);
+            reverse(stdout, debugging_core, debug_ports, VdbApi::ReverseStep);
+        }
+        "reverse-continue" => {
+            cmd.remove(0);
+            reverse(stdout, debugging_core, debug_ports, VdbApi::ReverseContinue);
+        }
         "interrupt" => {
             cmd.remove(0);
             interrupt(cmd, stdout, debugging_core, debug_ports);
         }
+        "pending" => {
+            cmd.remove(0);
+            pending(stdout, debugging_core, debug_ports);
+        }
         "instruction" => {
             cmd.remove(0);
             instruction(stdout, debugging_core, debug_ports, memory);
@@ -140,7 +160,7 @@ pub fn run(
             Terminal::newline(stdout);
             write!(
                 stdout,
-                "  instruction                   查看当前ip指向的指令id\n"
+                "  instruction                   查看当前ip指向的指令id和反汇编\n"
             )
             .unwrap();
             Terminal::newline(stdout);
@@ -157,8 +177,48 @@ pub fn run(
             )
             .unwrap();
             Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  break add <addr> [phys] [<register> <op> <value>]"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "                                添加断点, 核心将要执行addr处的指令时切换到step模式并暂停; addr默认是虚拟地址,"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "                                phys表示物理地址; 给出条件时只在寄存器与value的比较成立时暂停, op为==、!=、<、<=、>、>="
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+            writeln!(stdout, "  break del <id>                删除断点").unwrap();
+            Terminal::newline(stdout);
+            writeln!(stdout, "  break list                    列出断点及命中次数").unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  reverse-step                  在step模式下有效, 回到上一条指令执行之前"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  reverse-continue              在step模式下有效, 回到上一次命中断点的位置(执行历史中没有命中断点时回到最早的检查点)"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
             write!(stdout, "  interrupt <interrupt_id>      产生一个中断\n").unwrap();
             Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  pending                       查看待处理的中断, 核间中断显示发送和接收的核心"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
             write!(stdout, "  exit                          退出当前核心\n").unwrap();
             Terminal::newline(stdout);
             write!(stdout, "  help                          打印此帮助文档\n").unwrap();
@@ -200,6 +260,43 @@ fn interrupt(
     }
 }
 
+fn pending(
+    stdout: &mut Stdout,
+    debugging_core: &mut Option<usize>,
+    debug_ports: &mut [SharedPointer<VdbApi>],
+) {
+    let Some(core) = *debugging_core else {
+        core_not_entered(stdout);
+        return;
+    };
+    let pending = match debug_ports[core].get_result(VdbApi::Pending(None)) {
+        VdbApi::Pending(Some(pending)) => *pending,
+        VdbApi::NotRunning => {
+            core_not_runnig(stdout, debugging_core);
+            return;
+        }
+        _ => {
+            core_noresult(stdout, debugging_core);
+            return;
+        }
+    };
+    writeln!(stdout, "屏蔽字: {:016x}", pending.mask).unwrap();
+    Terminal::newline(stdout);
+    for &(id, msg) in pending.list.iter().take(pending.count) {
+        let intid = InterruptId::generate(id);
+        if let InterruptId::InterProcessor = intid {
+            writeln!(stdout, "{:2} {:?}: 核心{} -> 核心{}", id, intid, msg, core).unwrap();
+        } else {
+            writeln!(stdout, "{:2} {:?}: imsg = {:016x}", id, intid, msg).unwrap();
+        }
+        Terminal::newline(stdout);
+    }
+    if pending.count > pending.list.len() {
+        writeln!(stdout, "...共{}个", pending.count).unwrap();
+        Terminal::newline(stdout);
+    }
+}
+
 fn instruction(
     stdout: &mut Stdout,
     debugging_core: &mut Option<usize>,
@@ -220,8 +317,12 @@ fn instruction(
         core_noresult(stdout, debugging_core);
         return;
     };
-    let inst = *memory.borrow().at(regs.ip);
-    write!(stdout, "0x{:02x}\n", inst).unwrap();
+    if regs.ip >= memory.borrow().size() as u64 {
+        writeln!(stdout, "ip={:#x}超出内存范围", regs.ip).unwrap();
+        return;
+    }
+    let inst = Instruction::decode(memory.borrow().slice(regs.ip, MAX_LENGTH as u64), regs.ip);
+    writeln!(stdout, "0x{:02x}  {}", inst.bytes[0], inst).unwrap();
 }
 
 fn register(
@@ -247,33 +348,8 @@ fn register_write(
         arg_loss(stdout);
         return;
     }
-    let reg = {
-        if cmd[0].starts_with("x") {
-            let mut reg = cmd[0].to_string();
-            reg.remove(0);
-            if let Ok(reg) = reg.rdxparse() {
-                Regs::X(reg)
-            } else {
-                arg_unireg_nan(stdout, &cmd[0]);
-                return;
-            }
-        } else {
-            match cmd[0].as_str() {
-                "ip" => Regs::Ip,
-                "flag" => Regs::Flag,
-                "ivt" => Regs::Ivt,
-                "kpt" => Regs::Kpt,
-                "upt" => Regs::Upt,
-                "scp" => Regs::Scp,
-                "imsg" => Regs::Imsg,
-                "ipdump" => Regs::IpDump,
-                "flagdump" => Regs::FlagDump,
-                _ => {
-                    arg_spereg_nan(stdout, &cmd[0]);
-                    return;
-                }
-            }
-        }
+    let Some(reg) = parse_register(stdout, &cmd[0]) else {
+        return;
     };
     let data: u64 = if let Ok(data) = cmd[1].rdxparse() {
         data
@@ -292,6 +368,39 @@ fn register_write(
     }
 }
 
+/// 解析寄存器名，不是寄存器时打印错误
+fn parse_register(stdout: &mut Stdout, name: &str) -> Option<Regs> {
+    if let Some(reg) = name.strip_prefix("x") {
+        return match reg.rdxparse() {
+            Ok(reg) if reg < 16 => Some(Regs::X(reg)),
+            _ => {
+                arg_unireg_nan(stdout, name);
+                None
+            }
+        };
+    }
+    let reg = match name {
+        "ip" => Regs::Ip,
+        "flag" => Regs::Flag,
+        "ivt" => Regs::Ivt,
+        "kpt" => Regs::Kpt,
+        "upt" => Regs::Upt,
+        "scp" => Regs::Scp,
+        "imsg" => Regs::Imsg,
+        "ipdump" => Regs::IpDump,
+        "flagdump" => Regs::FlagDump,
+        "fcsr" => Regs::Fcsr,
+        _ => match name.strip_prefix("f").map(|reg| reg.rdxparse()) {
+            Some(Ok(reg)) if reg < 16 => Regs::F(reg),
+            _ => {
+                arg_spereg_nan(stdout, name);
+                return None;
+            }
+        },
+    };
+    Some(reg)
+}
+
 fn register_get(
     stdout: &mut Stdout,
     debugging_core: &mut Option<usize>,
@@ -336,6 +445,29 @@ fn register_get(
     write!(stdout, "ipdump  : {:016x}\n", regs.ipdump).unwrap();
     Terminal::newline(stdout);
     write!(stdout, "flagdump: {:016x}\n", regs.flagdump).unwrap();
+    Terminal::newline(stdout);
+    let result = debug_ports[debugging_core.unwrap()].get_result(VdbApi::FloatRegister(None));
+    let fregs = if let VdbApi::FloatRegister(Some(fregs)) = result {
+        *fregs
+    } else {
+        core_noresult(stdout, debugging_core);
+        return;
+    };
+    writeln!(stdout, "浮点寄存器:").unwrap();
+    Terminal::newline(stdout);
+    for i in 0..fregs.f.len() {
+        writeln!(
+            stdout,
+            "f{:02}: {:016x} (f32: {}, f64: {})",
+            i,
+            fregs.f[i],
+            f32::from_bits(fregs.f[i] as u32),
+            f64::from_bits(fregs.f[i])
+        )
+        .unwrap();
+        Terminal::newline(stdout);
+    }
+    writeln!(stdout, "fcsr: {:016x}", fregs.fcsr).unwrap();
 }
 
 fn start(
@@ -413,3 +545,174 @@ fn continue_instruction(
         core_noresult(stdout, debugging_core);
     }
 }
+
+fn reverse(
+    stdout: &mut Stdout,
+    debugging_core: &mut Option<usize>,
+    debug_ports: &mut [SharedPointer<VdbApi>],
+    request: VdbApi,
+) {
+    if debugging_core.is_none() {
+        core_not_entered(stdout);
+        return;
+    }
+    match debug_ports[debugging_core.unwrap()].get_result(request) {
+        VdbApi::NotRunning => core_not_runnig(stdout, debugging_core),
+        VdbApi::Ok => (),
+        VdbApi::NoHistory => {
+            writeln!(stdout, "没有足够的执行历史").unwrap();
+        }
+        VdbApi::None => {
+            writeln!(
+                stdout,
+                "核心{}的debug模式是none, 反向执行命令无效",
+                debugging_core.unwrap()
+            )
+            .unwrap();
+        }
+        _ => core_noresult(stdout, debugging_core),
+    }
+}
+
+fn breakpoint(
+    cmd: &[String],
+    stdout: &mut Stdout,
+    debugging_core: &mut Option<usize>,
+    debug_ports: &mut [SharedPointer<VdbApi>],
+) {
+    if cmd.is_empty() {
+        arg_loss(stdout);
+        return;
+    }
+    let Some(core) = *debugging_core else {
+        core_not_entered(stdout);
+        return;
+    };
+    let request = match cmd[0].as_str() {
+        "add" => {
+            let Some(breakpoint) = parse_breakpoint(stdout, &cmd[1..]) else {
+                return;
+            };
+            VdbApi::AddBreakpoint(breakpoint)
+        }
+        "del" => {
+            let Some(id) = cmd.get(1) else {
+                arg_loss(stdout);
+                return;
+            };
+            let Ok(id) = id.rdxparse() else {
+                arg_nan(stdout, id);
+                return;
+            };
+            VdbApi::DeleteBreakpoint(id)
+        }
+        "list" => {
+            breakpoint_list(stdout, debugging_core, debug_ports);
+            return;
+        }
+        _ => {
+            writeln!(
+                stdout,
+                "未知命令\"core break {}\", 输入\"core help\"获得帮助",
+                cmd[0]
+            )
+            .unwrap();
+            return;
+        }
+    };
+    match debug_ports[core].get_result(request) {
+        VdbApi::BreakpointAdded(id) => {
+            writeln!(stdout, "断点{}", id).unwrap();
+        }
+        VdbApi::TooManyBreakpoints => {
+            writeln!(stdout, "每个核心最多设置{}个断点", MAX_BREAKPOINTS).unwrap();
+        }
+        VdbApi::NoBreakpoint => {
+            writeln!(stdout, "断点\"{}\"不存在", cmd[1]).unwrap();
+        }
+        VdbApi::Ok => (),
+        _ => core_noresult(stdout, debugging_core),
+    }
+}
+
+fn breakpoint_list(
+    stdout: &mut Stdout,
+    debugging_core: &mut Option<usize>,
+    debug_ports: &mut [SharedPointer<VdbApi>],
+) {
+    let core = debugging_core.unwrap();
+    for n in 0.. {
+        let (b, stopped) = match debug_ports[core].get_result(VdbApi::ListBreakpoint(n, None)) {
+            VdbApi::ListBreakpoint(_, Some(reply)) => *reply,
+            VdbApi::NoBreakpoint => break,
+            _ => {
+                core_noresult(stdout, debugging_core);
+                return;
+            }
+        };
+        let address = match b.address {
+            BreakAddress::Virtual(address) => format!("{:016x}", address),
+            BreakAddress::Physical(address) => format!("{:016x} (phys)", address),
+        };
+        write!(stdout, "{:3}: {}", b.id, address).unwrap();
+        if let Some(condition) = b.condition {
+            write!(
+                stdout,
+                " if {} {} 0x{:x}",
+                condition.register,
+                condition.compare.symbol(),
+                condition.value
+            )
+            .unwrap();
+        }
+        write!(stdout, ", 命中{}次", b.hits).unwrap();
+        if stopped {
+            write!(stdout, ", 核心暂停于此").unwrap();
+        }
+        writeln!(stdout).unwrap();
+        Terminal::newline(stdout);
+    }
+}
+
+/// 解析`<addr> [phys] [<register> <op> <value>]`
+fn parse_breakpoint(stdout: &mut Stdout, args: &[String]) -> Option<Breakpoint> {
+    let Some(addr) = args.first() else {
+        arg_loss(stdout);
+        return None;
+    };
+    let Ok(addr) = addr.rdxparse() else {
+        arg_nan(stdout, addr);
+        return None;
+    };
+    let mut args = &args[1..];
+    let address = if args.first().is_some_and(|arg| arg == "phys") {
+        args = &args[1..];
+        BreakAddress::Physical(addr)
+    } else {
+        BreakAddress::Virtual(addr)
+    };
+    let condition = match args {
+        [] => None,
+        [register, compare, value] => {
+            let register = parse_register(stdout, register)?;
+            let Some(compare) = Compare::parse(compare) else {
+                writeln!(stdout, "\"{}\"不是比较运算符", compare).unwrap();
+                return None;
+            };
+            let Ok(value) = value.rdxparse() else {
+                arg_nan(stdout, value);
+                return None;
+            };
+            Some(Condition {
+                register,
+                compare,
+                value,
+            })
+        }
+        _ => {
+            writeln!(stdout, "断点条件应为\"<register> <op> <value>\"").unwrap();
+            return None;
+        }
+    };
+    Some(Breakpoint::new(address, condition))
+}
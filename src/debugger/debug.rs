@@ -1,20 +1,23 @@
 use std::io::{Stdout, Write};
-use std::{thread, time::Duration};
+use std::{fmt, thread, time::Duration};
 
-use crate::vrisc::vcore::intcontroller::{InterruptController, InterruptId};
-use crate::vrisc::vcore::regs_flags::Registers;
+use crate::vrisc::vcore::intcontroller::InterruptId;
+use crate::vrisc::vcore::regs_flags::{FloatRegisters, Registers};
 use crate::{
     utils::{
         memory::Memory,
-        shared::{Addressable, SharedPointer},
+        shared::{shared_name, Addressable, SharedPointer},
     },
-    vrisc::vcore::DebugMode,
+    vrisc::vcore::{DebugMode, Vcore},
 };
 
 use crossterm::execute;
 use crossterm::style::{Attribute, Print, SetAttribute};
 
+use super::breakpoint::{Breakpoint, MAX_BREAKPOINTS};
+use super::history::History;
 use super::terminal::Terminal;
+use super::watchpoint::{Watchpoint, Watchpoints};
 use super::{corehack, memhack};
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -33,6 +36,61 @@ pub enum Regs {
     Imsg,
     IpDump,
     FlagDump,
+    F(usize),
+    Fcsr,
+}
+
+impl fmt::Display for Regs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Regs::None => write!(f, "none"),
+            Regs::X(uni) => write!(f, "x{}", uni),
+            Regs::Ip => write!(f, "ip"),
+            Regs::Flag => write!(f, "flag"),
+            Regs::Ivt => write!(f, "ivt"),
+            Regs::Kpt => write!(f, "kpt"),
+            Regs::Upt => write!(f, "upt"),
+            Regs::Scp => write!(f, "scp"),
+            Regs::Imsg => write!(f, "imsg"),
+            Regs::IpDump => write!(f, "ipdump"),
+            Regs::FlagDump => write!(f, "flagdump"),
+            Regs::F(uni) => write!(f, "f{}", uni),
+            Regs::Fcsr => write!(f, "fcsr"),
+        }
+    }
+}
+
+impl Regs {
+    /// 读取寄存器的值，`Regs::None`为0
+    pub fn read(&self, regs: &Registers, fregs: &FloatRegisters) -> u64 {
+        match *self {
+            Regs::None => 0,
+            Regs::X(uni) => regs.x[uni],
+            Regs::Ip => regs.ip,
+            Regs::Flag => regs.flag,
+            Regs::Ivt => regs.ivt,
+            Regs::Kpt => regs.kpt,
+            Regs::Upt => regs.upt,
+            Regs::Scp => regs.scp,
+            Regs::Imsg => regs.imsg,
+            Regs::IpDump => regs.ipdump,
+            Regs::FlagDump => regs.flagdump,
+            Regs::F(uni) => fregs.f[uni],
+            Regs::Fcsr => fregs.fcsr,
+        }
+    }
+}
+
+/// ## 待处理的中断
+///
+/// 用于debug中传递核心的中断控制器状态，最多传递最先产生的16个中断
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct PendingInterrupts {
+    pub mask: u64,
+    /// 待处理的中断总数
+    pub count: usize,
+    /// (中断号, 编码后的消息)
+    pub list: [(u8, u64); 16],
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -45,12 +103,46 @@ pub enum VdbApi {
     CoreStarted,
     /// 发送时内部包含None，回复时内部包含Some(regs)
     Register(Option<Registers>),
+    /// 浮点寄存器，发送时内部包含None，回复时内部包含Some(fregs)
+    FloatRegister(Option<FloatRegisters>),
     WriteRegister(Regs, u64),
     DebugMode(DebugMode),
+    /// 获得调试模式，发送时内部包含None，回复时内部包含Some(mode)
+    GetDebugMode(Option<DebugMode>),
     /// 获得当前正在执行的指令，发送时内部包含None，回复时内部包含Some(inst)
     Instruction(Option<u8>),
     Interrupt(u8),
+    /// 待处理的中断，发送时内部包含None，回复时内部包含Some(pending)
+    Pending(Option<PendingInterrupts>),
     Continue,
+    /// 回到上一条指令执行之前，在step模式下有效
+    ReverseStep,
+    /// 回到最早的检查点，在step模式下有效
+    ReverseContinue,
+    /// 没有足够的执行历史，用于回应反向执行的请求
+    NoHistory,
+    /// 添加断点，回复时内部为分配的断点号
+    AddBreakpoint(Breakpoint),
+    BreakpointAdded(u32),
+    /// 断点数量已达到`MAX_BREAKPOINTS`
+    TooManyBreakpoints,
+    /// 删除断点，不存在时回复`NoBreakpoint`
+    DeleteBreakpoint(u32),
+    NoBreakpoint,
+    /// 第n个断点，发送时内部包含None，回复时内部包含Some((断点, 是否使核心暂停在当前指令))，
+    /// 超出断点数量时回复`NoBreakpoint`
+    ListBreakpoint(usize, Option<(Breakpoint, bool)>),
+    /// 添加观察点，回复时内部为分配的观察点号
+    AddWatchpoint(Watchpoint),
+    WatchpointAdded(u32),
+    /// 观察点数量已达到`MAX_WATCHPOINTS`
+    TooManyWatchpoints,
+    /// 删除观察点，不存在时回复`NoWatchpoint`
+    DeleteWatchpoint(u32),
+    NoWatchpoint,
+    /// 第n个观察点，发送时内部包含None，回复时内部包含Some(watchpoint)，
+    /// 超出观察点数量时回复`NoWatchpoint`
+    ListWatchpoint(usize, Option<Watchpoint>),
     Exit,
     /// 一般情况下用这个变体回复
     Ok,
@@ -84,10 +176,14 @@ pub struct Debugger<'a> {
 }
 
 impl<'a> Debugger<'a> {
-    pub fn new(memory: usize, cores_debug_port: &'a mut Vec<SharedPointer<VdbApi>>) -> Self {
+    pub fn new(
+        instance: &str,
+        memory: usize,
+        cores_debug_port: &'a mut Vec<SharedPointer<VdbApi>>,
+    ) -> Self {
         println!("VCore Debugger.");
         let mut dbgger = Debugger {
-            memory: Memory::bind(memory),
+            memory: Memory::bind(instance, memory).unwrap(),
             debug_ports: cores_debug_port,
             debugging_core: None,
             terminal: Terminal::new(),
@@ -96,9 +192,9 @@ impl<'a> Debugger<'a> {
         dbgger
     }
 
-    pub fn none(cores_debug_port: &'a mut Vec<SharedPointer<VdbApi>>) -> Self {
+    pub fn none(instance: &str, cores_debug_port: &'a mut Vec<SharedPointer<VdbApi>>) -> Self {
         Debugger {
-            memory: Memory::bind(1),
+            memory: Memory::bind(instance, 1).unwrap(),
             debug_ports: cores_debug_port,
             debugging_core: None,
             terminal: Terminal::none(),
@@ -132,7 +228,7 @@ impl<'a> Debugger<'a> {
         match cmd[0].as_str() {
             "mem" => {
                 cmd.remove(0);
-                memhack::run(&mut cmd, stdout, memory);
+                memhack::run(&mut cmd, stdout, debug_ports, memory);
             }
             "core" => {
                 cmd.remove(0);
@@ -186,14 +282,31 @@ impl<'a> Debugger<'a> {
 
 pub struct DebuggerBackend {
     pub core_debug_port: Box<SharedPointer<VdbApi>>,
+    /// 反向执行使用的执行历史
+    pub history: History,
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint: u32,
+    /// 使核心暂停在当前指令的断点
+    stopped: Option<u32>,
+    /// 从此物理地址继续运行时不再触发断点，避免继续后立刻停在原处
+    resume_at: Option<u64>,
 }
 
 impl DebuggerBackend {
-    pub fn new(id: usize) -> Self {
+    pub fn new(instance: &str, id: usize) -> Self {
         let mut res = Self {
             core_debug_port: Box::new(
-                SharedPointer::<VdbApi>::bind(format!("VcoreCore{}DebugApi", id), 1).unwrap(),
+                SharedPointer::<VdbApi>::bind(
+                    shared_name(instance, &format!("VcoreCore{}DebugApi", id)),
+                    1,
+                )
+                .unwrap(),
             ),
+            history: History::new(),
+            breakpoints: Vec::new(),
+            next_breakpoint: 1,
+            stopped: None,
+            resume_at: None,
         };
         res.core_debug_port.write(0, VdbApi::Initialized);
         res
@@ -213,6 +326,7 @@ impl DebuggerBackend {
         &mut self,
         core_startflg: &mut SharedPointer<(bool, u64)>,
         debug_mode: &mut DebugMode,
+        watchpoints: &mut Watchpoints,
     ) -> Option<bool> {
         match **self.core_debug_port {
             VdbApi::Initialized => Some(true),
@@ -227,8 +341,15 @@ impl DebuggerBackend {
                 self.core_debug_port.write(0, VdbApi::Ok);
                 Some(false)
             }
+            VdbApi::GetDebugMode(None) => {
+                self.core_debug_port
+                    .write(0, VdbApi::GetDebugMode(Some(*debug_mode)));
+                Some(false)
+            }
             _ => {
-                self.core_debug_port.write(0, VdbApi::NotRunning);
+                if !self.breakpoint_request() && !self.watchpoint_request(watchpoints) {
+                    self.core_debug_port.write(0, VdbApi::NotRunning);
+                }
                 Some(false)
             }
         }
@@ -244,14 +365,17 @@ impl DebuggerBackend {
     /// None - 退出
     /// Some(true) - 返回后要continue
     /// Some(false) - ()
-    pub fn after_start(
-        &mut self,
-        hot_ip: u64,
-        regs: &mut Registers,
-        intctl: &mut InterruptController,
-        debug_mode: &mut DebugMode,
-        memory: &mut Memory,
-    ) -> Option<bool> {
+    pub fn after_start(&mut self, core: &mut Vcore) -> Option<bool> {
+        let hot_ip = core.lazyaddr.hot_ip;
+        let Vcore {
+            regs,
+            fregs,
+            intctler: intctl,
+            debug_mode,
+            memory,
+            watchpoints,
+            ..
+        } = core;
         match **self.core_debug_port {
             VdbApi::Exit => {
                 return None;
@@ -260,6 +384,10 @@ impl DebuggerBackend {
                 self.core_debug_port
                     .write(0, VdbApi::Register(Some(regs.clone())));
             }
+            VdbApi::FloatRegister(None) => {
+                self.core_debug_port
+                    .write(0, VdbApi::FloatRegister(Some(*fregs)));
+            }
             VdbApi::WriteRegister(register, value) => {
                 match register {
                     Regs::X(uni) => {
@@ -292,6 +420,12 @@ impl DebuggerBackend {
                     Regs::FlagDump => {
                         regs.flagdump = value;
                     }
+                    Regs::F(uni) => {
+                        fregs.f[uni] = value;
+                    }
+                    Regs::Fcsr => {
+                        fregs.fcsr = value;
+                    }
                     _ => (),
                 }
                 self.core_debug_port.write(0, VdbApi::Ok);
@@ -301,8 +435,14 @@ impl DebuggerBackend {
             }
             VdbApi::DebugMode(mode) => {
                 *debug_mode = mode;
+                self.stopped = None;
+                self.resume_at = Some(hot_ip);
                 self.core_debug_port.write(0, VdbApi::Ok);
             }
+            VdbApi::GetDebugMode(None) => {
+                self.core_debug_port
+                    .write(0, VdbApi::GetDebugMode(Some(*debug_mode)));
+            }
             VdbApi::Instruction(None) => {
                 self.core_debug_port
                     .write(0, VdbApi::Instruction(Some(*memory.borrow().at(hot_ip))));
@@ -310,13 +450,39 @@ impl DebuggerBackend {
             VdbApi::Continue => {
                 if *debug_mode == DebugMode::None {
                     self.core_debug_port.write(0, VdbApi::None);
+                } else {
+                    self.stopped = None;
                 }
             }
             VdbApi::Interrupt(id) => {
                 intctl.interrupt(InterruptId::generate(id));
                 self.core_debug_port.write(0, VdbApi::Ok);
             }
-            _ => (),
+            VdbApi::Pending(None) => {
+                let mut pending = PendingInterrupts {
+                    mask: intctl.mask(),
+                    count: intctl.pending().count(),
+                    list: [(0, 0); 16],
+                };
+                for (slot, p) in pending.list.iter_mut().zip(intctl.pending()) {
+                    *slot = (p.id as u8, p.msg);
+                }
+                self.core_debug_port
+                    .write(0, VdbApi::Pending(Some(pending)));
+            }
+            _ => {
+                if !self.breakpoint_request() {
+                    self.watchpoint_request(watchpoints);
+                }
+            }
+        }
+        if *debug_mode == DebugMode::None && self.resume_at.take() != Some(hot_ip) {
+            if let Some(id) = self.breakpoint(regs.ip, hot_ip, regs, fregs) {
+                let breakpoint = self.breakpoints.iter_mut().find(|b| b.id == id).unwrap();
+                breakpoint.hits += 1;
+                self.stopped = Some(id);
+                *debug_mode = DebugMode::Step;
+            }
         }
         if *debug_mode == DebugMode::Step {
             if let VdbApi::Continue = **self.core_debug_port {
@@ -330,4 +496,84 @@ impl DebuggerBackend {
             Some(false)
         }
     }
+
+    /// 在虚拟地址`ip`、物理地址`hot_ip`处生效的断点
+    pub fn breakpoint(
+        &self,
+        ip: u64,
+        hot_ip: u64,
+        regs: &Registers,
+        fregs: &FloatRegisters,
+    ) -> Option<u32> {
+        self.breakpoints
+            .iter()
+            .find(|b| b.matches(ip, hot_ip, regs, fregs))
+            .map(|b| b.id)
+    }
+
+    /// 处理添加、删除、列出断点的请求，核心未启动时也可以使用
+    fn breakpoint_request(&mut self) -> bool {
+        match **self.core_debug_port {
+            VdbApi::AddBreakpoint(mut breakpoint) => {
+                if self.breakpoints.len() >= MAX_BREAKPOINTS {
+                    self.core_debug_port.write(0, VdbApi::TooManyBreakpoints);
+                } else {
+                    breakpoint.id = self.next_breakpoint;
+                    breakpoint.hits = 0;
+                    self.next_breakpoint += 1;
+                    self.breakpoints.push(breakpoint);
+                    self.core_debug_port
+                        .write(0, VdbApi::BreakpointAdded(breakpoint.id));
+                }
+            }
+            VdbApi::DeleteBreakpoint(id) => {
+                if let Some(i) = self.breakpoints.iter().position(|b| b.id == id) {
+                    self.breakpoints.remove(i);
+                    self.core_debug_port.write(0, VdbApi::Ok);
+                } else {
+                    self.core_debug_port.write(0, VdbApi::NoBreakpoint);
+                }
+            }
+            VdbApi::ListBreakpoint(n, None) => match self.breakpoints.get(n) {
+                Some(&b) => {
+                    let stopped = self.stopped == Some(b.id);
+                    self.core_debug_port
+                        .write(0, VdbApi::ListBreakpoint(n, Some((b, stopped))));
+                }
+                None => self.core_debug_port.write(0, VdbApi::NoBreakpoint),
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// 处理添加、删除、列出观察点的请求，核心未启动时也可以使用
+    fn watchpoint_request(&mut self, watchpoints: &mut Watchpoints) -> bool {
+        match **self.core_debug_port {
+            VdbApi::AddWatchpoint(watchpoint) => {
+                let reply = match watchpoints.add(watchpoint) {
+                    Some(id) => VdbApi::WatchpointAdded(id),
+                    None => VdbApi::TooManyWatchpoints,
+                };
+                self.core_debug_port.write(0, reply);
+            }
+            VdbApi::DeleteWatchpoint(id) => {
+                let reply = if watchpoints.remove(id) {
+                    VdbApi::Ok
+                } else {
+                    VdbApi::NoWatchpoint
+                };
+                self.core_debug_port.write(0, reply);
+            }
+            VdbApi::ListWatchpoint(n, None) => {
+                let reply = match watchpoints.get(n) {
+                    Some(watchpoint) => VdbApi::ListWatchpoint(n, Some(watchpoint)),
+                    None => VdbApi::NoWatchpoint,
+                };
+                self.core_debug_port.write(0, reply);
+            }
+            _ => return false,
+        }
+        true
+    }
 }
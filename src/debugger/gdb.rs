@@ -0,0 +1,654 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+};
+
+use crate::{
+    utils::{
+        memory::Memory,
+        shared::{Addressable, SharedPointer},
+    },
+    vrisc::vcore::DebugMode,
+};
+
+use super::{
+    breakpoint::{BreakAddress, Breakpoint},
+    debug::{Regs, VdbApi},
+};
+
+/// gdb看到的寄存器数量：x0-x15、9个内部寄存器、f0-f15和fcsr
+pub const GDB_REGISTERS: usize = 42;
+
+/// 停止原因中的信号
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// gdb寄存器号对应的寄存器，顺序与`target_xml`相同
+pub fn register(n: usize) -> Option<Regs> {
+    let reg = match n {
+        0..=15 => Regs::X(n),
+        16 => Regs::Ip,
+        17 => Regs::Flag,
+        18 => Regs::Ivt,
+        19 => Regs::Kpt,
+        20 => Regs::Upt,
+        21 => Regs::Scp,
+        22 => Regs::Imsg,
+        23 => Regs::IpDump,
+        24 => Regs::FlagDump,
+        25..=40 => Regs::F(n - 25),
+        41 => Regs::Fcsr,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+/// ## vrisc的target description
+///
+/// 通过`qXfer:features:read:target.xml`发送给gdb，描述`register`中的寄存器
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.vrisc.core\">\n",
+    );
+    for n in 0..GDB_REGISTERS {
+        let reg = register(n).unwrap();
+        if n == 25 {
+            xml.push_str("</feature>\n<feature name=\"org.vrisc.float\">\n");
+        }
+        let ty = match reg {
+            Regs::Ip => "code_ptr",
+            Regs::X(15) => "data_ptr",
+            Regs::F(_) => "ieee_double",
+            _ => "uint64",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"64\" type=\"{}\" regnum=\"{}\"/>\n",
+            reg, ty, n
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// 把回复编码为`$<data>#<校验和>`，转义`#`、`$`、`}`、`*`
+pub fn encode_packet(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', byte ^ 0x20]);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend(&escaped);
+    packet.extend(format!("#{:02x}", checksum(&escaped)).bytes());
+    packet
+}
+
+/// 从gdb读到的内容
+#[derive(Debug, PartialEq, Eq)]
+pub enum Incoming {
+    /// 校验和正确的包，不含`$`和校验和
+    Packet(Vec<u8>),
+    /// 包之外的Ctrl-C（0x03）
+    Interrupt,
+}
+
+/// ## 读取下一个包
+///
+/// 跳过gdb的确认（`+`、`-`），校验和正确时回复`+`，否则回复`-`并等待重发。
+/// 连接关闭时返回None
+pub fn read_packet(conn: &mut (impl Read + Write)) -> io::Result<Option<Incoming>> {
+    loop {
+        match read_byte(conn)? {
+            None => return Ok(None),
+            Some(0x03) => return Ok(Some(Incoming::Interrupt)),
+            Some(b'$') => (),
+            Some(_) => continue,
+        }
+        let mut data = Vec::new();
+        loop {
+            match read_byte(conn)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+            }
+        }
+        let mut sum = [0u8; 2];
+        for digit in sum.iter_mut() {
+            match read_byte(conn)? {
+                None => return Ok(None),
+                Some(byte) => *digit = byte,
+            }
+        }
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if expected == Some(checksum(&data)) {
+            conn.write_all(b"+")?;
+            return Ok(Some(Incoming::Packet(data)));
+        }
+        conn.write_all(b"-")?;
+    }
+}
+
+fn read_byte(conn: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        match conn.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// 寄存器的值按目标字节序（小端）编码
+fn register_hex(value: u64) -> String {
+    hex(&value.to_le_bytes())
+}
+
+fn parse_register_hex(s: &str) -> Option<u64> {
+    let bytes: [u8; 8] = unhex(s)?.try_into().ok()?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// `addr,len`
+fn parse_range(s: &str) -> Option<(u64, u64)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((
+        u64::from_str_radix(addr, 16).ok()?,
+        u64::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// `address`是端口号时监听本机的tcp端口，否则作为unix socket的路径
+    fn bind(address: &str) -> io::Result<Self> {
+        if let Ok(port) = address.parse::<u16>() {
+            return Ok(Self::Tcp(TcpListener::bind(("127.0.0.1", port))?));
+        }
+        #[cfg(unix)]
+        {
+            // 删除上次运行留下的socket
+            if fs::metadata(address).is_ok_and(|meta| meta.file_type().is_socket()) {
+                fs::remove_file(address)?;
+            }
+            Ok(Self::Unix(UnixListener::bind(address)?))
+        }
+        #[cfg(not(unix))]
+        Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("\"{}\"不是端口号", address),
+        ))
+    }
+
+    fn accept(&self) -> io::Result<Connection> {
+        Ok(match self {
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Connection::Tcp(stream)
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => Connection::Unix(listener.accept()?.0),
+        })
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// 处理一个包之后要做的事
+enum Action {
+    Reply(String),
+    /// gdb断开，核心继续运行
+    Detach,
+    /// 结束vcore
+    Kill,
+}
+
+/// ## gdb远程串行协议
+///
+/// 代替debugger终端，通过与终端相同的`VdbApi`调试核心，gdb中的每个线程是一个核心（线程号为核心号加1）。
+/// 使用all-stop模式：一个核心因断点或单步停下时所有核心都切换到step模式。
+///
+/// 内存读写使用物理地址，断点（`Z0`、`Z1`）使用虚拟地址并设置在所有核心上。
+pub struct GdbStub<'a> {
+    memory: Memory,
+    debug_ports: &'a mut Vec<SharedPointer<VdbApi>>,
+    /// `g`、`p`、`s`等命令操作的核心
+    current: usize,
+    /// 断点地址 -> 每个核心中的断点号
+    breakpoints: HashMap<u64, Vec<Option<u32>>>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(
+        instance: &str,
+        memory: usize,
+        debug_ports: &'a mut Vec<SharedPointer<VdbApi>>,
+    ) -> Self {
+        Self {
+            memory: Memory::bind(instance, memory).unwrap(),
+            debug_ports,
+            current: 0,
+            breakpoints: HashMap::new(),
+        }
+    }
+
+    /// ## 等待gdb连接并处理请求
+    ///
+    /// gdb断开后等待下一次连接，gdb要求结束（`k`）时返回
+    pub fn serve(&mut self, address: &str) -> io::Result<()> {
+        let listener = Listener::bind(address)?;
+        println!("等待gdb连接到{}", address);
+        loop {
+            let mut conn = listener.accept()?;
+            self.attach();
+            if !self.session(&mut conn)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 所有核心切换到step模式，启动核心0
+    fn attach(&mut self) {
+        self.current = 0;
+        self.stop_all();
+        self.debug_ports[0].get_result(VdbApi::StartCore);
+    }
+
+    fn stop_all(&mut self) {
+        for port in self.debug_ports.iter_mut() {
+            port.get_result(VdbApi::DebugMode(DebugMode::Step));
+        }
+    }
+
+    /// 处理一次连接，返回false表示要结束vcore
+    fn session(&mut self, conn: &mut Connection) -> io::Result<bool> {
+        loop {
+            let Some(incoming) = read_packet(conn)? else {
+                return Ok(true);
+            };
+            // 核心已经停止时收到的Ctrl-C没有意义
+            let Incoming::Packet(packet) = incoming else {
+                continue;
+            };
+            let packet = String::from_utf8_lossy(&packet).to_string();
+            match self.handle(&packet, conn)? {
+                Action::Reply(reply) => conn.write_all(&encode_packet(reply.as_bytes()))?,
+                Action::Detach => {
+                    conn.write_all(&encode_packet(b"OK"))?;
+                    for port in self.debug_ports.iter_mut() {
+                        port.get_result(VdbApi::DebugMode(DebugMode::None));
+                    }
+                    return Ok(true);
+                }
+                Action::Kill => return Ok(false),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str, conn: &mut Connection) -> io::Result<Action> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => self.stop_reply(self.current, SIGTRAP),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => self.read_register(&packet[1..]),
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'H') => self.set_thread(&packet[2..]),
+            Some(b'T') => match self.thread(&packet[1..]) {
+                Some(_) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            Some(b's') => self.step(),
+            Some(b'c') => match self.resume(conn)? {
+                Some(reply) => reply,
+                None => return Ok(Action::Detach),
+            },
+            Some(b'Z') => self.insert_breakpoint(&packet[1..]),
+            Some(b'z') => self.remove_breakpoint(&packet[1..]),
+            Some(b'D') => return Ok(Action::Detach),
+            Some(b'k') => return Ok(Action::Kill),
+            _ if packet.starts_with("vKill") => return Ok(Action::Kill),
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;qXfer:features:read+".to_string()
+            }
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                self.target_xml(&packet["qXfer:features:read:target.xml:".len()..])
+            }
+            _ if packet == "qAttached" => "1".to_string(),
+            _ if packet == "qC" => format!("QC{:x}", self.current + 1),
+            _ if packet == "qfThreadInfo" => {
+                let threads: Vec<String> = (1..=self.debug_ports.len())
+                    .map(|tid| format!("{:x}", tid))
+                    .collect();
+                format!("m{}", threads.join(","))
+            }
+            _ if packet == "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    fn stop_reply(&self, core: usize, signal: u8) -> String {
+        format!("T{:02x}thread:{:x};", signal, core + 1)
+    }
+
+    /// 线程号对应的核心，0和-1表示任意核心
+    fn thread(&self, tid: &str) -> Option<usize> {
+        match tid {
+            "0" | "-1" => Some(self.current),
+            _ => usize::from_str_radix(tid, 16)
+                .ok()
+                .filter(|&tid| (1..=self.debug_ports.len()).contains(&tid))
+                .map(|tid| tid - 1),
+        }
+    }
+
+    fn set_thread(&mut self, tid: &str) -> String {
+        match self.thread(tid) {
+            Some(core) => {
+                self.current = core;
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    /// 当前核心所有寄存器的值，核心未启动时为None
+    fn registers(&mut self) -> Option<[u64; GDB_REGISTERS]> {
+        let port = &mut self.debug_ports[self.current];
+        let VdbApi::Register(Some(regs)) = *port.get_result(VdbApi::Register(None)) else {
+            return None;
+        };
+        let VdbApi::FloatRegister(Some(fregs)) = *port.get_result(VdbApi::FloatRegister(None))
+        else {
+            return None;
+        };
+        let mut values = [0; GDB_REGISTERS];
+        for (n, value) in values.iter_mut().enumerate() {
+            *value = register(n).unwrap().read(&regs, &fregs);
+        }
+        Some(values)
+    }
+
+    fn read_registers(&mut self) -> String {
+        match self.registers() {
+            Some(values) => values.iter().map(|&value| register_hex(value)).collect(),
+            // 未启动的核心的寄存器不可用
+            None => "x".repeat(16 * GDB_REGISTERS),
+        }
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        if data.len() != 16 * GDB_REGISTERS {
+            return "E01".to_string();
+        }
+        for n in 0..GDB_REGISTERS {
+            let Some(value) = parse_register_hex(&data[16 * n..16 * (n + 1)]) else {
+                return "E01".to_string();
+            };
+            if !self.set_register(n, value) {
+                return "E02".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&mut self, n: &str) -> String {
+        let Some(n) = usize::from_str_radix(n, 16)
+            .ok()
+            .filter(|&n| n < GDB_REGISTERS)
+        else {
+            return "E01".to_string();
+        };
+        match self.registers() {
+            Some(values) => register_hex(values[n]),
+            None => "x".repeat(16),
+        }
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let parsed = assignment.split_once('=').and_then(|(n, value)| {
+            let n = usize::from_str_radix(n, 16).ok()?;
+            Some((n, parse_register_hex(value)?))
+        });
+        match parsed {
+            Some((n, value)) if n < GDB_REGISTERS => {
+                if self.set_register(n, value) {
+                    "OK".to_string()
+                } else {
+                    "E02".to_string()
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u64) -> bool {
+        let reg = register(n).unwrap();
+        matches!(
+            self.debug_ports[self.current].get_result(VdbApi::WriteRegister(reg, value)),
+            VdbApi::Ok
+        )
+    }
+
+    /// 物理内存中的`[addr, addr + len)`，越界时返回None
+    fn range(&self, addr: u64, len: u64) -> Option<(u64, u64)> {
+        let size = self.memory.borrow().size() as u64;
+        (addr.checked_add(len)? <= size).then_some((addr, len))
+    }
+
+    fn read_memory(&mut self, args: &str) -> String {
+        match parse_range(args).and_then(|(addr, len)| self.range(addr, len)) {
+            Some((addr, len)) => hex(self.memory.borrow().slice(addr, len)),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let range = parse_range(range).and_then(|(addr, len)| self.range(addr, len));
+        match (range, unhex(data)) {
+            (Some((addr, len)), Some(data)) if data.len() as u64 == len => {
+                self.memory.borrow_mut().write_slice(addr, &data);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// 当前核心执行一条指令
+    fn step(&mut self) -> String {
+        match self.debug_ports[self.current].get_result(VdbApi::Continue) {
+            VdbApi::Ok => self.stop_reply(self.current, SIGTRAP),
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// ## 所有核心继续运行
+    ///
+    /// 直到某个核心因断点等原因切换回step模式，或者gdb发送Ctrl-C，然后停止所有核心。
+    /// gdb断开时返回None
+    fn resume(&mut self, conn: &mut Connection) -> io::Result<Option<String>> {
+        for port in self.debug_ports.iter_mut() {
+            port.get_result(VdbApi::DebugMode(DebugMode::None));
+        }
+        conn.set_read_timeout(Some(Duration::from_millis(10)))?;
+        let stopped = loop {
+            let mut byte = [0u8];
+            match conn.read(&mut byte) {
+                Ok(0) => break None,
+                Ok(_) if byte[0] == 0x03 => break Some((self.current, SIGINT)),
+                Ok(_) => (),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(err) => return Err(err),
+            }
+            let stopped = self.debug_ports.iter_mut().position(|port| {
+                matches!(
+                    port.get_result(VdbApi::GetDebugMode(None)),
+                    VdbApi::GetDebugMode(Some(DebugMode::Step))
+                )
+            });
+            if let Some(core) = stopped {
+                break Some((core, SIGTRAP));
+            }
+        };
+        conn.set_read_timeout(None)?;
+        let Some((core, signal)) = stopped else {
+            return Ok(None);
+        };
+        self.stop_all();
+        self.current = core;
+        Ok(Some(self.stop_reply(core, signal)))
+    }
+
+    /// `<type>,<addr>,<kind>`，只支持软件和硬件断点
+    fn breakpoint_address(args: &str) -> Option<u64> {
+        let mut fields = args.split(',');
+        if !matches!(fields.next(), Some("0" | "1")) {
+            return None;
+        }
+        u64::from_str_radix(fields.next()?, 16).ok()
+    }
+
+    fn insert_breakpoint(&mut self, args: &str) -> String {
+        let Some(addr) = Self::breakpoint_address(args) else {
+            return String::new();
+        };
+        if self.breakpoints.contains_key(&addr) {
+            return "OK".to_string();
+        }
+        let breakpoint = Breakpoint::new(BreakAddress::Virtual(addr), None);
+        let ids: Vec<Option<u32>> = self
+            .debug_ports
+            .iter_mut()
+            .map(
+                |port| match port.get_result(VdbApi::AddBreakpoint(breakpoint)) {
+                    VdbApi::BreakpointAdded(id) => Some(*id),
+                    _ => None,
+                },
+            )
+            .collect();
+        let added = ids.iter().all(Option::is_some);
+        self.breakpoints.insert(addr, ids);
+        if added {
+            "OK".to_string()
+        } else {
+            self.remove_breakpoint(args);
+            "E01".to_string()
+        }
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> String {
+        let Some(addr) = Self::breakpoint_address(args) else {
+            return String::new();
+        };
+        if let Some(ids) = self.breakpoints.remove(&addr) {
+            for (port, id) in self.debug_ports.iter_mut().zip(ids) {
+                if let Some(id) = id {
+                    port.get_result(VdbApi::DeleteBreakpoint(id));
+                }
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// `<offset>,<length>`
+    fn target_xml(&self, args: &str) -> String {
+        let xml = target_xml();
+        let Some((offset, length)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+        let offset = (offset as usize).min(xml.len());
+        let end = (offset + length as usize).min(xml.len());
+        let prefix = if end == xml.len() { 'l' } else { 'm' };
+        format!("{}{}", prefix, &xml[offset..end])
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{machine::snapshot::CoreState, vrisc::vcore::intcontroller::Pending};
+
+/// 相邻两个检查点之间至少执行的指令数
+pub const CHECKPOINT_INTERVAL: u64 = 10000;
+
+/// 最多保留的检查点数量，超过时丢弃最早的检查点
+pub const MAX_CHECKPOINTS: usize = 16;
+
+/// 检查点记录内存的单位，与最小页相同
+pub const PAGE_SIZE: u64 = 16 * 1024;
+
+/// ## 检查点
+///
+/// 核心执行完一条指令后的状态，以及此后核心第一次写入的每一页在写入前的内容。
+/// 不复制整个物理内存，只记录被这个核心改变的页
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub state: CoreState,
+    /// 页号到此检查点时页的内容
+    pub pages: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Checkpoint {
+    pub fn new(state: CoreState) -> Self {
+        Self {
+            state,
+            pages: BTreeMap::new(),
+        }
+    }
+}
+
+/// ## 执行历史
+///
+/// 调试时用于反向执行。每隔`CHECKPOINT_INTERVAL`条指令保存一个检查点，
+/// 同时记录不是由指令产生的中断（设备、核间中断和debugger产生的中断）。
+///
+/// 回到某条指令时，把之后被写入的页恢复为它之前的最后一个检查点时的内容，
+/// 在相同的指令数重新产生记录的中断并向前执行到这条指令。
+///
+/// 只恢复这个核心写入过的页，其它核心、设备和debugger在这期间对内存的写入以及io端口中的数据不会被重现。
+#[derive(Debug, Default)]
+pub struct History {
+    checkpoints: VecDeque<Checkpoint>,
+    /// (产生中断时的指令数, 中断)
+    interrupts: VecDeque<(u64, Pending)>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 丢弃所有历史，如debugger修改了寄存器之后历史无法再被重现
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.interrupts.clear();
+    }
+
+    /// 执行了`count`条指令时是否应该保存检查点
+    pub fn due(&self, count: u64) -> bool {
+        self.checkpoints
+            .back()
+            .is_none_or(|last| count >= last.state.inst_count + CHECKPOINT_INTERVAL)
+    }
+
+    pub fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.push_back(checkpoint);
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            self.checkpoints.pop_front();
+            let earliest = self.earliest().unwrap();
+            while self
+                .interrupts
+                .front()
+                .is_some_and(|(count, _)| *count < earliest)
+            {
+                self.interrupts.pop_front();
+            }
+        }
+    }
+
+    pub fn interrupt(&mut self, count: u64, pending: Pending) {
+        self.interrupts.push_back((count, pending));
+    }
+
+    /// 最早可以回到的指令数
+    pub fn earliest(&self) -> Option<u64> {
+        self.checkpoints.front().map(|c| c.state.inst_count)
+    }
+
+    /// 指令数不超过`count`的最后一个检查点
+    pub fn before(&self, count: u64) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find(|c| c.state.inst_count <= count)
+    }
+
+    /// ## 记录核心写入内存前的内容
+    ///
+    /// 执行了`count`条指令时执行的指令依次写入了`writes`（物理地址, 写入前的内容），
+    /// `page(n)`返回第n页现在的内容。被写入的页在指令数不超过`count`的最后一个检查点中没有记录时，
+    /// 撤销这些写入得到页在指令执行前的内容并记入这个检查点
+    pub fn written(
+        &mut self,
+        count: u64,
+        writes: &[(u64, Vec<u8>)],
+        page: impl Fn(u64) -> Vec<u8>,
+    ) {
+        let Some(checkpoint) = self
+            .checkpoints
+            .iter_mut()
+            .rev()
+            .find(|c| c.state.inst_count <= count)
+        else {
+            return;
+        };
+        for (addr, old) in writes.iter() {
+            let last = (addr + old.len() as u64).div_ceil(PAGE_SIZE);
+            for n in addr / PAGE_SIZE..last {
+                if checkpoint.pages.contains_key(&n) {
+                    continue;
+                }
+                let mut content = page(n);
+                for (addr, old) in writes.iter().rev() {
+                    undo(&mut content, n * PAGE_SIZE, *addr, old);
+                }
+                checkpoint.pages.insert(n, content);
+            }
+        }
+    }
+
+    /// ## 恢复内存
+    ///
+    /// 调用`write(页号, 内容)`把指令数不少于`count`的检查点记录的页恢复为第一个这样的检查点时的内容
+    pub fn restore_pages(&self, count: u64, mut write: impl FnMut(u64, &[u8])) {
+        for checkpoint in self
+            .checkpoints
+            .iter()
+            .rev()
+            .take_while(|c| c.state.inst_count >= count)
+        {
+            for (n, content) in checkpoint.pages.iter() {
+                write(*n, content);
+            }
+        }
+    }
+
+    /// 指令数在`from`到`to`（都包括）之间产生的中断
+    pub fn interrupts(&self, from: u64, to: u64) -> Vec<(u64, Pending)> {
+        self.interrupts
+            .iter()
+            .filter(|(count, _)| (from..=to).contains(count))
+            .copied()
+            .collect()
+    }
+
+    /// 回到执行了`count`条指令时，丢弃之后的历史
+    pub fn truncate(&mut self, count: u64) {
+        self.checkpoints.retain(|c| c.state.inst_count <= count);
+        self.interrupts.retain(|(c, _)| *c <= count);
+    }
+}
+
+/// 把从`start`开始的`content`中与写入`addr`的部分恢复为写入前的内容`old`
+fn undo(content: &mut [u8], start: u64, addr: u64, old: &[u8]) {
+    let end = start + content.len() as u64;
+    let from = addr.max(start);
+    let to = (addr + old.len() as u64).min(end);
+    if from < to {
+        content[(from - start) as usize..(to - start) as usize]
+            .copy_from_slice(&old[(from - addr) as usize..(to - addr) as usize]);
+    }
+}
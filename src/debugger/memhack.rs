@@ -5,12 +5,22 @@ use crossterm::{
     style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
 };
 
-use crate::utils::{
-    rdxparse::RadixParse,
-    {memory::Memory, shared::Addressable},
+use crate::{
+    utils::{
+        rdxparse::RadixParse,
+        {
+            memory::Memory,
+            shared::{Addressable, SharedPointer},
+        },
+    },
+    vrisc::disasm::{disassemble, MAX_LENGTH},
 };
 
-use super::terminal::Terminal;
+use super::{
+    debug::VdbApi,
+    terminal::Terminal,
+    watchpoint::{AccessKind, WatchAccess, Watchpoint, MAX_WATCHPOINTS},
+};
 
 fn arg_loss(stdout: &mut Stdout) {
     write!(*stdout, "缺少参数, 输入\"mem help\"获得帮助\n").unwrap();
@@ -20,7 +30,12 @@ fn arg_nan(stdout: &mut Stdout, arg: &str) {
     write!(*stdout, "参数\"{}\"不是数字\n", arg).unwrap();
 }
 
-pub fn run(cmd: &mut Vec<String>, stdout: &mut Stdout, memory: &mut Memory) {
+pub fn run(
+    cmd: &mut Vec<String>,
+    stdout: &mut Stdout,
+    debug_ports: &mut [SharedPointer<VdbApi>],
+    memory: &mut Memory,
+) {
     if cmd.len() == 0 {
         arg_loss(stdout);
         return;
@@ -34,6 +49,14 @@ pub fn run(cmd: &mut Vec<String>, stdout: &mut Stdout, memory: &mut Memory) {
             cmd.remove(0);
             write(cmd, stdout, memory);
         }
+        "watch" => {
+            cmd.remove(0);
+            watch(cmd, stdout, debug_ports);
+        }
+        "disasm" => {
+            cmd.remove(0);
+            disasm(cmd, stdout, memory);
+        }
         "help" => {
             execute!(
                 stdout,
@@ -57,6 +80,22 @@ pub fn run(cmd: &mut Vec<String>, stdout: &mut Stdout, memory: &mut Memory) {
             Terminal::newline(stdout);
             write!(stdout, "  write <start> <unit_size<=8> [data0 data1 ..] 将一组数据写入内存, 每个数据的大小都是unit_size\n").unwrap();
             Terminal::newline(stdout);
+            writeln!(stdout, "  watch <start> <length> <r|w|rw>               添加观察点, 任一核心读或写这段物理内存时切换到step模式并暂停").unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  watch del <id>                                删除观察点"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+            writeln!(stdout, "  watch list                                    列出观察点、命中次数以及每个核心最后一次触发的指令和新旧值").unwrap();
+            Terminal::newline(stdout);
+            writeln!(
+                stdout,
+                "  disasm <start> <count>                        反汇编从物理地址start开始的count条指令"
+            )
+            .unwrap();
+            Terminal::newline(stdout);
             write!(
                 stdout,
                 "  help                                          打印此帮助文档\n"
@@ -191,3 +230,166 @@ fn view(cmd: &mut Vec<String>, stdout: &mut Stdout, memory: &mut Memory) {
         Terminal::newline(stdout);
     }
 }
+
+fn disasm(cmd: &[String], stdout: &mut Stdout, memory: &mut Memory) {
+    if cmd.len() < 2 {
+        arg_loss(stdout);
+        return;
+    }
+    let Ok(start) = cmd[0].rdxparse() else {
+        arg_nan(stdout, &cmd[0]);
+        return;
+    };
+    let Ok(count) = cmd[1].rdxparse() else {
+        arg_nan(stdout, &cmd[1]);
+        return;
+    };
+    if start >= memory.borrow().size() as u64 {
+        writeln!(stdout, "地址{:#x}超出内存范围", start).unwrap();
+        return;
+    }
+    let data = memory
+        .borrow()
+        .slice(start, (count as u64).saturating_mul(MAX_LENGTH as u64));
+    for inst in disassemble(data, start).iter().take(count) {
+        execute!(stdout, SetForegroundColor(Color::DarkGreen)).unwrap();
+        write!(
+            stdout,
+            "{:08x} {:08x}",
+            inst.address >> 32,
+            inst.address & 0xffff_ffff
+        )
+        .unwrap();
+        execute!(stdout, ResetColor).unwrap();
+        write!(stdout, " |").unwrap();
+        execute!(stdout, SetForegroundColor(Color::DarkGrey)).unwrap();
+        for byte in inst.bytes.iter() {
+            write!(stdout, " {:02x}", byte).unwrap();
+        }
+        write!(stdout, "{:1$}", "", 3 * (MAX_LENGTH - inst.len())).unwrap();
+        execute!(stdout, ResetColor).unwrap();
+        writeln!(stdout, "  | {}", inst).unwrap();
+        Terminal::newline(stdout);
+    }
+}
+
+fn watch(cmd: &[String], stdout: &mut Stdout, debug_ports: &mut [SharedPointer<VdbApi>]) {
+    if cmd.is_empty() {
+        arg_loss(stdout);
+        return;
+    }
+    match cmd[0].as_str() {
+        "list" => watch_list(stdout, debug_ports),
+        "del" => {
+            let Some(id) = cmd.get(1) else {
+                arg_loss(stdout);
+                return;
+            };
+            let Ok(id) = id.rdxparse() else {
+                arg_nan(stdout, id);
+                return;
+            };
+            for (core, port) in debug_ports.iter_mut().enumerate() {
+                match port.get_result(VdbApi::DeleteWatchpoint(id)) {
+                    VdbApi::Ok => (),
+                    VdbApi::NoWatchpoint => {
+                        writeln!(stdout, "观察点\"{}\"不存在", cmd[1]).unwrap();
+                        return;
+                    }
+                    _ => {
+                        writeln!(stdout, "核心{}未响应", core).unwrap();
+                        Terminal::newline(stdout);
+                    }
+                }
+            }
+        }
+        _ => {
+            if cmd.len() < 3 {
+                arg_loss(stdout);
+                return;
+            }
+            let Ok(start) = cmd[0].rdxparse() else {
+                arg_nan(stdout, &cmd[0]);
+                return;
+            };
+            let Ok(length) = cmd[1].rdxparse() else {
+                arg_nan(stdout, &cmd[1]);
+                return;
+            };
+            let Some(access) = WatchAccess::parse(&cmd[2]) else {
+                writeln!(stdout, "参数\"{}\"应为r、w或rw", cmd[2]).unwrap();
+                return;
+            };
+            let watchpoint = Watchpoint::new(start, length, access);
+            let mut added = Vec::new();
+            for (core, port) in debug_ports.iter_mut().enumerate() {
+                match port.get_result(VdbApi::AddWatchpoint(watchpoint)) {
+                    VdbApi::WatchpointAdded(id) => added.push((core, *id)),
+                    VdbApi::TooManyWatchpoints => {
+                        writeln!(stdout, "最多设置{}个观察点", MAX_WATCHPOINTS).unwrap();
+                        return;
+                    }
+                    _ => {
+                        writeln!(stdout, "核心{}未响应", core).unwrap();
+                        Terminal::newline(stdout);
+                    }
+                }
+            }
+            // 各核心分配的观察点号应当相同，否则`watch del`无法删除同一个观察点
+            let Some(&(_, id)) = added.first() else {
+                return;
+            };
+            if added.iter().all(|&(_, other)| other == id) {
+                writeln!(stdout, "观察点{}", id).unwrap();
+            } else {
+                for (core, id) in added {
+                    writeln!(stdout, "核心{}的观察点号不一致: {}", core, id).unwrap();
+                    Terminal::newline(stdout);
+                }
+            }
+        }
+    }
+}
+
+fn watch_list(stdout: &mut Stdout, debug_ports: &mut [SharedPointer<VdbApi>]) {
+    for n in 0.. {
+        // 每个核心的观察点相同，命中次数和最后一次触发各自记录
+        let mut watchpoints = Vec::new();
+        for port in debug_ports.iter_mut() {
+            if let VdbApi::ListWatchpoint(_, Some(watchpoint)) =
+                port.get_result(VdbApi::ListWatchpoint(n, None))
+            {
+                watchpoints.push(*watchpoint);
+            }
+        }
+        let Some(first) = watchpoints.first() else {
+            break;
+        };
+        let hits: u64 = watchpoints.iter().map(|w| w.hits).sum();
+        writeln!(
+            stdout,
+            "{:3}: {:016x} +{:x} {}, 命中{}次",
+            first.id,
+            first.start,
+            first.length,
+            first.access.name(),
+            hits
+        )
+        .unwrap();
+        Terminal::newline(stdout);
+        for hit in watchpoints.iter().filter_map(|w| w.last) {
+            let kind = match hit.kind {
+                AccessKind::Read => "读",
+                AccessKind::Write => "写",
+                AccessKind::Dma => "dma写入",
+            };
+            writeln!(
+                stdout,
+                "     核心{} ip {:016x} 指令0x{:02x} {} {:016x}: {:x} -> {:x}",
+                hit.core, hit.ip, hit.opcode, kind, hit.address, hit.old, hit.new
+            )
+            .unwrap();
+            Terminal::newline(stdout);
+        }
+    }
+}
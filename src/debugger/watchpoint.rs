@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+/// 每个核心最多设置的观察点数量
+pub const MAX_WATCHPOINTS: usize = 16;
+
+/// ## 观察的访问方式
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchAccess {
+    /// 解析`r`、`w`、`rw`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "r" => Some(Self::Read),
+            "w" => Some(Self::Write),
+            "rw" => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "r",
+            Self::Write => "w",
+            Self::ReadWrite => "rw",
+        }
+    }
+
+    fn read(&self) -> bool {
+        *self != Self::Write
+    }
+
+    fn write(&self) -> bool {
+        *self != Self::Read
+    }
+}
+
+/// 触发观察点的访问
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AccessKind {
+    /// 指令读取内存
+    Read,
+    /// 指令写入内存
+    Write,
+    /// 执行`in`、`out`时发现内存已被改变，通常是设备通过dma写入，也可能是其它核心写入
+    Dma,
+}
+
+/// ## 观察点的一次触发
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct WatchHit {
+    pub core: usize,
+    /// 指令的虚拟地址
+    pub ip: u64,
+    pub opcode: u8,
+    pub kind: AccessKind,
+    /// 观察范围内第一个被访问的物理地址
+    pub address: u64,
+    /// 访问前从`address`开始的最多8字节（小端），读取时与`new`相同
+    pub old: u64,
+    /// 访问后从`address`开始的最多8字节（小端）
+    pub new: u64,
+}
+
+/// ## 内存观察点
+///
+/// 核心在寻址之后访问物理内存`[start, start + length)`时，debugger后端把核心切换到step模式，
+/// 并记录命中次数和最后一次触发
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Watchpoint {
+    /// 由debugger后端分配
+    pub id: u32,
+    pub start: u64,
+    pub length: u64,
+    pub access: WatchAccess,
+    pub hits: u64,
+    pub last: Option<WatchHit>,
+}
+
+impl Watchpoint {
+    pub fn new(start: u64, length: u64, access: WatchAccess) -> Self {
+        Self {
+            id: 0,
+            start,
+            length,
+            access,
+            hits: 0,
+            last: None,
+        }
+    }
+
+    /// 与`[addr, addr + len)`重叠的部分，(起始地址, 长度)
+    fn overlap(&self, addr: u64, len: u64) -> Option<(u64, u64)> {
+        let start = self.start.max(addr);
+        // 区间可能到达地址空间的末尾
+        let end = self
+            .start
+            .saturating_add(self.length)
+            .min(addr.saturating_add(len));
+        (start < end).then(|| (start, end - start))
+    }
+}
+
+/// 正在执行的指令，(核心, 虚拟地址, 操作码)
+pub type Site = (usize, u64, u8);
+
+/// ## 核心的观察点
+///
+/// 由`Vcore`持有，访问内存的指令在寻址后调用`Vcore::watch_read`等检查，
+/// 触发的观察点在指令执行完后由`take_triggered`取出
+#[derive(Debug)]
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    next_id: u32,
+    /// 本条指令触发的观察点
+    triggered: Vec<(u32, WatchHit)>,
+    /// 每个写观察点上次看到的内存内容，用于发现dma写入
+    seen: HashMap<u32, Vec<u8>>,
+}
+
+impl Default for Watchpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self {
+            list: Vec::new(),
+            next_id: 1,
+            triggered: Vec::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// 添加观察点并返回分配的id，数量已达到`MAX_WATCHPOINTS`时返回None
+    pub fn add(&mut self, mut watchpoint: Watchpoint) -> Option<u32> {
+        if self.list.len() >= MAX_WATCHPOINTS {
+            return None;
+        }
+        watchpoint.id = self.next_id;
+        watchpoint.hits = 0;
+        watchpoint.last = None;
+        self.next_id += 1;
+        self.list.push(watchpoint);
+        Some(watchpoint.id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        self.seen.remove(&id);
+        let len = self.list.len();
+        self.list.retain(|w| w.id != id);
+        self.list.len() != len
+    }
+
+    /// 第n个观察点
+    pub fn get(&self, n: usize) -> Option<Watchpoint> {
+        self.list.get(n).copied()
+    }
+
+    /// 读取了物理地址`addr`开始的`data`
+    pub fn read(&mut self, addr: u64, data: &[u8], site: Site) {
+        for w in self.list.iter().filter(|w| w.access.read()) {
+            if let Some((start, _)) = w.overlap(addr, data.len() as u64) {
+                let value = word(&data[(start - addr) as usize..]);
+                self.triggered
+                    .push((w.id, hit(site, AccessKind::Read, start, value, value)));
+            }
+        }
+    }
+
+    /// 将要把`new`写入物理地址`addr`，`old`是写入前的内容
+    pub fn write(&mut self, addr: u64, old: &[u8], new: &[u8], site: Site) {
+        for w in self.list.iter() {
+            let Some((start, len)) = w.overlap(addr, new.len() as u64) else {
+                continue;
+            };
+            let from = (start - addr) as usize;
+            if let Some(seen) = self.seen.get_mut(&w.id) {
+                let at = (start - w.start) as usize;
+                seen[at..at + len as usize].copy_from_slice(&new[from..from + len as usize]);
+            }
+            if w.access.write() {
+                let (old, new) = (word(&old[from..]), word(&new[from..]));
+                self.triggered
+                    .push((w.id, hit(site, AccessKind::Write, start, old, new)));
+            }
+        }
+    }
+
+    /// ## 检查写观察点的内容是否被核心以外写入
+    ///
+    /// `memory(start, length)`返回物理内存中的内容，第一次检查时只记录内容
+    pub fn dma(&mut self, memory: impl Fn(u64, u64) -> Vec<u8>, site: Site) {
+        for w in self.list.iter().filter(|w| w.access.write()) {
+            let now = memory(w.start, w.length);
+            let Some(seen) = self.seen.insert(w.id, now.clone()) else {
+                continue;
+            };
+            if let Some(i) = (0..now.len()).find(|&i| seen.get(i) != now.get(i)) {
+                let (old, new) = (word(&seen[i..]), word(&now[i..]));
+                let address = w.start + i as u64;
+                self.triggered
+                    .push((w.id, hit(site, AccessKind::Dma, address, old, new)));
+            }
+        }
+    }
+
+    /// 记录本条指令触发的观察点，返回是否有观察点被触发
+    pub fn take_triggered(&mut self) -> bool {
+        let triggered = !self.triggered.is_empty();
+        for (id, hit) in self.triggered.drain(..) {
+            if let Some(w) = self.list.iter_mut().find(|w| w.id == id) {
+                w.hits += 1;
+                w.last = Some(hit);
+            }
+        }
+        triggered
+    }
+
+    /// 丢弃本条指令触发的观察点，如反向执行时重新执行的指令
+    pub fn clear_triggered(&mut self) {
+        self.triggered.clear();
+    }
+}
+
+fn hit(site: Site, kind: AccessKind, address: u64, old: u64, new: u64) -> WatchHit {
+    let (core, ip, opcode) = site;
+    WatchHit {
+        core,
+        ip,
+        opcode,
+        kind,
+        address,
+        old,
+        new,
+    }
+}
+
+/// 最多8字节，小端
+fn word(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .enumerate()
+        .fold(0, |value, (i, &byte)| value | (byte as u64) << (i * 8))
+}
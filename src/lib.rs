@@ -1,6 +1,7 @@
 pub mod api;
 pub mod config;
 pub mod debugger;
+pub mod machine;
 pub mod utils;
 pub mod vcore;
 pub mod vrisc;
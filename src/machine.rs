@@ -0,0 +1,1270 @@
+pub mod event;
+pub mod snapshot;
+pub mod trace;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io, panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "debugger")]
+use crate::{
+    debugger::{
+        debug::{DebuggerBackend, VdbApi},
+        history::{Checkpoint, PAGE_SIZE},
+    },
+    vrisc::vcore::DebugMode,
+};
+use crate::{
+    utils::{
+        memory::Memory,
+        shared::{self, shared_name, Addressable, SharedPointer},
+    },
+    vrisc::vcore::{
+        dma::{DMAStatus, DirectMemoryAccess},
+        intcontroller::{InterruptId, Message},
+        iocontroller::{IOController, IOPortBuffer, PortRequest},
+        timer::{Timer, TIMER_PORT},
+        Vcore,
+    },
+};
+
+use self::{
+    event::{Event, Recorder, TimedEvent},
+    snapshot::{CoreState, PortQueues, Snapshot},
+    trace::{register_values, TraceRecord, Tracer},
+};
+
+/// 连续多少轮没有任何核心执行指令时，认为虚拟机已经空闲
+const IDLE_ROUNDS: usize = 16;
+
+/// 虚拟时间下每微秒的周期数
+pub const VIRTUAL_CYCLES_PER_USEC: u64 = 100;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+/// ## 核心单步执行的结果
+pub enum StepResult {
+    /// 核心尚未被启动
+    Waiting,
+
+    /// 执行了一条指令
+    Executed,
+
+    /// 没有执行指令（产生了中断或被debugger暂停）
+    Stalled,
+
+    /// 核心处于nop状态
+    Idle,
+
+    /// 核心退出
+    Exit,
+}
+
+/// ## 核心运行器
+///
+/// 包装一个vcore核心以及它运行时需要的启动标志、指令计数、io请求管道和定时器。
+///
+/// 每调用一次`step`就执行一轮核心主循环，因此既可以在独立的进程或线程中循环运行，
+/// 也可以与其它核心在同一个线程中轮流运行。
+pub struct CoreRunner {
+    pub core: Vcore,
+    id: usize,
+    startflg: SharedPointer<(bool, u64)>,
+    /// 指令计数，计算从开始运行到现在此核心共运行了多少条指令
+    inst_count: SharedPointer<u64>,
+    ioreq_receiver: Receiver<PortRequest>,
+    /// 可编程定时器，默认每4ms产生一次时钟中断，使用外部时钟时默认停止
+    timer: Timer,
+    /// 核心开始运行的时间，定时器的微秒计数器从此开始
+    started_at: Instant,
+    /// ## 虚拟时间
+    ///
+    /// 为true时定时器的微秒计数器不取自真实时间，而是由`cycles`换算得到。
+    /// 不用指令计数换算，因为nop状态中不执行指令，时间会停止，等待时钟中断的核心永远不会被唤醒
+    virtual_time: bool,
+    /// 核心开始运行后经过的周期数，每执行一轮主循环（执行一条指令或在nop状态中等待等）增加1
+    cycles: u64,
+    /// 重放模式下定时器不产生时钟中断，时钟中断只来自重放的事件
+    replaying: bool,
+    /// 是否记录核心观察到的非确定性事件
+    recording: bool,
+    /// 已记录、尚未被`take_recorded`取出的事件
+    recorded: Vec<TimedEvent>,
+    /// 每个设备端口中已经记录过的设备写入位置
+    input_positions: HashMap<u16, usize>,
+    /// 重放的、尚未被读取的微秒计数器的值
+    times: VecDeque<u64>,
+    /// 已执行、尚未被`take_traced`取出的指令，只在跟踪时记录
+    traced: Vec<TraceRecord>,
+    debug: bool,
+    started: bool,
+    #[cfg(feature = "debugger")]
+    debugger_backend: Option<DebuggerBackend>,
+}
+
+impl CoreRunner {
+    /// 绑定与主进程通信的共享内存并初始化核心，共享内存连接失败时返回错误信息
+    pub fn new(
+        instance: &str,
+        memory: Memory,
+        id: usize,
+        total_core: usize,
+        debug: bool,
+        external_clock: bool,
+        ioreq_receiver: Receiver<PortRequest>,
+    ) -> Result<Self, String> {
+        let startflg = SharedPointer::<(bool, u64)>::open(
+            shared_name(instance, &format!("VcoreCore{}StartFlg", id)),
+            1,
+        )?;
+        let mut inst_count = SharedPointer::<u64>::open(
+            shared_name(instance, &format!("VcoreCore{}InstCount", id)),
+            1,
+        )?;
+        inst_count.write(0, 0);
+        let mut core = Vcore::new(instance, id, total_core, memory);
+        core.init()?;
+        #[cfg(feature = "debugger")]
+        if debug {
+            core.writes = Some(Vec::new());
+        }
+        Ok(Self {
+            core,
+            id,
+            startflg,
+            inst_count,
+            ioreq_receiver,
+            timer: if external_clock {
+                Timer::new()
+            } else {
+                Timer::periodic(4000)
+            },
+            started_at: Instant::now(),
+            virtual_time: false,
+            cycles: 0,
+            replaying: false,
+            recording: false,
+            recorded: Vec::new(),
+            input_positions: HashMap::new(),
+            times: VecDeque::new(),
+            traced: Vec::new(),
+            debug,
+            started: false,
+            #[cfg(feature = "debugger")]
+            debugger_backend: if debug {
+                Some(DebuggerBackend::new(instance, id))
+            } else {
+                None
+            },
+        })
+    }
+
+    /// 创建debugger后端，核心开始处理vcore debugger的请求并记录执行历史
+    #[cfg(feature = "debugger")]
+    pub fn set_debug(&mut self) {
+        self.debug = true;
+        self.core.writes = Some(Vec::new());
+        self.debugger_backend = Some(DebuggerBackend::new(self.core.instance(), self.id));
+    }
+
+    #[inline]
+    pub fn instruction_count(&self) -> u64 {
+        *self.inst_count
+    }
+
+    #[inline]
+    pub fn started(&self) -> bool {
+        self.started
+    }
+
+    /// 设置是否使用虚拟时间，见`virtual_time`
+    pub fn set_virtual_time(&mut self, virtual_time: bool) {
+        self.virtual_time = virtual_time;
+    }
+
+    /// 设置是否处于重放模式，见`replaying`
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    /// 设置是否记录非确定性事件
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// 取出已记录的事件
+    pub fn take_recorded(&mut self) -> Vec<TimedEvent> {
+        std::mem::take(&mut self.recorded)
+    }
+
+    /// 设置是否跟踪执行的每条指令
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.core.accesses = if tracing { Some(Vec::new()) } else { None };
+    }
+
+    /// 取出已跟踪的指令
+    pub fn take_traced(&mut self) -> Vec<TraceRecord> {
+        std::mem::take(&mut self.traced)
+    }
+
+    fn record(&mut self, event: Event) {
+        if self.recording {
+            self.recorded
+                .push(TimedEvent::new(self.id, *self.inst_count, event));
+        }
+    }
+
+    /// 定时器的微秒计数器
+    fn usec(&self) -> u64 {
+        if self.virtual_time {
+            self.cycles / VIRTUAL_CYCLES_PER_USEC
+        } else if self.started {
+            self.started_at.elapsed().as_micros() as u64
+        } else {
+            0
+        }
+    }
+
+    /// 保存核心的状态
+    fn save(&self) -> CoreState {
+        let core = &self.core;
+        let mut linked: Vec<u16> = core
+            .io_ports
+            .keys()
+            .copied()
+            .filter(|&port| port >= 256)
+            .collect();
+        linked.sort();
+        CoreState {
+            started: self.started,
+            startflg: *self.startflg,
+            inst_count: *self.inst_count,
+            cycles: self.cycles,
+            usec: self.usec(),
+            timer: self.timer,
+            regs: core.regs,
+            fregs: core.fregs,
+            interrupt_mask: core.intctler.mask(),
+            pending: core.intctler.pending().copied().collect(),
+            hot_ip: core.lazyaddr.hot_ip,
+            had_run_inst: core.lazyaddr.had_run_inst,
+            crossed_page: core.lazyaddr.crossed_page,
+            ip_increment: core.ip_increment,
+            extensions: core.extensions,
+            transferred: core.transferred,
+            nopflag: core.nopflag,
+            linked,
+        }
+    }
+
+    /// 恢复`save`保存的状态，连接设备端口失败时返回错误信息
+    fn restore(&mut self, state: &CoreState) -> Result<(), String> {
+        self.started = state.started;
+        self.startflg.write(0, state.startflg);
+        self.inst_count.write(0, state.inst_count);
+        self.cycles = state.cycles;
+        self.started_at = Instant::now()
+            .checked_sub(Duration::from_micros(state.usec))
+            .unwrap_or_else(Instant::now);
+        self.timer = state.timer;
+        let core = &mut self.core;
+        core.regs = state.regs;
+        core.fregs = state.fregs;
+        core.intctler.reset();
+        core.intctler.set_mask(state.interrupt_mask);
+        for pending in state.pending.iter() {
+            core.intctler.interrupt_with(pending.id, pending.msg);
+        }
+        core.lazyaddr.hot_ip = state.hot_ip;
+        core.lazyaddr.had_run_inst = state.had_run_inst;
+        core.lazyaddr.crossed_page = state.crossed_page;
+        core.ip_increment = state.ip_increment;
+        for id in 0..64 {
+            if core.extensions & (1 << id) != 0 {
+                core.unload_extension(id);
+            }
+        }
+        for id in 0..64 {
+            if state.extensions & (1 << id) != 0 {
+                core.load_extension(id);
+            }
+        }
+        core.transferred = state.transferred;
+        core.nopflag = state.nopflag;
+        for &port in state.linked.iter() {
+            if !core.io_ports.contains_key(&port) {
+                core.add_port(port, false, self.id)?;
+            }
+        }
+        core.memory.clear_address_buffer();
+        Ok(())
+    }
+
+    /// 设备写入端口`port`、尚未被记录的数据
+    fn unrecorded_input(&mut self, port: u16) -> Vec<u8> {
+        let Some(buffer) = self.core.io_ports.get(&port) else {
+            return Vec::new();
+        };
+        let from = self.input_positions.get(&port).copied().unwrap_or(0);
+        let (data, to) = buffer.device_written(from);
+        self.input_positions.insert(port, to);
+        data.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    /// ## 执行一轮核心主循环
+    ///
+    /// 核心未启动时只检查启动标志（以及debugger的请求），
+    /// 启动后依次处理io请求、时钟、中断、寻址、debugger，最后取指令并执行。
+    ///
+    /// 由于debugger的存在，执行一条指令的过程并没有在这里完全体现出来。
+    ///
+    /// 设备请求连接、但核心无法连接设备的端口时返回错误。
+    pub fn step(&mut self) -> io::Result<StepResult> {
+        if !self.started {
+            if !self.startflg.0 {
+                #[cfg(feature = "debugger")]
+                if let Some(backend) = self.debugger_backend.as_mut() {
+                    if backend
+                        .before_start(
+                            &mut self.startflg,
+                            &mut self.core.debug_mode,
+                            &mut self.core.watchpoints,
+                        )
+                        .is_none()
+                    {
+                        return Ok(StepResult::Exit);
+                    }
+                }
+                return Ok(StepResult::Waiting);
+            }
+            self.core.regs.ip = self.startflg.1;
+            self.started = true;
+            self.started_at = Instant::now();
+        }
+        self.cycles += 1;
+        #[cfg(feature = "debugger")]
+        let external = self.core.intctler.pending().count();
+
+        if let Ok(port) = self.ioreq_receiver.try_recv() {
+            match port {
+                PortRequest::Link(port) => {
+                    self.record(Event::Link(port));
+                    self.core.link_device(port).map_err(io::Error::other)?;
+                }
+                PortRequest::Interrupt(port) => {
+                    if self.recording {
+                        let data = self.unrecorded_input(port);
+                        self.record(Event::Input(port, data));
+                    }
+                    self.core.intctler.interrupt_with(
+                        InterruptId::DeviceCommunication,
+                        Message::Port(port).encode(),
+                    );
+                }
+                PortRequest::Ipi(source) => {
+                    self.core.intctler.interrupt_with(
+                        InterruptId::InterProcessor,
+                        Message::Core(source).encode(),
+                    );
+                }
+            }
+        }
+
+        // 定时器
+        let usec = self.usec();
+        let insts = *self.inst_count;
+        if let Some(port) = self.core.io_ports.get_mut(&TIMER_PORT) {
+            while let Some(data) = port.device_get() {
+                // 读取的微秒计数器与真实时间有关，需要记录下来才能重放
+                let usec = if !Timer::reads_usec(data) {
+                    usec
+                } else if self.replaying {
+                    self.times.pop_front().unwrap_or(usec)
+                } else {
+                    if self.recording {
+                        self.recorded
+                            .push(TimedEvent::new(self.id, insts, Event::Time(usec)));
+                    }
+                    usec
+                };
+                if let Some(counter) = self.timer.command(data, usec, insts) {
+                    port.device_push(counter);
+                }
+            }
+        }
+        if !self.debug && !self.replaying && self.timer.hit(usec, insts) {
+            self.record(Event::Clock);
+            self.core.intctler.interrupt(InterruptId::Clock);
+        }
+        #[cfg(feature = "debugger")]
+        self.log_interrupts(external);
+        let core = &mut self.core;
+        // 检测中断
+        if let Some(intid) = core.intctler.interrupted() {
+            core.interrupt_jump(intid);
+        }
+        // 指令寻址，更新hot_ip
+        if core.flush_lazy_address_system(self.debug) {
+            return Ok(StepResult::Stalled);
+        }
+        // debugger后端
+        #[cfg(feature = "debugger")]
+        if self.debugger_backend.is_some() {
+            if self.reverse() {
+                return Ok(StepResult::Stalled);
+            }
+            let core = &mut self.core;
+            let backend = self.debugger_backend.as_mut().unwrap();
+            let external = core.intctler.pending().count();
+            let (regs, fregs) = (core.regs, core.fregs);
+            let result = backend.after_start(core);
+            // 被debugger修改过寄存器之后，执行历史无法再被重现
+            if core.regs != regs || core.fregs != fregs {
+                backend.history.clear();
+            }
+            self.log_interrupts(external);
+            match result {
+                Some(true) => {
+                    return Ok(StepResult::Stalled);
+                }
+                None => {
+                    return Ok(StepResult::Exit);
+                }
+                _ => (),
+            };
+        }
+        // nop指令状态下，在此停止
+        if self.core.nopflag {
+            return Ok(StepResult::Idle);
+        }
+        let result = self.execute();
+        #[cfg(feature = "debugger")]
+        {
+            if result == StepResult::Executed {
+                self.log_writes(*self.inst_count - 1);
+                self.checkpoint();
+            }
+            // 观察点被触发时暂停核心
+            if self.core.watchpoints.take_triggered() {
+                self.core.debug_mode = DebugMode::Step;
+            }
+        }
+        Ok(result)
+    }
+
+    /// 取指令并执行
+    fn execute(&mut self) -> StepResult {
+        let core = &mut self.core;
+        /* 取指令 */
+        let opcode = *core.memory.borrow().at(core.lazyaddr.hot_ip);
+        // 未加载的扩展指令与不存在的指令一样产生InvalidInstruction
+        if core.instruction_space[opcode as usize].is_none() {
+            core.instruction_fault(InterruptId::InvalidInstruction);
+            return StepResult::Stalled;
+        }
+        let instlen = core.instruction_space[opcode as usize].unwrap().1;
+        let (inst, cont) = core.read_instruction(instlen);
+        if cont {
+            return StepResult::Stalled;
+        }
+        /* 执行指令 */
+        let before = core.accesses.as_mut().map(|accesses| {
+            accesses.clear();
+            register_values(&core.regs, &core.fregs)
+        });
+        let (ip, hot_ip) = (core.regs.ip, core.lazyaddr.hot_ip);
+        core.execute_instruction(opcode, inst.as_slice());
+        if let Some(before) = before {
+            let after = register_values(&core.regs, &core.fregs);
+            self.traced.push(TraceRecord {
+                core: self.id,
+                count: *self.inst_count,
+                ip,
+                hot_ip,
+                bytes: inst,
+                changes: TraceRecord::changes(&before, &after),
+                accesses: core.accesses.as_mut().map(std::mem::take).unwrap(),
+            });
+        }
+        // 更新指令计数
+        *self.inst_count += 1;
+        StepResult::Executed
+    }
+
+    /// 把在执行了相同指令数时新产生的待处理中断（从第`from`个起）记入执行历史
+    #[cfg(feature = "debugger")]
+    fn log_interrupts(&mut self, from: usize) {
+        if let Some(backend) = self.debugger_backend.as_mut() {
+            let count = *self.inst_count;
+            for pending in self.core.intctler.pending().skip(from) {
+                backend.history.interrupt(count, *pending);
+            }
+        }
+    }
+
+    /// 需要时保存检查点
+    #[cfg(feature = "debugger")]
+    fn checkpoint(&mut self) {
+        let count = *self.inst_count;
+        let Some(backend) = self.debugger_backend.as_ref() else {
+            return;
+        };
+        if !backend.history.due(count) {
+            return;
+        }
+        let checkpoint = Checkpoint::new(self.save());
+        let backend = self.debugger_backend.as_mut().unwrap();
+        backend.history.checkpoint(checkpoint);
+    }
+
+    /// 把执行了`count`条指令时核心写入内存前的内容记入执行历史
+    #[cfg(feature = "debugger")]
+    fn log_writes(&mut self, count: u64) {
+        let Some(writes) = self.core.writes.as_mut().map(std::mem::take) else {
+            return;
+        };
+        if writes.is_empty() {
+            return;
+        }
+        let memory = self.core.memory.borrow();
+        let size = memory.size() as u64;
+        let backend = self.debugger_backend.as_mut().unwrap();
+        backend.history.written(count, &writes, |n| {
+            let start = n * PAGE_SIZE;
+            memory.slice(start, PAGE_SIZE.min(size - start)).to_vec()
+        });
+    }
+
+    /// ## 处理debugger的反向执行请求
+    ///
+    /// `core reverse-step`回到上一条指令执行之前；`core reverse-continue`回到上一次命中断点的位置，
+    /// 执行历史中没有命中断点时回到最早的检查点。
+    ///
+    /// 返回true表示处理了请求，本轮不再执行指令
+    #[cfg(feature = "debugger")]
+    fn reverse(&mut self) -> bool {
+        let backend = self.debugger_backend.as_mut().unwrap();
+        let request = **backend.core_debug_port;
+        if !matches!(request, VdbApi::ReverseStep | VdbApi::ReverseContinue) {
+            return false;
+        }
+        if self.core.debug_mode != DebugMode::Step {
+            backend.core_debug_port.write(0, VdbApi::None);
+            return true;
+        }
+        let count = *self.inst_count;
+        let earliest = backend.history.earliest().filter(|&c| c < count);
+        let target = if request == VdbApi::ReverseStep {
+            count.checked_sub(1)
+        } else {
+            earliest.and_then(|earliest| {
+                let hits = self.rewind(earliest, count)?;
+                Some(hits.last().copied().unwrap_or(earliest))
+            })
+        };
+        let reply = if target.is_some_and(|target| self.rewind(target, target).is_some()) {
+            let history = &mut self.debugger_backend.as_mut().unwrap().history;
+            history.truncate(*self.inst_count);
+            VdbApi::Ok
+        } else {
+            VdbApi::NoHistory
+        };
+        let backend = self.debugger_backend.as_mut().unwrap();
+        backend.core_debug_port.write(0, reply);
+        true
+    }
+
+    /// ## 回到执行了`target`条指令时的状态
+    ///
+    /// 从指令数不超过`from`的最后一个检查点恢复，再向前重新执行到`target`，
+    /// 返回途中断点生效时的指令数，没有可用的检查点时返回None
+    #[cfg(feature = "debugger")]
+    fn rewind(&mut self, from: u64, target: u64) -> Option<Vec<u64>> {
+        // 本轮进入中断时的写入还没有记录
+        self.log_writes(*self.inst_count);
+        let history = &self.debugger_backend.as_ref().unwrap().history;
+        let state = history.before(from)?.state.clone();
+        let interrupts = history.interrupts(state.inst_count, target);
+        history.restore_pages(state.inst_count, |n, content| {
+            self.core
+                .memory
+                .borrow_mut()
+                .write_slice(n * PAGE_SIZE, content)
+        });
+        let debug_mode = self.core.debug_mode;
+        self.restore(&state).ok()?;
+        self.core.debug_mode = debug_mode;
+        let mut interrupts = interrupts.into_iter().peekable();
+        let mut raised = None;
+        let mut hits = Vec::new();
+        loop {
+            let count = *self.inst_count;
+            if raised != Some(count) {
+                raised = Some(count);
+                while let Some((_, pending)) = interrupts.next_if(|(c, _)| *c == count) {
+                    self.core.intctler.interrupt_with(pending.id, pending.msg);
+                }
+            }
+            if count >= target || self.replay_round(&mut hits) == StepResult::Idle {
+                break;
+            }
+        }
+        Some(hits)
+    }
+
+    /// 重新执行时的一轮主循环，不处理io请求、定时器和debugger，只记录断点生效时的指令数
+    #[cfg(feature = "debugger")]
+    fn replay_round(&mut self, hits: &mut Vec<u64>) -> StepResult {
+        self.cycles += 1;
+        let core = &mut self.core;
+        if let Some(intid) = core.intctler.interrupted() {
+            core.interrupt_jump(intid);
+        }
+        if core.flush_lazy_address_system(self.debug) {
+            return StepResult::Stalled;
+        }
+        if core.nopflag {
+            return StepResult::Idle;
+        }
+        let backend = self.debugger_backend.as_ref().unwrap();
+        let count = *self.inst_count;
+        if hits.last() != Some(&count)
+            && backend
+                .breakpoint(core.regs.ip, core.lazyaddr.hot_ip, &core.regs, &core.fregs)
+                .is_some()
+        {
+            hits.push(count);
+        }
+        let result = self.execute();
+        if result == StepResult::Executed {
+            self.log_writes(*self.inst_count - 1);
+        }
+        self.core.watchpoints.clear_triggered();
+        result
+    }
+
+    /// ## 循环运行核心
+    ///
+    /// 直到核心退出、`stop`被置位或`step`返回错误
+    pub fn run_until(&mut self, stop: &AtomicBool) -> io::Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            match self.step()? {
+                StepResult::Exit => break,
+                // 等待核心被允许开始
+                StepResult::Waiting => thread::sleep(Duration::from_millis(1)),
+                StepResult::Idle => thread::sleep(Duration::from_micros(1)),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ## 可嵌入的虚拟机
+///
+/// 在当前进程中创建内存、io控制器、dma和所有核心，不fork子进程，
+/// 可以在rust测试或宿主程序中创建、单步执行、运行指定数量的指令并销毁。
+///
+/// 所有共享内存名都以实例标识`instance`为前缀，使用不同实例标识的虚拟机可以同时运行，
+/// 外部设备需要使用相同的实例标识连接。
+///
+/// 与`vcore::run`相同，创建后core#0从地址0开始运行，其它核心由core#0通过固定端口1启动，
+/// 也可以使用`start_core`直接启动。
+///
+/// ## 确定性模式
+///
+/// `set_deterministic`之后，定时器使用由周期数换算的虚拟时间（每`VIRTUAL_CYCLES_PER_USEC`个周期为一微秒），
+/// 不再接受外部设备的连接和中断，
+/// 设备输入只来自`schedule`预先安排的定时事件。`step`和`run`以固定的顺序轮流运行各个核心，
+/// 所以相同的内存映像和事件总是得到完全相同的状态。
+///
+/// 周期数在核心的每一轮主循环中增加，包括在nop状态中等待的轮次，而不是取自指令计数：
+/// 否则执行nop等待时钟中断的核心会使时间停止，永远不会被唤醒。
+///
+/// ## 记录与重放
+///
+/// `set_recorder`之后，核心观察到的所有非确定性事件（时钟中断、设备连接、设备输入、
+/// 设备通过dma写入的数据以及通过固定端口1启动核心）都连同当时的指令数写入记录文件。
+/// `replay`在确定性模式下把记录的事件在相同的指令数重新注入，定时器不再自行产生时钟中断。
+///
+/// 设备输入和dma写入的数据在设备的中断到达核心时记录，
+/// 读取微秒计数器时得到的值也被记录，多个核心之间访问内存的先后顺序不被记录。
+///
+/// ## 跟踪
+///
+/// `set_tracer`之后，每个核心执行的每条指令都按执行的顺序写入跟踪文件，见`TraceRecord`。
+///
+/// ```no_run
+/// use vcore::machine::Machine;
+///
+/// let mut machine = Machine::new("example", 1024 * 1024, 1, true)?;
+/// machine.load(0, &std::fs::read("vrom.img").unwrap());
+/// machine.run(1000)?;
+/// println!("{:x}", machine.core(0).regs.x[0]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Machine {
+    memory: Memory,
+    cores: Vec<CoreRunner>,
+    io_controller: IOController,
+    solid_io_ports: Vec<Vec<SharedPointer<IOPortBuffer>>>,
+    cores_startflg: Vec<SharedPointer<(bool, u64)>>,
+    cores_inst_count: Vec<SharedPointer<u64>>,
+    dma_controller: RwLock<DirectMemoryAccess>,
+    dma_current: u64,
+    dma_opstatus: DMAStatus,
+    deterministic: bool,
+    /// 每个核心待注入的定时事件，按指令数排序
+    events: Vec<VecDeque<TimedEvent>>,
+    recorder: Option<Recorder>,
+    tracer: Option<Tracer>,
+    /// 每个可写dma区域上一次记录时的内容
+    dma_snapshots: HashMap<u64, Vec<u8>>,
+    #[cfg(feature = "debugger")]
+    debug_ports: Vec<SharedPointer<VdbApi>>,
+}
+
+impl Machine {
+    /// ## 创建虚拟机
+    ///
+    /// 实例标识无效（见`shared::check_instance`）、没有核心或共享内存创建失败时返回错误信息，
+    /// 已经创建的共享内存随之删除
+    pub fn new(
+        instance: &str,
+        memory_size: usize,
+        total_core: usize,
+        external_clock: bool,
+    ) -> Result<Self, String> {
+        shared::check_instance(instance)?;
+        if total_core == 0 {
+            return Err("虚拟机至少需要一个核心".to_string());
+        }
+        // 初始化io相关数据结构
+        let mut ioreq_delivers = Vec::new();
+        let mut ioreq_receivers = Vec::new();
+        for _ in 0..total_core {
+            let (tx, rx) = mpsc::channel();
+            ioreq_delivers.push(tx);
+            ioreq_receivers.push(rx);
+        }
+        let io_controller = IOController::new(instance, ioreq_delivers)?;
+        let mut solid_io_ports = Vec::new();
+        for c in 0..total_core {
+            let mut cp = Vec::new();
+            for i in 0..256 {
+                cp.push(SharedPointer::<IOPortBuffer>::create(
+                    shared_name(instance, &format!("VcoreIOPort{}C{}", i, c)),
+                    1,
+                )?);
+            }
+            solid_io_ports.push(cp);
+        }
+
+        let memory = Memory::new(instance, memory_size)?;
+
+        let mut cores_startflg = Vec::new();
+        let mut cores_inst_count = Vec::new();
+        #[cfg(feature = "debugger")]
+        let mut debug_ports = Vec::new();
+        let mut cores = Vec::new();
+        for (i, receiver) in ioreq_receivers.into_iter().enumerate() {
+            #[cfg(feature = "debugger")]
+            {
+                let mut port = SharedPointer::<VdbApi>::create(
+                    shared_name(instance, &format!("VcoreCore{}DebugApi", i)),
+                    1,
+                )?;
+                port.write(0, VdbApi::None);
+                debug_ports.push(port);
+            }
+            let mut startflg = SharedPointer::<(bool, u64)>::create(
+                shared_name(instance, &format!("VcoreCore{}StartFlg", i)),
+                1,
+            )?;
+            startflg.write(0, (i == 0, 0));
+            cores_startflg.push(startflg);
+            cores_inst_count.push(SharedPointer::<u64>::create(
+                shared_name(instance, &format!("VcoreCore{}InstCount", i)),
+                1,
+            )?);
+            cores.push(CoreRunner::new(
+                instance,
+                Memory::bind(instance, memory_size)?,
+                i,
+                total_core,
+                false,
+                external_clock,
+                receiver,
+            )?);
+        }
+
+        Ok(Self {
+            memory,
+            cores,
+            io_controller,
+            solid_io_ports,
+            cores_startflg,
+            cores_inst_count,
+            dma_controller: RwLock::new(DirectMemoryAccess::new(instance)),
+            dma_current: 0,
+            dma_opstatus: DMAStatus::None,
+            deterministic: false,
+            events: (0..total_core).map(|_| VecDeque::new()).collect(),
+            recorder: None,
+            tracer: None,
+            dma_snapshots: HashMap::new(),
+            #[cfg(feature = "debugger")]
+            debug_ports,
+        })
+    }
+
+    /// ## 开启调试
+    ///
+    /// 与`vcore --debug`相同，核心处理vcore debugger的请求并记录执行历史，定时器不再产生时钟中断。
+    /// 核心的启动状态不变，之后用`debug_request`向核心发送请求
+    #[cfg(feature = "debugger")]
+    pub fn set_debug(&mut self) {
+        for runner in self.cores.iter_mut() {
+            runner.set_debug();
+        }
+    }
+
+    /// ## 向核心发送vcore debugger的请求
+    ///
+    /// 不像`VdbApi::get_result`那样等待核心线程，而是在当前线程中运行核心`id`的主循环，
+    /// 直到核心回复或运行了1000轮，返回核心的回复。运行核心时出错见`run`
+    #[cfg(feature = "debugger")]
+    pub fn debug_request(&mut self, id: usize, api: VdbApi) -> io::Result<VdbApi> {
+        self.debug_ports[id].write(0, api);
+        for _ in 0..1000 {
+            if *self.debug_ports[id] != api {
+                break;
+            }
+            self.step_core(id)?;
+        }
+        Ok(*self.debug_ports[id])
+    }
+
+    /// 设置是否运行在确定性模式，见类型说明
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+        for runner in self.cores.iter_mut() {
+            runner.set_virtual_time(deterministic);
+        }
+    }
+
+    /// ## 安排定时事件
+    ///
+    /// 核心执行到事件的指令数时注入，指令数相同的事件按安排的顺序注入。
+    /// 只在`step`、`step_core`和`run`中注入
+    pub fn schedule(&mut self, event: TimedEvent) {
+        let events = &mut self.events[event.core];
+        let at = events.partition_point(|e| e.count <= event.count);
+        events.insert(at, event);
+    }
+
+    /// ## 记录非确定性事件
+    ///
+    /// 只记录`step`、`step_core`和`run`运行期间发生的事件，见类型说明
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        for runner in self.cores.iter_mut() {
+            runner.set_recording(true);
+        }
+        self.recorder = Some(recorder);
+    }
+
+    /// ## 跟踪执行的指令
+    ///
+    /// 只跟踪`step`、`step_core`和`run`运行期间执行的指令，见类型说明
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        for runner in self.cores.iter_mut() {
+            runner.set_tracing(true);
+        }
+        self.tracer = Some(tracer);
+    }
+
+    /// ## 重放记录的事件
+    ///
+    /// 进入确定性模式并安排所有事件，定时器不再产生时钟中断
+    pub fn replay(&mut self, events: impl IntoIterator<Item = TimedEvent>) {
+        self.set_deterministic(true);
+        for runner in self.cores.iter_mut() {
+            runner.set_replaying(true);
+        }
+        for event in events {
+            self.schedule(event);
+        }
+    }
+
+    fn record(&mut self, event: &TimedEvent) -> io::Result<()> {
+        match self.recorder.as_mut() {
+            Some(recorder) => recorder.record(event),
+            None => Ok(()),
+        }
+    }
+
+    /// 记录核心`id`在上一轮主循环中观察到的事件
+    fn record_events(&mut self, id: usize) -> io::Result<()> {
+        for event in self.cores[id].take_recorded() {
+            // 设备在发出中断之前已经完成dma写入
+            if let Event::Input(..) = event.event {
+                self.record_dma(id, event.count)?;
+            }
+            self.record(&event)?;
+        }
+        Ok(())
+    }
+
+    /// 记录内容发生了变化的可写dma区域
+    fn record_dma(&mut self, id: usize, count: u64) -> io::Result<()> {
+        let regions: Vec<_> = self.dma_controller.read().unwrap().writable().collect();
+        for (dma, start, length) in regions {
+            if length == 0 {
+                continue;
+            }
+            let data = self.memory.borrow().slice(start, length).to_vec();
+            if self.dma_snapshots.get(&dma) == Some(&data) {
+                continue;
+            }
+            self.record(&TimedEvent::new(id, count, Event::Dma(start, data.clone())))?;
+            self.dma_snapshots.insert(dma, data);
+        }
+        Ok(())
+    }
+
+    /// 注入核心`id`已经到期的定时事件，设备的端口无法创建或连接时返回错误
+    fn deliver_events(&mut self, id: usize) -> io::Result<()> {
+        while self.events[id]
+            .front()
+            .is_some_and(|e| e.count <= *self.cores_inst_count[id])
+        {
+            let event = self.events[id].pop_front().unwrap().event;
+            let core = &mut self.cores[id].core;
+            match event {
+                Event::Link(port) => {
+                    self.io_controller.port(port).map_err(io::Error::other)?;
+                    core.link_device(port).map_err(io::Error::other)?;
+                }
+                Event::Input(port, data) => {
+                    let buffer = if port < 256 {
+                        &mut self.solid_io_ports[id][port as usize]
+                    } else {
+                        self.io_controller.port(port).map_err(io::Error::other)?
+                    };
+                    buffer.device_write(&data);
+                    core.intctler.interrupt_with(
+                        InterruptId::DeviceCommunication,
+                        Message::Port(port).encode(),
+                    );
+                }
+                Event::Clock => core.intctler.interrupt(InterruptId::Clock),
+                Event::Start(target, ip) => self.cores_startflg[target].write(0, (true, ip)),
+                Event::Dma(addr, data) => self.memory.borrow_mut().write_slice(addr, &data),
+                Event::Time(usec) => self.cores[id].times.push_back(usec),
+            }
+        }
+        Ok(())
+    }
+
+    /// ## 注入到期的事件后执行核心`id`的一轮主循环
+    ///
+    /// 写入记录文件或跟踪文件失败时返回错误
+    fn step_runner(&mut self, id: usize) -> io::Result<StepResult> {
+        self.deliver_events(id)?;
+        let result = self.cores[id].step()?;
+        if self.recorder.is_some() {
+            self.record_events(id)?;
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            for record in self.cores[id].take_traced() {
+                tracer.trace(&record)?;
+            }
+            tracer.flush()?;
+        }
+        Ok(result)
+    }
+
+    /// 将数据（如vrom）写入物理内存
+    pub fn load(&mut self, addr: u64, data: &[u8]) {
+        self.memory.borrow_mut().write_slice(addr, data);
+    }
+
+    /// ## 生成快照
+    ///
+    /// 快照包含恢复运行所需的全部状态，见`Snapshot`。
+    /// 定时事件和记录器不属于快照
+    pub fn snapshot(&self) -> Snapshot {
+        let size = self.memory.borrow().size() as u64;
+        let queues = |buffer: &IOPortBuffer| {
+            let (input, output) = buffer.contents();
+            PortQueues { input, output }
+        };
+        let mut solid_ports = Vec::new();
+        for (core, ports) in self.solid_io_ports.iter().enumerate() {
+            for (port, buffer) in ports.iter().enumerate() {
+                let queues = queues(buffer);
+                if !queues.is_empty() {
+                    solid_ports.push((core, port as u16, queues));
+                }
+            }
+        }
+        let mut ports: Vec<_> = self
+            .io_controller
+            .ports
+            .iter()
+            .map(|(&port, buffer)| (port, queues(buffer)))
+            .collect();
+        ports.sort_by_key(|(port, _)| *port);
+        let dma = self.dma_controller.read().unwrap();
+        Snapshot {
+            memory: self.memory.borrow().slice(0, size).to_vec(),
+            cores: self.cores.iter().map(CoreRunner::save).collect(),
+            solid_ports,
+            ports,
+            next_port: self.io_controller.next_port(),
+            dma_next_id: dma.next_id(),
+            dma_current: self.dma_current,
+            dma_opstatus: self.dma_opstatus,
+            dma_regions: dma.regions(),
+        }
+    }
+
+    /// ## 从快照创建虚拟机
+    ///
+    /// 内存大小和核心数都与快照相同，创建后从生成快照时的状态继续运行。
+    /// 快照没有通过`Snapshot::check`时返回错误信息，创建失败时见`new`
+    pub fn restore(instance: &str, snapshot: &Snapshot) -> Result<Self, String> {
+        snapshot.check().map_err(|err| err.to_string())?;
+        let mut machine = Self::new(instance, snapshot.memory.len(), snapshot.cores.len(), true)?;
+        machine.load(0, &snapshot.memory);
+        for (core, port, queues) in snapshot.solid_ports.iter() {
+            machine.solid_io_ports[*core][*port as usize]
+                .set_contents(&queues.input, &queues.output);
+        }
+        for (port, queues) in snapshot.ports.iter() {
+            machine
+                .io_controller
+                .port(*port)?
+                .set_contents(&queues.input, &queues.output);
+        }
+        machine.io_controller.set_next_port(snapshot.next_port);
+        // 核心连接的设备端口须已经创建
+        for (runner, state) in machine.cores.iter_mut().zip(snapshot.cores.iter()) {
+            runner.restore(state)?;
+        }
+        machine
+            .dma_controller
+            .write()
+            .unwrap()
+            .restore(snapshot.dma_next_id, &snapshot.dma_regions);
+        machine.dma_current = snapshot.dma_current;
+        machine.dma_opstatus = snapshot.dma_opstatus;
+        Ok(machine)
+    }
+
+    /// 从`ip`处启动核心，效果与通过固定端口1唤醒核心相同
+    pub fn start_core(&mut self, id: usize, ip: u64) {
+        self.cores_startflg[id].write(0, (true, ip));
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn core(&self, id: usize) -> &Vcore {
+        &self.cores[id].core
+    }
+
+    pub fn core_mut(&mut self, id: usize) -> &mut Vcore {
+        &mut self.cores[id].core
+    }
+
+    pub fn total_core(&self) -> usize {
+        self.cores.len()
+    }
+
+    pub fn instruction_count(&self, id: usize) -> u64 {
+        *self.cores_inst_count[id]
+    }
+
+    /// 处理一轮io请求和固定端口服务，确定性模式下不处理外部设备的请求
+    fn service_io(&mut self) -> io::Result<()> {
+        if !self.deterministic {
+            self.io_controller
+                .dispatch_ioreq()
+                .map_err(io::Error::other)?;
+        }
+        let starts = IOController::solid_ports_service(
+            &mut self.solid_io_ports,
+            &mut self.cores_startflg,
+            &self.dma_controller,
+            &mut self.dma_current,
+            &mut self.dma_opstatus,
+            &self.io_controller.port_deliver,
+        );
+        for (source, target, ip) in starts {
+            let count = *self.cores_inst_count[source];
+            self.record(&TimedEvent::new(source, count, Event::Start(target, ip)))?;
+        }
+        Ok(())
+    }
+
+    /// 单独执行某个核心的一轮主循环
+    pub fn step_core(&mut self, id: usize) -> io::Result<StepResult> {
+        self.service_io()?;
+        self.step_runner(id)
+    }
+
+    /// ## 单步执行
+    ///
+    /// 所有核心依次执行一轮主循环，返回共执行了多少条指令
+    pub fn step(&mut self) -> io::Result<u64> {
+        self.service_io()?;
+        let mut executed = 0;
+        for id in 0..self.cores.len() {
+            if self.step_runner(id)? == StepResult::Executed {
+                executed += 1;
+            }
+        }
+        Ok(executed)
+    }
+
+    /// ## 在当前线程中轮流运行所有核心
+    ///
+    /// 直到所有核心共执行`count`条指令，返回实际执行的指令数。
+    ///
+    /// 若连续多轮都没有核心执行指令（核心未启动、处于nop状态或不断产生异常），
+    /// 认为虚拟机已空闲并提前返回。
+    ///
+    /// 设备的端口无法创建或连接、写入记录文件或跟踪文件失败时返回错误，
+    /// 此时已经执行的指令不会撤销。
+    pub fn run(&mut self, count: u64) -> io::Result<u64> {
+        let mut executed = 0;
+        let mut idle_rounds = 0;
+        while executed < count && idle_rounds < IDLE_ROUNDS {
+            self.service_io()?;
+            let mut progressed = false;
+            for id in 0..self.cores.len() {
+                if executed == count {
+                    break;
+                }
+                if self.step_runner(id)? == StepResult::Executed {
+                    executed += 1;
+                    progressed = true;
+                }
+            }
+            if progressed {
+                idle_rounds = 0;
+            } else {
+                idle_rounds += 1;
+            }
+        }
+        Ok(executed)
+    }
+
+    /// ## 每个核心在独立的线程中运行
+    ///
+    /// io服务也在独立线程中运行，使用`RunningMachine::stop`停止并取回虚拟机。
+    ///
+    /// 多线程运行期间不记录事件，也不跟踪指令。
+    /// 出错的线程提前停止，错误在`RunningMachine::stop`时返回。
+    pub fn spawn(mut self) -> RunningMachine {
+        let stop = Arc::new(AtomicBool::new(false));
+        let inst_counts = self.cores_inst_count.clone();
+        let recorder = self.recorder.take();
+        let mut cores = Vec::new();
+        for mut runner in self.cores.drain(..) {
+            runner.set_recording(false);
+            runner.set_tracing(false);
+            let stop = Arc::clone(&stop);
+            cores.push(thread::spawn(move || {
+                let result = runner.run_until(&stop);
+                (runner, result)
+            }));
+        }
+        let service_stop = Arc::clone(&stop);
+        let service = thread::spawn(move || {
+            let mut result = Ok(());
+            while result.is_ok() && !service_stop.load(Ordering::Relaxed) {
+                result = self.service_io();
+                thread::yield_now();
+            }
+            (self, result)
+        });
+        RunningMachine {
+            stop,
+            cores,
+            service: Some(service),
+            inst_counts,
+            recorder,
+        }
+    }
+}
+
+/// ## 正在多线程运行的虚拟机
+///
+/// 被drop时停止所有线程
+pub struct RunningMachine {
+    stop: Arc<AtomicBool>,
+    cores: Vec<JoinHandle<(CoreRunner, io::Result<()>)>>,
+    service: Option<JoinHandle<(Machine, io::Result<()>)>>,
+    inst_counts: Vec<SharedPointer<u64>>,
+    /// 停止后放回虚拟机的记录器
+    recorder: Option<Recorder>,
+}
+
+impl RunningMachine {
+    pub fn instruction_count(&self, id: usize) -> u64 {
+        *self.inst_counts[id]
+    }
+
+    /// ## 停止所有核心并取回虚拟机
+    ///
+    /// 有线程因为错误提前停止时返回第一个错误，有线程panic时把panic传给调用者
+    pub fn stop(mut self) -> io::Result<Machine> {
+        match self.join() {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => unreachable!("虚拟机已经被取回"),
+        }
+    }
+
+    fn join(&mut self) -> Option<thread::Result<io::Result<Machine>>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let cores: Vec<_> = self.cores.drain(..).map(|h| h.join()).collect();
+        let service = self.service.take()?.join();
+        Some(service.and_then(|(mut machine, mut result)| {
+            for core in cores {
+                let (runner, core_result) = core?;
+                result = result.and(core_result);
+                machine.cores.push(runner);
+            }
+            machine.recorder = self.recorder.take();
+            let recording = machine.recorder.is_some();
+            let tracing = machine.tracer.is_some();
+            for runner in machine.cores.iter_mut() {
+                runner.set_recording(recording);
+                runner.set_tracing(tracing);
+            }
+            Ok(result.map(|()| machine))
+        }))
+    }
+}
+
+impl Drop for RunningMachine {
+    fn drop(&mut self) {
+        // drop时不能panic，忽略线程的错误和panic
+        let _ = self.join();
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, LineWriter, Write},
+    path::Path,
+};
+
+/// ## 外部事件
+///
+/// 在确定性模式下，设备输入等外部事件不是由设备异步产生的，
+/// 而是在某个核心执行到固定的指令数时注入。
+///
+/// 记录运行时产生的所有非确定性事件也使用同样的表示，见`Recorder`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// 连接一个设备到`port`，核心产生Device中断
+    Link(u16),
+    /// 设备向`port`输入数据（按8字节小端打包），核心产生DeviceCommunication中断
+    Input(u16, Vec<u8>),
+    /// 定时器到期，核心产生Clock中断
+    Clock,
+    /// 通过固定端口1从`ip`启动核心`target`
+    Start(usize, u64),
+    /// 设备通过dma向物理地址`addr`写入数据
+    Dma(u64, Vec<u8>),
+    /// 核心读取定时器的微秒计数器（命令5）时得到的值，重放时代替虚拟时间
+    Time(u64),
+}
+
+/// ## 定时事件
+///
+/// 核心`core`执行了`count`条指令后，在执行下一条指令之前注入`event`。
+///
+/// 文本格式为一行：
+///
+/// * `<core> <count> link <port>`
+/// * `<core> <count> input <port> <十六进制数据>`
+/// * `<core> <count> clock`
+/// * `<core> <count> start <target> <ip>`
+/// * `<core> <count> dma <addr> <十六进制数据>`
+/// * `<core> <count> time <微秒>`
+///
+/// 空行和以`#`开始的行被忽略。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub core: usize,
+    pub count: u64,
+    pub event: Event,
+}
+
+impl TimedEvent {
+    pub fn new(core: usize, count: u64, event: Event) -> Self {
+        Self { core, count, event }
+    }
+
+    /// 解析一行事件，空行和注释返回`Ok(None)`
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<u64, String> {
+            let field = fields
+                .get(i)
+                .ok_or_else(|| format!("缺少字段: \"{}\"", line))?;
+            field
+                .parse()
+                .map_err(|_| format!("\"{}\"不是数字: \"{}\"", field, line))
+        };
+        let data = |i: usize| -> Result<Vec<u8>, String> {
+            let hex = fields.get(i).copied().unwrap_or("");
+            if hex.len() % 2 != 0 || !hex.is_ascii() {
+                return Err(format!("数据不是十六进制字节: \"{}\"", line));
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("数据不是十六进制字节: \"{}\"", line))
+        };
+        let core = number(0)? as usize;
+        let count = number(1)?;
+        let event = match fields.get(2) {
+            Some(&"link") => Event::Link(number(3)? as u16),
+            Some(&"input") => Event::Input(number(3)? as u16, data(4)?),
+            Some(&"clock") => Event::Clock,
+            Some(&"start") => Event::Start(number(3)? as usize, number(4)?),
+            Some(&"dma") => Event::Dma(number(3)?, data(4)?),
+            Some(&"time") => Event::Time(number(3)?),
+            _ => return Err(format!("未知事件: \"{}\"", line)),
+        };
+        Ok(Some(Self::new(core, count, event)))
+    }
+
+    /// 从文件读取所有事件
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>, String> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|err| format!("{}: {}", path.as_ref().display(), err))?;
+        let mut events = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            match Self::parse(line) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => (),
+                Err(err) => return Err(format!("第{}行: {}", i + 1, err)),
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl fmt::Display for TimedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |f: &mut fmt::Formatter<'_>, data: &[u8]| {
+            data.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+        };
+        write!(f, "{} {} ", self.core, self.count)?;
+        match &self.event {
+            Event::Link(port) => write!(f, "link {}", port),
+            Event::Input(port, data) => {
+                write!(f, "input {} ", port)?;
+                hex(f, data)
+            }
+            Event::Clock => write!(f, "clock"),
+            Event::Start(target, ip) => write!(f, "start {} {}", target, ip),
+            Event::Dma(addr, data) => {
+                write!(f, "dma {} ", addr)?;
+                hex(f, data)
+            }
+            Event::Time(usec) => write!(f, "time {}", usec),
+        }
+    }
+}
+
+/// ## 事件记录器
+///
+/// 按发生的顺序把事件逐行写入文件，格式与`TimedEvent::load`读取的相同，
+/// 记录的文件可以直接用于`Machine::replay`重放。
+pub struct Recorder {
+    writer: LineWriter<File>,
+}
+
+impl Recorder {
+    /// 创建（或清空）记录文件
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: LineWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, event: &TimedEvent) -> io::Result<()> {
+        writeln!(self.writer, "{}", event)
+    }
+}
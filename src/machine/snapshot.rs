@@ -0,0 +1,375 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::vrisc::vcore::{
+    dma::{DMARegion, DMAStatus},
+    intcontroller::{InterruptId, Pending},
+    regs_flags::{FloatRegisters, Registers},
+    timer::Timer,
+};
+
+/// 快照文件开头的标识
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"VCORESNP";
+
+/// ## 快照格式版本
+///
+/// 快照中保存的内容或编码方式改变时递增，读取时拒绝其它版本的快照
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+/// ## 虚拟机快照
+///
+/// 保存虚拟机的完整状态：物理内存、每个核心的寄存器、中断控制器、惰性寻址状态、
+/// 已加载的扩展指令集、定时器，所有io端口队列中的数据以及dma区域。
+///
+/// 使用`Machine::snapshot`生成，`Machine::restore`从快照创建新的虚拟机。
+/// 文件中所有数值都按小端存储，以`SNAPSHOT_MAGIC`和`SNAPSHOT_VERSION`开始。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub memory: Vec<u8>,
+    pub cores: Vec<CoreState>,
+    /// 非空的固定端口，每一项为(核心, 端口, 队列)
+    pub solid_ports: Vec<(usize, u16, PortQueues)>,
+    /// 所有设备端口
+    pub ports: Vec<(u16, PortQueues)>,
+    /// 下一个要分配给设备的端口号
+    pub next_port: u16,
+    pub dma_next_id: u64,
+    pub dma_current: u64,
+    pub dma_opstatus: DMAStatus,
+    pub dma_regions: Vec<DMARegion>,
+}
+
+/// ## 核心状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreState {
+    pub started: bool,
+    /// 启动标志，(是否允许启动, 启动地址)
+    pub startflg: (bool, u64),
+    pub inst_count: u64,
+    pub cycles: u64,
+    /// 定时器的微秒计数器
+    pub usec: u64,
+    pub timer: Timer,
+    pub regs: Registers,
+    pub fregs: FloatRegisters,
+    pub interrupt_mask: u64,
+    /// 待处理的中断，按产生的顺序
+    pub pending: Vec<Pending>,
+    pub hot_ip: u64,
+    pub had_run_inst: bool,
+    pub crossed_page: bool,
+    pub ip_increment: i64,
+    /// 已加载的扩展指令集，见`Vcore::extensions`
+    pub extensions: u64,
+    pub transferred: bool,
+    pub nopflag: bool,
+    /// 已连接到此核心的设备端口
+    pub linked: Vec<u16>,
+}
+
+/// ## io端口中的数据
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PortQueues {
+    /// 设备写入、核心尚未读出的数据
+    pub input: Vec<u64>,
+    /// 核心写入、设备尚未读出的数据
+    pub output: Vec<u64>,
+}
+
+impl PortQueues {
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty() && self.output.is_empty()
+    }
+}
+
+impl Snapshot {
+    /// 写入快照文件
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let error = |err: io::Error| format!("{}: {}", path.as_ref().display(), err);
+        let file = File::create(path.as_ref()).map_err(error)?;
+        let mut writer = BufWriter::new(file);
+        self.encode(&mut writer).map_err(error)?;
+        writer.flush().map_err(error)
+    }
+
+    /// 读取快照文件
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let error = |err: io::Error| format!("{}: {}", path.as_ref().display(), err);
+        let file = File::open(path.as_ref()).map_err(error)?;
+        Self::decode(&mut BufReader::new(file)).map_err(error)
+    }
+
+    fn encode(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(&SNAPSHOT_MAGIC)?;
+        put(w, SNAPSHOT_VERSION)?;
+        put(w, self.memory.len() as u64)?;
+        w.write_all(&self.memory)?;
+        put(w, self.cores.len() as u64)?;
+        for core in self.cores.iter() {
+            core.encode(w)?;
+        }
+        put(w, self.solid_ports.len() as u64)?;
+        for (core, port, queues) in self.solid_ports.iter() {
+            put(w, *core as u64)?;
+            put(w, *port as u64)?;
+            queues.encode(w)?;
+        }
+        put(w, self.ports.len() as u64)?;
+        for (port, queues) in self.ports.iter() {
+            put(w, *port as u64)?;
+            queues.encode(w)?;
+        }
+        put(w, self.next_port as u64)?;
+        put(w, self.dma_next_id)?;
+        put(w, self.dma_current)?;
+        put(w, self.dma_opstatus as u64)?;
+        put(w, self.dma_regions.len() as u64)?;
+        for region in self.dma_regions.iter() {
+            put(w, region.id)?;
+            put(w, region.start)?;
+            put(w, region.length)?;
+            put(w, region.read as u64)?;
+            put(w, region.write as u64)?;
+        }
+        Ok(())
+    }
+
+    fn decode(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(invalid("不是vcore快照文件".to_string()));
+        }
+        let version = get(r)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(format!(
+                "快照版本为{}，只支持版本{}",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+        // 长度来自文件，不预先分配，截断的文件在读完之前就会出错
+        let length = get(r)?;
+        let mut memory = Vec::new();
+        r.take(length).read_to_end(&mut memory)?;
+        if memory.len() as u64 != length {
+            return Err(invalid(format!(
+                "内存大小为{}，文件中只有{}字节",
+                length,
+                memory.len()
+            )));
+        }
+        let cores = list(r, CoreState::decode)?;
+        let solid_ports = list(r, |r| {
+            Ok((get(r)? as usize, get(r)? as u16, PortQueues::decode(r)?))
+        })?;
+        let ports = list(r, |r| Ok((get(r)? as u16, PortQueues::decode(r)?)))?;
+        let next_port = get(r)? as u16;
+        let dma_next_id = get(r)?;
+        let dma_current = get(r)?;
+        let dma_opstatus = match get(r)? {
+            0 => DMAStatus::None,
+            1 => DMAStatus::SetCurrentDMAId,
+            2 => DMAStatus::SetDMAStart,
+            3 => DMAStatus::SetDMALength,
+            4 => DMAStatus::SetDMARead,
+            5 => DMAStatus::SetDMAWrite,
+            6 => DMAStatus::RemoveDMA,
+            status => return Err(invalid(format!("无效的dma状态{}", status))),
+        };
+        let dma_regions = list(r, |r| {
+            Ok(DMARegion {
+                id: get(r)?,
+                start: get(r)?,
+                length: get(r)?,
+                read: get(r)? != 0,
+                write: get(r)? != 0,
+            })
+        })?;
+        let snapshot = Self {
+            memory,
+            cores,
+            solid_ports,
+            ports,
+            next_port,
+            dma_next_id,
+            dma_current,
+            dma_opstatus,
+            dma_regions,
+        };
+        snapshot.check()?;
+        Ok(snapshot)
+    }
+
+    /// ## 检查快照是否可以恢复
+    ///
+    /// 至少有一个核心、内存不为空，固定端口的核心和端口号都在范围之内，
+    /// 否则返回`InvalidData`
+    pub fn check(&self) -> io::Result<()> {
+        if self.cores.is_empty() {
+            return Err(invalid("快照中没有核心".to_string()));
+        }
+        if self.memory.is_empty() {
+            return Err(invalid("快照中的内存为空".to_string()));
+        }
+        for (core, port, _) in self.solid_ports.iter() {
+            if *core >= self.cores.len() || *port >= 256 {
+                return Err(invalid(format!(
+                    "核心{}的固定端口{}不存在，快照中只有{}个核心",
+                    core,
+                    port,
+                    self.cores.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CoreState {
+    fn encode(&self, w: &mut impl Write) -> io::Result<()> {
+        put(w, self.started as u64)?;
+        put(w, self.startflg.0 as u64)?;
+        put(w, self.startflg.1)?;
+        put(w, self.inst_count)?;
+        put(w, self.cycles)?;
+        put(w, self.usec)?;
+        self.timer.to_raw().iter().try_for_each(|&x| put(w, x))?;
+        let regs = &self.regs;
+        regs.x.iter().try_for_each(|&x| put(w, x))?;
+        for x in [
+            regs.ip,
+            regs.flag,
+            regs.ivt,
+            regs.kpt,
+            regs.upt,
+            regs.scp,
+            regs.imsg,
+            regs.ipdump,
+            regs.flagdump,
+        ] {
+            put(w, x)?;
+        }
+        self.fregs.f.iter().try_for_each(|&x| put(w, x))?;
+        put(w, self.fregs.fcsr)?;
+        put(w, self.interrupt_mask)?;
+        put(w, self.pending.len() as u64)?;
+        for pending in self.pending.iter() {
+            put(w, pending.id as u64)?;
+            put(w, pending.msg)?;
+        }
+        put(w, self.hot_ip)?;
+        put(w, self.had_run_inst as u64)?;
+        put(w, self.crossed_page as u64)?;
+        put(w, self.ip_increment as u64)?;
+        put(w, self.extensions)?;
+        put(w, self.transferred as u64)?;
+        put(w, self.nopflag as u64)?;
+        put(w, self.linked.len() as u64)?;
+        self.linked.iter().try_for_each(|&port| put(w, port as u64))
+    }
+
+    fn decode(r: &mut impl Read) -> io::Result<Self> {
+        let started = get(r)? != 0;
+        let startflg = (get(r)? != 0, get(r)?);
+        let inst_count = get(r)?;
+        let cycles = get(r)?;
+        let usec = get(r)?;
+        let timer = [get(r)?, get(r)?, get(r)?, get(r)?];
+        let timer = Timer::from_raw(timer).ok_or_else(|| invalid("无效的定时器".to_string()))?;
+        let mut regs = Registers::new();
+        for x in regs.x.iter_mut() {
+            *x = get(r)?;
+        }
+        for x in [
+            &mut regs.ip,
+            &mut regs.flag,
+            &mut regs.ivt,
+            &mut regs.kpt,
+            &mut regs.upt,
+            &mut regs.scp,
+            &mut regs.imsg,
+            &mut regs.ipdump,
+            &mut regs.flagdump,
+        ] {
+            *x = get(r)?;
+        }
+        let mut fregs = FloatRegisters::new();
+        for f in fregs.f.iter_mut() {
+            *f = get(r)?;
+        }
+        fregs.fcsr = get(r)?;
+        let interrupt_mask = get(r)?;
+        let pending = list(r, |r| {
+            Ok(Pending {
+                id: InterruptId::generate(get(r)? as u8),
+                msg: get(r)?,
+            })
+        })?;
+        Ok(Self {
+            started,
+            startflg,
+            inst_count,
+            cycles,
+            usec,
+            timer,
+            regs,
+            fregs,
+            interrupt_mask,
+            pending,
+            hot_ip: get(r)?,
+            had_run_inst: get(r)? != 0,
+            crossed_page: get(r)? != 0,
+            ip_increment: get(r)? as i64,
+            extensions: get(r)?,
+            transferred: get(r)? != 0,
+            nopflag: get(r)? != 0,
+            linked: list(r, |r| Ok(get(r)? as u16))?,
+        })
+    }
+}
+
+impl PortQueues {
+    fn encode(&self, w: &mut impl Write) -> io::Result<()> {
+        for queue in [&self.input, &self.output] {
+            put(w, queue.len() as u64)?;
+            queue.iter().try_for_each(|&x| put(w, x))?;
+        }
+        Ok(())
+    }
+
+    fn decode(r: &mut impl Read) -> io::Result<Self> {
+        Ok(Self {
+            input: list(r, get)?,
+            output: list(r, get)?,
+        })
+    }
+}
+
+fn put(w: &mut impl Write, x: u64) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+fn get(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// 读取长度和之后的各项，长度来自文件，所以不按它预先分配
+fn list<R: Read, T>(
+    r: &mut R,
+    mut item: impl FnMut(&mut R) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    let mut list = Vec::new();
+    for _ in 0..get(r)? {
+        list.push(item(r)?);
+    }
+    Ok(list)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
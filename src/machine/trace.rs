@@ -0,0 +1,347 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::vrisc::{
+    disasm::Instruction,
+    vcore::regs_flags::{FloatRegisters, Registers},
+};
+
+/// 二进制跟踪文件开头的标识
+pub const TRACE_MAGIC: [u8; 8] = *b"VCORETRC";
+
+/// ## 二进制跟踪格式版本
+///
+/// 记录的内容或编码方式改变时递增，读取时拒绝其它版本的跟踪文件
+pub const TRACE_VERSION: u64 = 1;
+
+/// 跟踪中比较的寄存器个数，编号见`register_name`
+pub const TRACE_REGISTERS: usize = 41;
+
+const CONTROL_REGISTERS: [&str; 8] = [
+    "flag", "ivt", "kpt", "upt", "scp", "imsg", "ipdump", "flagdump",
+];
+
+/// ## 寄存器的名字
+///
+/// 0-15为x0-x15，16-23为flag、ivt、kpt、upt、scp、imsg、ipdump、flagdump，
+/// 24-39为浮点寄存器f0-f15，40为fcsr。ip不在其中，每条记录都有执行时的ip
+pub fn register_name(index: u8) -> String {
+    match index {
+        0..=15 => format!("x{}", index),
+        16..=23 => CONTROL_REGISTERS[index as usize - 16].to_string(),
+        24..=39 => format!("f{}", index - 24),
+        40 => "fcsr".to_string(),
+        _ => format!("r{}", index),
+    }
+}
+
+/// 按`register_name`的编号取出所有寄存器的值
+pub fn register_values(regs: &Registers, fregs: &FloatRegisters) -> [u64; TRACE_REGISTERS] {
+    let mut values = [0; TRACE_REGISTERS];
+    values[..16].copy_from_slice(&regs.x);
+    values[16..24].copy_from_slice(&[
+        regs.flag,
+        regs.ivt,
+        regs.kpt,
+        regs.upt,
+        regs.scp,
+        regs.imsg,
+        regs.ipdump,
+        regs.flagdump,
+    ]);
+    values[24..40].copy_from_slice(&fregs.f);
+    values[40] = fregs.fcsr;
+    values
+}
+
+/// ## 一次内存访问
+///
+/// `addr`是寻址后的物理地址，读取时`data`是读到的数据，写入时是写入的数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub write: bool,
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+impl MemoryAccess {
+    pub fn new(write: bool, addr: u64, data: Vec<u8>) -> Self {
+        Self { write, addr, data }
+    }
+}
+
+/// ## 一条指令的执行记录
+///
+/// 核心`core`执行的第`count`条指令（从0开始），`ip`为虚拟地址，`hot_ip`为寻址后的物理地址，
+/// `bytes`为指令的全部字节。`changes`是执行后值发生了变化的寄存器（编号见`register_name`）和新值，
+/// `accesses`是指令按顺序进行的内存访问。
+///
+/// 文本格式为一行：
+///
+/// ```text
+/// <core> <count> ip=<ip> hot_ip=<hot_ip> <十六进制字节> <反汇编> | <寄存器>=<新值> ... | r[<物理地址>]=<十六进制数据> w[<物理地址>]=<十六进制数据> ...
+/// ```
+///
+/// 二进制格式见`Tracer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub core: usize,
+    pub count: u64,
+    pub ip: u64,
+    pub hot_ip: u64,
+    pub bytes: Vec<u8>,
+    pub changes: Vec<(u8, u64)>,
+    pub accesses: Vec<MemoryAccess>,
+}
+
+impl TraceRecord {
+    /// 解码后的指令
+    pub fn instruction(&self) -> Instruction {
+        Instruction::decode(&self.bytes, self.ip)
+    }
+
+    /// 比较执行前后的寄存器，见`register_values`
+    pub fn changes(
+        before: &[u64; TRACE_REGISTERS],
+        after: &[u64; TRACE_REGISTERS],
+    ) -> Vec<(u8, u64)> {
+        (0..TRACE_REGISTERS)
+            .filter(|&i| before[i] != after[i])
+            .map(|i| (i as u8, after[i]))
+            .collect()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Vec::new();
+        put(&mut w, self.core as u64);
+        put(&mut w, self.count);
+        put(&mut w, self.ip);
+        put(&mut w, self.hot_ip);
+        put(&mut w, self.bytes.len() as u64);
+        w.extend_from_slice(&self.bytes);
+        put(&mut w, self.changes.len() as u64);
+        for &(reg, value) in self.changes.iter() {
+            w.push(reg);
+            put(&mut w, value);
+        }
+        put(&mut w, self.accesses.len() as u64);
+        for access in self.accesses.iter() {
+            w.push(access.write as u8);
+            put(&mut w, access.addr);
+            put(&mut w, access.data.len() as u64);
+            w.extend_from_slice(&access.data);
+        }
+        w
+    }
+
+    /// 解码一条记录，文件在记录之间结束时返回`Ok(None)`
+    fn decode(r: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut first = [0u8];
+        if r.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let core = continue_get(r, first[0])? as usize;
+        let count = get(r)?;
+        let ip = get(r)?;
+        let hot_ip = get(r)?;
+        let bytes = data(r)?;
+        let changes = (0..get(r)?)
+            .map(|_| Ok((byte(r)?, get(r)?)))
+            .collect::<io::Result<_>>()?;
+        let accesses = (0..get(r)?)
+            .map(|_| {
+                let write = match byte(r)? {
+                    0 => false,
+                    1 => true,
+                    kind => return Err(invalid(format!("无效的访问类型{}", kind))),
+                };
+                Ok(MemoryAccess::new(write, get(r)?, data(r)?))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Some(Self {
+            core,
+            count,
+            ip,
+            hot_ip,
+            bytes,
+            changes,
+            accesses,
+        }))
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |data: &[u8]| -> String { data.iter().map(|b| format!("{:02x}", b)).collect() };
+        write!(
+            f,
+            "{} {} ip={:#x} hot_ip={:#x} {} {} |",
+            self.core,
+            self.count,
+            self.ip,
+            self.hot_ip,
+            hex(&self.bytes),
+            self.instruction()
+        )?;
+        for &(reg, value) in self.changes.iter() {
+            write!(f, " {}={:#x}", register_name(reg), value)?;
+        }
+        write!(f, " |")?;
+        for access in self.accesses.iter() {
+            let kind = if access.write { 'w' } else { 'r' };
+            write!(f, " {}[{:#x}]={}", kind, access.addr, hex(&access.data))?;
+        }
+        Ok(())
+    }
+}
+
+/// ## 指令跟踪器
+///
+/// 按执行的顺序把`TraceRecord`写入文件。
+///
+/// * 文本格式每条记录一行，见`TraceRecord`
+/// * 二进制格式以`TRACE_MAGIC`和`TRACE_VERSION`开始，之后依次为每条记录，
+///   数值都是LEB128编码的无符号数：核心、指令数、ip、hot_ip、指令长度和字节、
+///   变化的寄存器个数和每个寄存器的(1字节编号, 新值)、内存访问次数和每次访问的
+///   (1字节类型（0读1写）, 物理地址, 长度, 数据)。用`TraceReader`读取
+///
+/// 两种格式都先写入缓冲区，`Machine`在每个核心执行一轮主循环后调用`flush`，
+/// 虚拟机被强行结束时已执行的指令不会丢失。`finish`或者drop时写出缓冲区中剩余的记录
+pub struct Tracer {
+    writer: BufWriter<File>,
+    binary: bool,
+}
+
+impl Tracer {
+    /// 创建（或清空）跟踪文件，`binary`为true时使用二进制格式
+    pub fn create(path: impl AsRef<Path>, binary: bool) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if binary {
+            let mut header = TRACE_MAGIC.to_vec();
+            put(&mut header, TRACE_VERSION);
+            writer.write_all(&header)?;
+        }
+        Ok(Self { writer, binary })
+    }
+
+    /// 把一条记录写入缓冲区
+    pub fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if self.binary {
+            self.writer.write_all(&record.encode())
+        } else {
+            writeln!(self.writer, "{}", record)
+        }
+    }
+
+    /// 把缓冲区中的记录写入文件
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// 结束跟踪，返回写入剩余记录时的错误
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// ## 二进制跟踪文件的读取器
+///
+/// 按写入的顺序逐条读出`TraceRecord`
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl TraceReader<BufReader<File>> {
+    /// 打开跟踪文件
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let error = |err: io::Error| format!("{}: {}", path.as_ref().display(), err);
+        let file = File::open(path.as_ref()).map_err(error)?;
+        Self::new(BufReader::new(file)).map_err(error)
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    /// 检查文件头
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != TRACE_MAGIC {
+            return Err(invalid("不是vcore跟踪文件".to_string()));
+        }
+        let version = get(&mut reader)?;
+        if version != TRACE_VERSION {
+            return Err(invalid(format!(
+                "跟踪文件版本为{}，只支持版本{}",
+                version, TRACE_VERSION
+            )));
+        }
+        Ok(Self { reader })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        TraceRecord::decode(&mut self.reader).transpose()
+    }
+}
+
+fn put(w: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        w.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    w.push(x as u8);
+}
+
+fn get(r: &mut impl Read) -> io::Result<u64> {
+    let first = byte(r)?;
+    continue_get(r, first)
+}
+
+/// 已经读出第一个字节`first`之后继续读取LEB128编码的数
+fn continue_get(r: &mut impl Read, first: u8) -> io::Result<u64> {
+    let mut x = (first & 0x7f) as u64;
+    let mut next = first;
+    let mut shift = 7;
+    while next & 0x80 != 0 {
+        if shift >= 64 {
+            return Err(invalid("数值超过64位".to_string()));
+        }
+        next = byte(r)?;
+        x |= ((next & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+    Ok(x)
+}
+
+fn byte(r: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0u8];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn data(r: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = get(r)?;
+    let mut data = Vec::new();
+    r.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
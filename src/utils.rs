@@ -1,4 +1,3 @@
-pub mod clock;
 pub mod memory;
 pub mod rdxparse;
 pub mod shared;
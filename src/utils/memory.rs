@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, BinaryHeap};
 
 use crate::{
-    utils::shared::{Addressable, SharedPointer},
+    utils::shared::{shared_name, Addressable, SharedPointer},
     vrisc::vcore::{regs_flags::FlagRegFlag, BitOptions},
 };
 
@@ -35,20 +35,22 @@ pub struct Memory {
 }
 
 impl Memory {
-    pub fn new(memory: usize) -> Self {
-        Memory {
-            memory: SharedPointer::new("VcoreVriscMainMemory".to_string(), memory).unwrap(),
+    /// 创建物理内存，共享内存创建失败时返回错误信息
+    pub fn new(instance: &str, memory: usize) -> Result<Self, String> {
+        Ok(Memory {
+            memory: SharedPointer::create(shared_name(instance, "VcoreVriscMainMemory"), memory)?,
             address_buffer: BTreeMap::new(),
             filter: BinaryHeap::new(),
-        }
+        })
     }
 
-    pub fn bind(memory: usize) -> Self {
-        Memory {
-            memory: SharedPointer::bind("VcoreVriscMainMemory".to_string(), memory).unwrap(),
+    /// 连接已经创建的物理内存，失败时返回错误信息
+    pub fn bind(instance: &str, memory: usize) -> Result<Self, String> {
+        Ok(Memory {
+            memory: SharedPointer::open(shared_name(instance, "VcoreVriscMainMemory"), memory)?,
             address_buffer: BTreeMap::new(),
             filter: BinaryHeap::new(),
-        }
+        })
     }
 
     #[inline]
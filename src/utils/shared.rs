@@ -17,7 +17,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 #[cfg(target_os = "linux")]
-use std::{mem::size_of, num::NonZeroUsize};
+use std::{fs, mem::size_of, num::NonZeroUsize, sync::Mutex};
 
 #[cfg(target_os = "windows")]
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
@@ -43,18 +43,33 @@ pub enum AssignError {
     IndexOutOfSize,
 }
 
+#[cfg(target_os = "linux")]
+/// ## 本进程创建的共享内存
+///
+/// (共享内存名, 创建者进程)
+///
+/// 用于在进程崩溃或被信号终止、来不及drop所有SharedPointer时删除所有共享内存。
+/// 由于fork出的子进程会复制这张表，删除时只删除由当前进程创建的共享内存。
+static CREATED: Mutex<Vec<(String, unistd::Pid)>> = Mutex::new(Vec::new());
+
 #[derive(Debug)]
 /// ## 共享内存的指针
 ///
 /// 将linux、winows、mac三个平台上的共享内存功能封装。
 ///
 /// > 并不是智能指针
+///
+/// 在linux上，使用`new`创建的共享内存归创建者进程所有，创建者drop时删除共享内存，
+/// 使用`bind`连接的共享内存drop时只解除映射。
 pub struct SharedPointer<T> {
     pub pointer: *mut T,
     size: usize,
     name: String,
     #[cfg(target_os = "linux")]
     fd: i32,
+    #[cfg(target_os = "linux")]
+    /// 创建者进程，`bind`得到的共享内存为None
+    owner: Option<unistd::Pid>,
     #[cfg(target_os = "windows")]
     hdl: *mut c_void,
 }
@@ -64,13 +79,15 @@ unsafe impl<T> Sync for SharedPointer<T> {}
 
 impl<T> SharedPointer<T> {
     #[cfg(target_os = "linux")]
+    /// 同名的共享内存已经存在（同一实例正在运行或之前崩溃的实例的残留）时返回`EEXIST`，
+    /// 不会连接到旧的共享内存上
     pub fn new(name: String, size: usize) -> Result<Self, Errno> {
         if size == 0 {
             panic!("The memory you are allocating sizes 0.");
         }
         let fd = sys::mman::shm_open(
             ("/".to_string() + &name).as_str(),
-            OFlag::O_RDWR | OFlag::O_CREAT,
+            OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL,
             Mode::S_IRUSR | Mode::S_IWUSR,
         )?;
         unistd::ftruncate(fd, (size * size_of::<T>()) as i64)?;
@@ -84,11 +101,16 @@ impl<T> SharedPointer<T> {
                 0,
             )?
         };
+        let owner = unistd::getpid();
+        if let Ok(mut created) = CREATED.lock() {
+            created.push((name.clone(), owner));
+        }
         Ok(SharedPointer {
             pointer: addr as *mut T,
             size,
             name,
             fd,
+            owner: Some(owner),
         })
     }
 
@@ -156,6 +178,7 @@ impl<T> SharedPointer<T> {
             size,
             name,
             fd,
+            owner: None,
         })
     }
 
@@ -191,9 +214,17 @@ impl<T> SharedPointer<T> {
 impl<T> Drop for SharedPointer<T> {
     #[cfg(target_os = "linux")]
     fn drop(&mut self) {
-        unsafe { sys::mman::munmap(self.pointer as *mut c_void, self.size).unwrap() };
+        unsafe {
+            sys::mman::munmap(self.pointer as *mut c_void, self.size * size_of::<T>()).unwrap()
+        };
         unistd::close(self.fd).unwrap();
         self.pointer = 0 as *mut T;
+        if self.owner == Some(unistd::getpid()) {
+            let _ = sys::mman::shm_unlink(("/".to_string() + &self.name).as_str());
+            if let Ok(mut created) = CREATED.lock() {
+                created.retain(|(name, _)| *name != self.name);
+            }
+        }
     }
 
     #[cfg(target_os = "windows")]
@@ -263,7 +294,7 @@ impl<T> Addressable<T> for SharedPointer<T> {
 
     #[inline]
     fn write_slice(&mut self, addr: u64, s: &[T]) {
-        if (addr as usize) + s.len() < self.size {
+        if (addr as usize) + s.len() <= self.size {
             unsafe {
                 self.pointer
                     .add(addr as usize)
@@ -286,7 +317,159 @@ impl<T> DerefMut for SharedPointer<T> {
     }
 }
 
+#[cfg(target_os = "linux")]
+/// ## 删除本进程创建的所有共享内存
+///
+/// 在进程被信号终止或panic时调用，此时持有SharedPointer的线程可能不会再drop它们。
+/// 已删除名字的共享内存在所有映射解除后才会真正释放，因此仍在运行的线程不受影响。
+pub fn unlink_all() {
+    // panic可能发生在持有锁期间，不能阻塞
+    let mut created = match CREATED.try_lock() {
+        Ok(created) => created,
+        Err(_) => return,
+    };
+    let pid = unistd::getpid();
+    created.retain(|(name, owner)| {
+        if *owner == pid {
+            let _ = sys::mman::shm_unlink(("/".to_string() + name).as_str());
+            false
+        } else {
+            true
+        }
+    });
+}
+
+#[cfg(target_os = "windows")]
+/// windows上的共享内存在所有句柄关闭后自动释放
+pub fn unlink_all() {}
+
+#[cfg(target_os = "linux")]
+/// ## 清理残留的共享内存
+///
+/// 删除实例`instance`遗留的所有共享内存（例如之前崩溃的vcore留下的），返回删除的数量。
+///
+/// 只删除去掉实例标识后恰好是vcore使用的共享内存名的共享内存，见`is_segment`，
+/// 不会误删标识以此实例标识开头的其它实例的共享内存。只应在该实例没有运行时使用。
+pub fn cleanup(instance: &str) -> usize {
+    let prefix = shared_name(instance, "");
+    let mut count = 0;
+    if let Ok(entries) = fs::read_dir("/dev/shm") {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.strip_prefix(&prefix).is_some_and(is_segment)
+                && sys::mman::shm_unlink(("/".to_string() + &name).as_str()).is_ok()
+            {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// 不带编号的共享内存名
+const SEGMENTS: [&str; 3] = [
+    "VcoreVriscMainMemory",
+    "VcoreIORequestPort",
+    "VcoreInterruptPort",
+];
+
+/// 带有一个编号（核心号、端口号或dma编号）的共享内存名的(前缀, 后缀)
+const NUMBERED_SEGMENTS: [(&str, &str); 5] = [
+    ("VcoreCore", "StartFlg"),
+    ("VcoreCore", "InstCount"),
+    ("VcoreCore", "DebugApi"),
+    ("VcoreDMA", "Obj"),
+    ("VcoreIOPort", ""),
+];
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// ## 是否为vcore使用的共享内存名
+///
+/// `name`不带实例标识。包括固定的共享内存名、每个核心的共享内存、设备端口和dma，
+/// 以及每个核心的固定端口`VcoreIOPort<端口>C<核心>`
+pub fn is_segment(name: &str) -> bool {
+    SEGMENTS.contains(&name)
+        || NUMBERED_SEGMENTS.iter().any(|(prefix, suffix)| {
+            name.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+                .is_some_and(is_number)
+        })
+        || name
+            .strip_prefix("VcoreIOPort")
+            .and_then(|rest| rest.split_once('C'))
+            .is_some_and(|(port, core)| is_number(port) && is_number(core))
+}
+
+#[cfg(target_os = "windows")]
+/// windows上的共享内存在所有句柄关闭后自动释放，没有需要清理的残留
+pub fn cleanup(_instance: &str) -> usize {
+    0
+}
+
+/// ## 共享内存名
+///
+/// 在共享内存名前加上vcore实例标识，使同一主机上运行的多个vcore实例互不干扰。
+///
+/// 实例标识为空时返回原名。
+pub fn shared_name(instance: &str, name: &str) -> String {
+    if instance.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", instance, name)
+    }
+}
+
+/// 实例标识的最大字节数，加上最长的共享内存名后仍不超过共享内存名的长度限制（255字节）
+pub const MAX_INSTANCE_LEN: usize = 200;
+
+/// ## 检查实例标识
+///
+/// 实例标识是共享内存名的一部分，不能包含`/`或NUL，也不能超过`MAX_INSTANCE_LEN`字节
+pub fn check_instance(instance: &str) -> Result<(), String> {
+    if instance.contains(['/', '\0']) {
+        Err(format!(
+            "实例标识\"{}\"不能包含'/'或NUL",
+            instance.escape_debug()
+        ))
+    } else if instance.len() > MAX_INSTANCE_LEN {
+        Err(format!("实例标识不能超过{}字节", MAX_INSTANCE_LEN))
+    } else {
+        Ok(())
+    }
+}
+
 impl<T> SharedPointer<T> {
+    /// ## 创建共享内存
+    ///
+    /// 与`new`相同，但大小为0时不panic，失败时返回带有共享内存名的错误信息。
+    /// 共享内存已经存在时同样返回错误
+    pub fn create(name: String, size: usize) -> Result<Self, String> {
+        if size == 0 {
+            return Err(format!("共享内存{}的大小为0", name));
+        }
+        Self::new(name.clone(), size).map_err(|err| match err {
+            #[cfg(target_os = "linux")]
+            Errno::EEXIST => format!(
+                "共享内存{}已经存在，同一实例正在运行或者需要用--cleanup删除之前的残留",
+                name
+            ),
+            err => format!("无法创建共享内存{}: {:?}", name, err),
+        })
+    }
+
+    /// ## 连接共享内存
+    ///
+    /// 与`bind`相同，但大小为0时不panic，失败时返回带有共享内存名的错误信息
+    pub fn open(name: String, size: usize) -> Result<Self, String> {
+        if size == 0 {
+            return Err(format!("共享内存{}的大小为0", name));
+        }
+        Self::bind(name.clone(), size).map_err(|err| format!("无法连接共享内存{}: {:?}", name, err))
+    }
+
     #[inline]
     pub fn size(&self) -> usize {
         self.size
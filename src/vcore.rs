@@ -1,13 +1,21 @@
-#[cfg(feature = "debugger")]
-use crate::debugger::debug::DebuggerBackend;
 use core::panic;
 #[cfg(target_os = "linux")]
-use nix::unistd;
+use nix::{
+    libc,
+    sys::{
+        signal::{self, SigSet, Signal},
+        wait,
+    },
+    unistd,
+};
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
 use std::{
     fs::File,
     io::Read,
     process::exit,
     sync::{
+        atomic::AtomicBool,
         mpsc::{self, Receiver},
         Arc, RwLock,
     },
@@ -25,17 +33,24 @@ use winapi::um::{
 
 use crate::config::Config;
 #[cfg(feature = "debugger")]
-use crate::debugger::debug::{Debugger, VdbApi};
+use crate::debugger::{
+    debug::{Debugger, VdbApi},
+    gdb::GdbStub,
+};
+use crate::machine::{
+    event::{Recorder, TimedEvent},
+    snapshot::Snapshot,
+    trace::Tracer,
+    CoreRunner, Machine,
+};
 use crate::utils::{
-    clock::Clock,
     memory::Memory,
-    shared::{Addressable, SharedPointer},
+    shared::{self, shared_name, Addressable, SharedPointer},
 };
+use crate::vrisc::asm::assemble_file;
 use crate::vrisc::vcore::{
     dma::DirectMemoryAccess,
-    intcontroller::InterruptId,
     iocontroller::{IOController, IOPortBuffer, PortRequest},
-    Vcore,
 };
 
 /// # vcore从这里开始运行
@@ -54,10 +69,22 @@ use crate::vrisc::vcore::{
 /// 这是一个vcore核心进程以及传递此核心的core id。
 /// 在windows平台上会首先检测此参数是否存在并跳至vcore运行，
 /// 对于主进程，使用winapi创建进程用于vcore核心。
+///
+/// ## 退出
+///
+/// debugger退出、有核心进程退出、收到SIGINT/SIGTERM/SIGHUP或主进程panic时，
+/// 结束所有核心进程并删除所有共享内存。
+/// 使用`--cleanup`参数可以删除之前崩溃的实例残留的共享内存。
 pub fn run(config: Config) {
+    if config.cleanup {
+        let count = shared::cleanup(&config.instance);
+        println!("已删除{}个残留的共享内存", count);
+        return;
+    }
     #[cfg(target_os = "windows")]
     if config.process_child {
         vcore(
+            &config.instance,
             config.memory,
             config.id_core,
             config.cores,
@@ -66,7 +93,20 @@ pub fn run(config: Config) {
         );
         exit(0);
     }
-    let mut cores = Vec::new();
+    #[cfg(target_os = "linux")]
+    let cores = Arc::new(Mutex::new(Vec::new()));
+    #[cfg(target_os = "linux")]
+    install_teardown(Arc::clone(&cores));
+    if config.deterministic
+        || config.replay.is_some()
+        || config.record.is_some()
+        || config.trace.is_some()
+        || config.restore.is_some()
+        || config.snapshot.is_some()
+    {
+        run_machine(&config);
+        return;
+    }
 
     let mut cores_startflg = Vec::new();
     let mut cores_inst_count = Vec::new();
@@ -79,7 +119,11 @@ pub fn run(config: Config) {
         ioreq_delivers.push(tx);
         ioreq_receivers.push(rx);
     }
-    let io_controller = IOController::new(ioreq_delivers);
+    let io_controller = match IOController::new(&config.instance, ioreq_delivers) {
+        Ok(io_controller) => io_controller,
+        Err(err) => panic!("{}", err),
+    };
+    let ipi_delivers = io_controller.port_deliver.clone();
     let io_controller = Arc::new(RwLock::new(io_controller));
     let solid_io_ports = {
         let mut p = Vec::new();
@@ -90,8 +134,11 @@ pub fn run(config: Config) {
         for cp in p.iter_mut() {
             for i in 0..256 {
                 cp.push(
-                    SharedPointer::<IOPortBuffer>::new(format!("VcoreIOPort{}C{}", i, c), 1)
-                        .unwrap(),
+                    SharedPointer::<IOPortBuffer>::new(
+                        shared_name(&config.instance, &format!("VcoreIOPort{}C{}", i, c)),
+                        1,
+                    )
+                    .unwrap(),
                 );
             }
             c += 1;
@@ -101,36 +148,44 @@ pub fn run(config: Config) {
     let solid_io_ports = Arc::new(RwLock::new(solid_io_ports));
 
     // 初始化dma
-    let dma_controller = DirectMemoryAccess::new();
+    let dma_controller = DirectMemoryAccess::new(&config.instance);
     let dma_controller = Arc::new(RwLock::new(dma_controller));
 
     #[cfg(feature = "debugger")]
     let mut cores_debug_port = Vec::new();
 
-    let mut memory = Memory::new(config.memory);
-    {
-        // 加载vrom
-        let rom = match File::open(config.vrom) {
-            Ok(rom) => rom,
-            Err(err) => panic!("{}", err),
-        };
-        let rom_: Vec<_> = rom.bytes().collect();
-        let mut rom = Vec::new();
-        for x in rom_ {
-            rom.push(x.unwrap());
-        }
-        memory.borrow_mut().write_slice(0, rom.as_slice());
-    }
+    let mut memory = match Memory::new(&config.instance, config.memory) {
+        Ok(memory) => memory,
+        Err(err) => panic!("{}", err),
+    };
+    memory
+        .borrow_mut()
+        .write_slice(0, boot_image(&config).as_slice());
 
     let mut ioreq_receivers = ioreq_receivers.into_iter();
     for i in 0..config.cores {
-        cores_startflg
-            .push(SharedPointer::<(bool, u64)>::new(format!("VcoreCore{}StartFlg", i), 1).unwrap());
-        cores_inst_count
-            .push(SharedPointer::<u64>::new(format!("VcoreCore{}InstCount", i), 1).unwrap());
+        cores_startflg.push(
+            SharedPointer::<(bool, u64)>::new(
+                shared_name(&config.instance, &format!("VcoreCore{}StartFlg", i)),
+                1,
+            )
+            .unwrap(),
+        );
+        cores_inst_count.push(
+            SharedPointer::<u64>::new(
+                shared_name(&config.instance, &format!("VcoreCore{}InstCount", i)),
+                1,
+            )
+            .unwrap(),
+        );
         #[cfg(feature = "debugger")]
-        cores_debug_port
-            .push(SharedPointer::<VdbApi>::new(format!("VcoreCore{}DebugApi", i), 1).unwrap());
+        cores_debug_port.push(
+            SharedPointer::<VdbApi>::new(
+                shared_name(&config.instance, &format!("VcoreCore{}DebugApi", i)),
+                1,
+            )
+            .unwrap(),
+        );
         #[cfg(feature = "debugger")]
         cores_debug_port[i].write(0, VdbApi::None);
 
@@ -142,9 +197,12 @@ pub fn run(config: Config) {
         }
         #[cfg(target_os = "linux")]
         match { unsafe { unistd::fork().unwrap() } } {
-            unistd::ForkResult::Parent { child } => cores.push(child),
+            unistd::ForkResult::Parent { child } => cores.lock().unwrap().push(child),
             unistd::ForkResult::Child => {
+                // 主进程意外死亡时核心进程随之结束
+                unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
                 vcore(
+                    &config.instance,
                     config.memory,
                     i,
                     config.cores,
@@ -171,142 +229,246 @@ pub fn run(config: Config) {
             solid_io_ports.write().unwrap().as_mut(),
             cores_startflg,
             dma_controller,
+            ipi_delivers,
         );
     });
 
+    #[cfg(feature = "debugger")]
+    if let Some(address) = &config.gdb {
+        let mut stub = GdbStub::new(&config.instance, config.memory, &mut cores_debug_port);
+        if let Err(err) = stub.serve(address) {
+            eprintln!("gdb: {}", err);
+        }
+        #[cfg(target_os = "linux")]
+        teardown(&cores);
+        return;
+    }
     #[cfg(feature = "debugger")]
     let mut debugger = if config.debug {
-        Debugger::new(config.memory, &mut cores_debug_port)
+        Debugger::new(&config.instance, config.memory, &mut cores_debug_port)
     } else {
-        Debugger::none(&mut cores_debug_port)
+        Debugger::none(&config.instance, &mut cores_debug_port)
     };
-    let mut running = true;
-    while running {
+    loop {
         thread::sleep(Duration::from_millis(1));
         #[cfg(feature = "debugger")]
-        if config.debug {
-            running = debugger.run();
+        if config.debug && !debugger.run() {
+            break;
+        }
+        #[cfg(target_os = "linux")]
+        if core_exited(&cores) {
+            break;
         }
     }
+    #[cfg(target_os = "linux")]
+    teardown(&cores);
 }
 
-/// ## vcore核心函数
-///
-/// 首先绑定内存和与主进程通信的共享内存，然后等待核心被打开，最后进入核心主循环。
+#[cfg(target_os = "linux")]
+/// ## 是否有核心进程已经退出
 ///
-/// 由于debugger的存在，执行一条指令的过程并没有在这里完全体现出来。
-fn vcore(
-    memory_size: usize,
-    id: usize,
-    total_core: usize,
-    debug: bool,
-    external_clock: bool,
-    ioreq_receiver: Receiver<PortRequest>,
-) {
-    let mut core_startflg =
-        SharedPointer::<(bool, u64)>::bind(format!("VcoreCore{}StartFlg", id), 1).unwrap();
-    // 指令计数，计算从a开始运行到现在此核心共运行了多少条指令
-    // 用于vcore父进程统计执行速度等
-    let mut core_instruction_count =
-        SharedPointer::<u64>::bind(format!("VcoreCore{}InstCount", id), 1).unwrap();
-    core_instruction_count.write(0, 0);
-    // vcore核心
-    let mut core = Vcore::new(id, total_core, Memory::bind(memory_size));
-    core.init();
-    #[cfg(feature = "debugger")]
-    // vcore debugger后端
-    let mut debugger_backend = DebuggerBackend::new(id);
-
-    while !core_startflg.0 {
-        // 等待核心被允许开始
-        thread::sleep(Duration::from_millis(1));
+/// 回收已退出的核心进程。核心进程只在debugger要求退出或panic时退出，
+/// 此时虚拟机无法继续运行，由`teardown`结束其它核心进程并删除共享内存
+fn core_exited(cores: &Mutex<Vec<unistd::Pid>>) -> bool {
+    let mut cores = cores.lock().unwrap();
+    let total = cores.len();
+    cores.retain(|core| {
+        matches!(
+            wait::waitpid(*core, Some(wait::WaitPidFlag::WNOHANG)),
+            Ok(wait::WaitStatus::StillAlive)
+        )
+    });
+    cores.len() != total
+}
 
-        #[cfg(feature = "debugger")]
-        if debug {
-            match debugger_backend.before_start(&mut core_startflg, &mut core.debug_mode) {
-                Some(true) => {
-                    continue;
-                }
-                None => {
-                    break;
-                }
-                _ => (),
-            };
-        }
+/// 启动镜像，指定了`--asm`时为汇编的结果，否则为vrom
+fn boot_image(config: &Config) -> Vec<u8> {
+    match &config.asm {
+        Some(path) => match assemble_file(path) {
+            Ok(image) => image,
+            Err(err) => panic!("{}", err),
+        },
+        None => read_vrom(&config.vrom),
     }
+}
 
-    // 内部时钟 (250Hz)
-    let mut clock = Clock::new(Duration::from_millis(4));
-
-    core.regs.ip = core_startflg.1;
+/// 读取vrom
+fn read_vrom(path: &str) -> Vec<u8> {
+    let mut rom = Vec::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut rom)) {
+        panic!("{}", err);
+    }
+    rom
+}
 
-    loop {
-        match ioreq_receiver.try_recv() {
-            Ok(port) => match port {
-                PortRequest::Link(port) => core.link_device(port),
-                PortRequest::Interrupt(port) => {
-                    core.intctler.interrupt(InterruptId::DeviceCommunication);
-                    core.regs.imsg = port as u64;
-                }
-            },
-            _ => (),
+/// ## 在当前线程中运行
+///
+/// 不创建核心进程，所有核心由`Machine`在当前线程中以固定顺序轮流运行。
+///
+/// * 确定性模式下定时器使用虚拟时间，设备输入只来自`--input`指定的定时事件文件，
+///   因此相同的vrom和事件文件总是得到完全相同的运行结果。
+///   使用`--replay`时重放记录的事件，时钟中断也只来自记录的事件。
+/// * 否则使用真实时间并接受外部设备，使用`--record`时运行中观察到的所有非确定性事件写入文件，
+///   之后可以用`--replay`重放。
+///
+/// 使用`--trace`时把执行的每条指令写入跟踪文件。
+///
+/// 使用`--restore`时从快照而不是vrom开始运行，
+/// 使用`--snapshot`时在所有核心共执行`--snapshot-after`条指令后保存快照。
+fn run_machine(config: &Config) {
+    let machine = match &config.restore {
+        Some(path) => {
+            Snapshot::load(path).and_then(|snapshot| Machine::restore(&config.instance, &snapshot))
         }
-
-        // 执行时钟
-        if !debug && !external_clock && clock.hit() {
-            core.intctler.interrupt(InterruptId::Clock);
+        None => Machine::new(
+            &config.instance,
+            config.memory,
+            config.cores,
+            config.external_clock,
+        )
+        .map(|mut machine| {
+            machine.load(0, &boot_image(config));
+            machine
+        }),
+    };
+    let mut machine = match machine {
+        Ok(machine) => machine,
+        Err(err) => panic!("{}", err),
+    };
+    let cores = machine.total_core();
+    if config.deterministic {
+        machine.set_deterministic(true);
+    }
+    if let Some(input) = &config.input {
+        for event in load_events(input, cores) {
+            machine.schedule(event);
         }
-        // 检测中断
-        if let Some(intid) = core.intctler.interrupted() {
-            core.interrupt_jump(intid);
+    }
+    if let Some(replay) = &config.replay {
+        machine.replay(load_events(replay, cores));
+    }
+    if let Some(record) = &config.record {
+        match Recorder::create(record) {
+            Ok(recorder) => machine.set_recorder(recorder),
+            Err(err) => panic!("{}: {}", record, err),
         }
-        // 指令寻址，更新hot_ip
-        if core.flush_lazy_address_system(debug) {
-            continue;
+    }
+    if let Some(trace) = &config.trace {
+        match Tracer::create(trace, config.trace_binary) {
+            Ok(tracer) => machine.set_tracer(tracer),
+            Err(err) => panic!("{}: {}", trace, err),
         }
-        // debugger后端
-        #[cfg(feature = "debugger")]
-        if debug {
-            match debugger_backend.after_start(
-                core.lazyaddr.hot_ip,
-                &mut core.regs,
-                &mut core.intctler,
-                &mut core.debug_mode,
-                &mut core.memory,
-            ) {
-                Some(true) => {
-                    continue;
-                }
-                None => {
-                    break;
+    }
+    let mut snapshot = config.snapshot.as_ref();
+    let mut executed = 0;
+    loop {
+        executed += match machine.step() {
+            Ok(executed) => executed,
+            Err(err) => panic!("{}", err),
+        };
+        if let Some(path) = snapshot {
+            if executed >= config.snapshot_after {
+                if let Err(err) = machine.snapshot().save(path) {
+                    panic!("{}", err);
                 }
-                _ => (),
-            };
+                println!("已保存快照到{}", path);
+                snapshot = None;
+            }
         }
-        // nop指令状态下，在此停止
-        if core.nopflag {
-            thread::sleep(Duration::from_micros(1));
-            continue;
+    }
+}
+
+/// 读取事件文件并检查事件中的核心是否存在
+fn load_events(path: &str, cores: usize) -> Vec<TimedEvent> {
+    let events = match TimedEvent::load(path) {
+        Ok(events) => events,
+        Err(err) => panic!("{}", err),
+    };
+    for event in events.iter() {
+        if event.core >= cores {
+            panic!("事件\"{}\"中的核心不存在", event);
+        }
+    }
+    events
+}
+
+#[cfg(target_os = "linux")]
+/// ## 结束所有核心进程并删除本进程创建的共享内存
+fn teardown(cores: &Mutex<Vec<unistd::Pid>>) {
+    if let Ok(cores) = cores.try_lock() {
+        for core in cores.iter() {
+            let _ = signal::kill(*core, Signal::SIGKILL);
+            let _ = wait::waitpid(*core, None);
         }
-        /* 取指令 */
-        let opcode = *core.memory.borrow().at(core.lazyaddr.hot_ip);
-        // opcode=0x3d,0x3e分别是initext和destext指令
-        // 目前不支持
-        // 这两个指令依然会产生InvalidInstruction
-        // TODO
-        // 添加指令执行内容需在base.rs中实现，并加入到指令空间中
-        if let None = core.instruction_space[opcode as usize] {
-            core.intctler.interrupt(InterruptId::InvalidInstruction);
-            continue;
+    }
+    shared::unlink_all();
+    #[cfg(feature = "debugger")]
+    let _ = crossterm::terminal::disable_raw_mode();
+}
+
+#[cfg(target_os = "linux")]
+/// ## 注册信号处理和panic钩子
+///
+/// 在主进程中屏蔽SIGINT、SIGTERM和SIGHUP，由一个单独的线程等待这些信号并清理，
+/// 必须在创建任何线程和核心进程之前调用，使屏蔽对所有线程生效。
+///
+/// 核心进程继承信号屏蔽，由主进程负责结束。
+fn install_teardown(cores: Arc<Mutex<Vec<unistd::Pid>>>) {
+    let mut sigset = SigSet::empty();
+    sigset.add(Signal::SIGINT);
+    sigset.add(Signal::SIGTERM);
+    sigset.add(Signal::SIGHUP);
+    sigset.thread_block().unwrap();
+    let sigcores = Arc::clone(&cores);
+    thread::spawn(move || {
+        if let Ok(sig) = sigset.wait() {
+            teardown(&sigcores);
+            exit(128 + sig as i32);
         }
-        let instlen = core.instruction_space[opcode as usize].unwrap().1;
-        let (inst, cont) = core.read_instruction(instlen);
-        if cont {
-            continue;
+    });
+
+    let pid = unistd::getpid();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // 核心进程panic时只结束自己
+        if unistd::getpid() == pid {
+            teardown(&cores);
+            exit(101);
         }
-        /* 执行指令 */
-        core.execute_instruction(opcode, inst.as_slice());
-        // 更新指令计数
-        *core_instruction_count += 1;
+    }));
+}
+
+/// ## vcore核心函数
+///
+/// 首先绑定内存和与主进程通信的共享内存，然后等待核心被打开，最后进入核心主循环。
+///
+/// 核心主循环的每一轮见`CoreRunner::step`。
+fn vcore(
+    instance: &str,
+    memory_size: usize,
+    id: usize,
+    total_core: usize,
+    debug: bool,
+    external_clock: bool,
+    ioreq_receiver: Receiver<PortRequest>,
+) {
+    let runner = Memory::bind(instance, memory_size).and_then(|memory| {
+        CoreRunner::new(
+            instance,
+            memory,
+            id,
+            total_core,
+            debug,
+            external_clock,
+            ioreq_receiver,
+        )
+    });
+    let mut runner = match runner {
+        Ok(runner) => runner,
+        Err(err) => panic!("{}", err),
+    };
+    if let Err(err) = runner.run_until(&AtomicBool::new(false)) {
+        panic!("{}", err);
     }
 }
@@ -1,2 +1,10 @@
+pub mod asm;
+pub mod atomic;
 pub mod base;
+pub mod disasm;
+pub mod extension;
+pub mod float;
+pub mod listing;
+pub mod math;
+pub mod string;
 pub mod vcore;
@@ -0,0 +1,655 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::utils::rdxparse::RadixParse;
+
+use super::{
+    base::BASE,
+    disasm::{BranchMode, Operation, Port, MNEMONICS, SIZES},
+    vcore::regs_flags::{ConditionCode, SP},
+};
+
+/// 镜像的最大大小，防止`.org`写错时占用过多内存
+pub const MAX_IMAGE: u64 = 1 << 28;
+
+/// ## 汇编
+///
+/// 把汇编源程序转换为从地址0开始的镜像，指令的写法与`disasm`的输出相同，如：
+///
+/// ```text
+/// ; 注释
+/// .equ count, 10
+/// start:
+///     ldi.q x1, count         ; 省略大小后缀时为.q
+///     call sum
+///     jc.e abs64 start        ; 转移指令默认使用32位相对寻址
+/// sum: add.q x2, x2, x1
+///     loop x1, sum
+///     ret
+/// .org 0x100
+/// message: .asciz "hello\n"
+/// ```
+///
+/// * 标签为`名字:`，可以与指令写在同一行，`.equ 名字, 表达式`定义常量
+/// * 伪指令：`.org`（只能向后移动，中间以0填充）、`.byte`、`.word`、`.dword`、`.qword`、
+///   `.ascii`、`.asciz`、`.zero <字节数>`、`.align <对齐>`
+/// * 表达式支持十、十六（`0x`）、八（`0o`）、二（`0b`）进制数，字符`'a'`，标签和常量，
+///   当前地址`$`，运算符与C相同：`~ - + * / % << >> & ^ |`和括号，使用64位无符号回绕运算
+/// * 寄存器为`x0`-`x15`，`sp`是`x15`
+///
+/// 出错时返回带行号的错误信息
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new();
+    assembler.pass(source)?;
+    assembler.final_pass = true;
+    assembler.pass(source)?;
+    Ok(assembler.image)
+}
+
+/// 汇编文件
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(path.as_ref())
+        .map_err(|err| format!("{}: {}", path.as_ref().display(), err))?;
+    assemble(&source).map_err(|err| format!("{}: {}", path.as_ref().display(), err))
+}
+
+struct Assembler {
+    symbols: HashMap<String, u64>,
+    /// 当前地址
+    address: u64,
+    image: Vec<u8>,
+    /// 第一遍只确定标签的地址，第二遍生成镜像
+    final_pass: bool,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+            address: 0,
+            image: Vec::new(),
+            final_pass: false,
+        }
+    }
+
+    fn pass(&mut self, source: &str) -> Result<(), String> {
+        self.address = 0;
+        for (i, line) in source.lines().enumerate() {
+            self.line(line)
+                .map_err(|err| format!("第{}行: {}: \"{}\"", i + 1, err, line.trim()))?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut line = strip_comment(line).trim();
+        while let Some((label, rest)) = line.split_once(':') {
+            if !is_identifier(label.trim()) {
+                break;
+            }
+            self.define(label.trim(), self.address)?;
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (mnemonic, operands) = match line.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, split_operands(operands.trim())?),
+            None => (line, Vec::new()),
+        };
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        if mnemonic.starts_with('.') {
+            self.directive(&mnemonic, &operands)
+        } else {
+            let operation = self.instruction(&mnemonic, &operands)?;
+            let bytes = match operation.encode(self.address) {
+                Some(bytes) => bytes,
+                // 第一遍中名字可能还未定义，只需要指令的长度
+                None if !self.final_pass => vec![0; length(&operation)],
+                None => return Err("操作数超出范围".to_string()),
+            };
+            self.emit(&bytes)
+        }
+    }
+
+    /// 定义标签或常量，第一遍时检查是否重复
+    fn define(&mut self, name: &str, value: u64) -> Result<(), String> {
+        if !self.final_pass && self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(format!("\"{}\"重复定义", name));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let end = self.address + bytes.len() as u64;
+        if end > MAX_IMAGE {
+            return Err(format!("镜像超过{:#x}字节", MAX_IMAGE));
+        }
+        if self.final_pass {
+            if self.image.len() < end as usize {
+                self.image.resize(end as usize, 0);
+            }
+            self.image[self.address as usize..end as usize].copy_from_slice(bytes);
+        }
+        self.address = end;
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, operands: &[&str]) -> Result<(), String> {
+        let count = |n: usize| {
+            if operands.len() == n {
+                Ok(())
+            } else {
+                Err(format!("{}需要{}个操作数", directive, n))
+            }
+        };
+        match directive {
+            ".org" => {
+                count(1)?;
+                let address = self.eval_now(operands[0])?;
+                if address < self.address {
+                    return Err(format!("不能向回移动到{:#x}", address));
+                }
+                self.emit(&vec![0; (address - self.address).min(MAX_IMAGE) as usize])
+            }
+            ".byte" | ".word" | ".dword" | ".qword" => {
+                let width = match directive {
+                    ".byte" => 1,
+                    ".word" => 2,
+                    ".dword" => 4,
+                    _ => 8,
+                };
+                for operand in operands {
+                    let value = self.eval(operand)?;
+                    if !fits(value, width) {
+                        return Err(format!("\"{}\"超出{}字节", operand, width));
+                    }
+                    self.emit(&value.to_le_bytes()[..width])?;
+                }
+                Ok(())
+            }
+            ".ascii" | ".asciz" => {
+                count(1)?;
+                let mut bytes = parse_string(operands[0])?;
+                if directive == ".asciz" {
+                    bytes.push(0);
+                }
+                self.emit(&bytes)
+            }
+            ".zero" => {
+                count(1)?;
+                let length = self.eval_now(operands[0])?;
+                self.emit(&vec![0; length.min(MAX_IMAGE) as usize])
+            }
+            ".align" => {
+                count(1)?;
+                let align = self.eval_now(operands[0])?;
+                if align == 0 {
+                    return Err("对齐不能为0".to_string());
+                }
+                let padding = (align - self.address % align) % align;
+                self.emit(&vec![0; padding.min(MAX_IMAGE) as usize])
+            }
+            ".equ" => {
+                count(2)?;
+                if !is_identifier(operands[0]) {
+                    return Err(format!("\"{}\"不是合法的名字", operands[0]));
+                }
+                let value = self.eval_now(operands[1])?;
+                self.define(operands[0], value)
+            }
+            _ => Err(format!("未知伪指令\"{}\"", directive)),
+        }
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[&str]) -> Result<Operation, String> {
+        let (name, suffix) = match mnemonic.split_once('.') {
+            Some((name, suffix)) => (name, Some(suffix)),
+            None => (mnemonic, None),
+        };
+        let opcode = MNEMONICS
+            .iter()
+            .position(|m| *m == Some(name))
+            .ok_or_else(|| format!("未知指令\"{}\"", name))? as u8;
+        if operands.len() != operand_count(opcode) {
+            return Err(format!("{}需要{}个操作数", name, operand_count(opcode)));
+        }
+        let size = || match suffix {
+            None => Ok(3),
+            Some(suffix) => SIZES
+                .iter()
+                .position(|s| *s == suffix)
+                .map(|size| size as u8)
+                .ok_or_else(|| format!("未知的大小\"{}\"", suffix)),
+        };
+        let no_suffix = || match suffix {
+            None => Ok(()),
+            Some(suffix) => Err(format!("{}没有后缀\"{}\"", name, suffix)),
+        };
+        let reg = |i: usize| register(operands[i]);
+        let operation = match opcode {
+            0x01 | 0x02 | 0x0a | 0x0b | 0x0d => Operation::Arith {
+                opcode,
+                size: size()?,
+                dest: reg(0)?,
+                a: reg(1)?,
+                b: reg(2)?,
+            },
+            0x03 | 0x04 => Operation::Unary {
+                opcode,
+                size: size()?,
+                reg: reg(0)?,
+            },
+            0x05..=0x08 => Operation::Shift {
+                opcode,
+                size: size()?,
+                reg: reg(0)?,
+                bits: reg(1)?,
+            },
+            0x09 | 0x0c => {
+                no_suffix()?;
+                if opcode == 0x09 {
+                    Operation::Cmp {
+                        a: reg(0)?,
+                        b: reg(1)?,
+                    }
+                } else {
+                    Operation::Not {
+                        dest: reg(0)?,
+                        src: reg(1)?,
+                    }
+                }
+            }
+            0x10 | 0x11 | 0x17 => {
+                let cond = match suffix {
+                    None => 0,
+                    Some(suffix) => ConditionCode::parse(suffix)
+                        .filter(|&cond| cond != 0)
+                        .ok_or_else(|| format!("未知的条件\"{}\"", suffix))?,
+                };
+                let (mode, target) = match operands[0].split_once(char::is_whitespace) {
+                    Some((mode, target)) => (
+                        BranchMode::parse(mode)
+                            .ok_or_else(|| format!("未知的寻址方式\"{}\"", mode))?,
+                        target,
+                    ),
+                    None => (BranchMode::Rel32, operands[0]),
+                };
+                Operation::Branch {
+                    opcode,
+                    cond,
+                    mode,
+                    target: self.eval(target)?,
+                }
+            }
+            0x13 => {
+                no_suffix()?;
+                Operation::Loop {
+                    reg: reg(0)?,
+                    target: self.eval(operands[1])?,
+                }
+            }
+            0x14 => {
+                no_suffix()?;
+                Operation::Ir(self.byte(operands[0])?)
+            }
+            0x20 => Operation::Ldi {
+                size: size()?,
+                reg: reg(0)?,
+                imm: self.eval(operands[1])?,
+            },
+            0x21 => Operation::Ldm {
+                size: size()?,
+                dest: reg(0)?,
+                addr: memory(operands[1])?,
+            },
+            0x22 => Operation::Stm {
+                size: size()?,
+                addr: memory(operands[0])?,
+                src: reg(1)?,
+            },
+            0x23 | 0x24 => {
+                no_suffix()?;
+                let (reg, port) = if opcode == 0x23 {
+                    (reg(0)?, operands[1])
+                } else {
+                    (reg(1)?, operands[0])
+                };
+                let port = match register(port) {
+                    Ok(port) => Port::Register(port),
+                    Err(_) => {
+                        let value = self.eval(port)?;
+                        if value > u16::MAX as u64 {
+                            return Err(format!("端口\"{}\"超出范围", port));
+                        }
+                        Port::Immediate(value as u16)
+                    }
+                };
+                Operation::Io { opcode, reg, port }
+            }
+            0x25 | 0x26 | 0x34..=0x3b => {
+                no_suffix()?;
+                Operation::Register {
+                    opcode,
+                    reg: reg(0)?,
+                }
+            }
+            0x3d | 0x3e => {
+                no_suffix()?;
+                Operation::Extension {
+                    opcode,
+                    id: self.byte(operands[0])?,
+                }
+            }
+            0x3f => Operation::Imask {
+                store: match suffix {
+                    Some("get") => true,
+                    Some("set") => false,
+                    _ => return Err("imask需要后缀.get或.set".to_string()),
+                },
+                reg: reg(0)?,
+            },
+            _ => {
+                no_suffix()?;
+                Operation::Plain(opcode)
+            }
+        };
+        Ok(operation)
+    }
+
+    /// 计算表达式，第一遍时未定义的名字为0
+    fn eval(&self, expr: &str) -> Result<u64, String> {
+        Expression::new(expr, self, !self.final_pass)?.parse()
+    }
+
+    /// 计算决定地址的表达式，只能使用已经定义的名字
+    fn eval_now(&self, expr: &str) -> Result<u64, String> {
+        Expression::new(expr, self, false)?.parse()
+    }
+
+    fn byte(&self, expr: &str) -> Result<u8, String> {
+        let value = self.eval(expr)?;
+        u8::try_from(value).map_err(|_| format!("\"{}\"超出1字节", expr))
+    }
+}
+
+/// 指令的长度，只有`ldi`的长度由大小字段决定
+fn length(operation: &Operation) -> usize {
+    match *operation {
+        Operation::Ldi { size, .. } => 2 + (1 << size),
+        _ => BASE[operation.opcode() as usize].unwrap().1 as usize,
+    }
+}
+
+/// 每条指令的操作数个数
+fn operand_count(opcode: u8) -> usize {
+    match opcode {
+        0x01 | 0x02 | 0x0a | 0x0b | 0x0d => 3,
+        0x05..=0x09 | 0x0c | 0x13 | 0x20..=0x24 => 2,
+        0x03 | 0x04 | 0x10 | 0x11 | 0x14 | 0x17 | 0x25 | 0x26 | 0x34..=0x3b | 0x3d..=0x3f => 1,
+        _ => 0,
+    }
+}
+
+fn register(operand: &str) -> Result<u8, String> {
+    let name = operand.to_ascii_lowercase();
+    if name == "sp" {
+        return Ok(SP as u8);
+    }
+    (0..16u8)
+        .find(|n| name == format!("x{}", n))
+        .ok_or_else(|| format!("\"{}\"不是寄存器", operand))
+}
+
+/// `[xN]`
+fn memory(operand: &str) -> Result<u8, String> {
+    operand
+        .strip_prefix('[')
+        .and_then(|operand| operand.strip_suffix(']'))
+        .ok_or_else(|| format!("\"{}\"不是[寄存器]", operand))
+        .and_then(|reg| register(reg.trim()))
+}
+
+/// 值能用`width`字节的无符号数或有符号数表示
+fn fits(value: u64, width: usize) -> bool {
+    let bits = 8 * width as u32;
+    bits >= 64 || value >> bits == 0 || (value as i64) >> (bits - 1) == -1
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// 去掉`;`之后的注释，忽略字符串和字符中的`;`
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ';' => return &line[..i],
+            None => (),
+        }
+    }
+    line
+}
+
+/// 按逗号分割操作数，忽略字符串、字符和括号中的逗号
+fn split_operands(operands: &str) -> Result<Vec<&str>, String> {
+    if operands.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in operands.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                ',' if depth == 0 => {
+                    result.push(operands[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            },
+        }
+    }
+    result.push(operands[start..].trim());
+    if result.iter().any(|operand| operand.is_empty()) {
+        return Err("缺少操作数".to_string());
+    }
+    Ok(result)
+}
+
+/// 解析字符串或字符中的转义：`\n`、`\t`、`\r`、`\0`、`\\`、`\"`、`\'`、`\xNN`
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buf).bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some(c @ ('\\' | '"' | '\'')) => c as u8,
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16).map_err(|_| format!("\"\\x{}\"不是字节", hex))?
+            }
+            c => return Err(format!("未知的转义\"\\{}\"", c.unwrap_or(' '))),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+fn parse_string(operand: &str) -> Result<Vec<u8>, String> {
+    operand
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("{}不是字符串", operand))
+        .and_then(unescape)
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Token {
+    Number(u64),
+    Name(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: [&str; 14] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "$",
+];
+
+/// 二元运算符的优先级，从低到高
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// ## 表达式
+struct Expression<'a> {
+    tokens: Vec<Token>,
+    next: usize,
+    assembler: &'a Assembler,
+    /// 未定义的名字为0，而不是出错
+    lenient: bool,
+}
+
+impl<'a> Expression<'a> {
+    fn new(expr: &str, assembler: &'a Assembler, lenient: bool) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut rest = expr.trim();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .to_string()
+                    .rdxparse()
+                    .map_err(|_| format!("\"{}\"不是数字", &rest[..len]))?;
+                tokens.push(Token::Number(number));
+                len
+            } else if c == '\'' {
+                let end = rest[1..]
+                    .find('\'')
+                    .filter(|&end| end > 0)
+                    .ok_or_else(|| format!("字符没有结束: {}", rest))?;
+                let bytes = unescape(&rest[1..end + 1])?;
+                if bytes.len() != 1 {
+                    return Err(format!("\"{}\"不是单字节字符", &rest[..end + 2]));
+                }
+                tokens.push(Token::Number(bytes[0] as u64));
+                end + 2
+            } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len());
+                tokens.push(Token::Name(rest[..len].to_string()));
+                len
+            } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                tokens.push(Token::Operator(op));
+                op.len()
+            } else {
+                return Err(format!("无法识别\"{}\"", c));
+            };
+            rest = rest[len..].trim_start();
+        }
+        Ok(Self {
+            tokens,
+            next: 0,
+            assembler,
+            lenient,
+        })
+    }
+
+    fn parse(&mut self) -> Result<u64, String> {
+        let value = self.binary(0)?;
+        match self.tokens.get(self.next) {
+            None => Ok(value),
+            Some(token) => Err(format!("表达式中多余的{:?}", token)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<u64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut value = self.binary(level + 1)?;
+        while let Some(&Token::Operator(op)) = self.tokens.get(self.next) {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.next += 1;
+            let rhs = self.binary(level + 1)?;
+            value = match op {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.checked_shl(rhs as u32).unwrap_or(0),
+                ">>" => value.checked_shr(rhs as u32).unwrap_or(0),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("除数为0".to_string()),
+                "/" => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<u64, String> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Name(name)) => match self.assembler.symbols.get(&name) {
+                Some(&value) => Ok(value),
+                None if self.lenient => Ok(0),
+                None => Err(format!("\"{}\"未定义", name)),
+            },
+            Some(Token::Operator("$")) => Ok(self.assembler.address),
+            Some(Token::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Operator("+")) => self.unary(),
+            Some(Token::Operator("~")) => Ok(!self.unary()?),
+            Some(Token::Operator("(")) => {
+                let value = self.binary(0)?;
+                if self.tokens.get(self.next) != Some(&Token::Operator(")")) {
+                    return Err("缺少\")\"".to_string());
+                }
+                self.next += 1;
+                Ok(value)
+            }
+            Some(token) => Err(format!("表达式中不应有{:?}", token)),
+            None => Err("表达式不完整".to_string()),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{self, AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use crate::utils::memory::ReadWrite;
+
+use super::vcore::{
+    intcontroller::{Access, InterruptId, Message},
+    regs_flags::FlagRegFlag,
+    BitOptions, Vcore, VcoreInstruction,
+};
+
+/// ## 原子操作扩展指令集
+///
+/// 原子指令直接在共享的主内存映射上进行真正的原子操作，
+/// 不同核心（即使在不同进程中）对同一地址的原子操作互相不会打断。
+///
+/// `cas`、`xadd`和`xchg`为3字节：
+///
+/// `opcode | r2 << 4 | r1 | width << 4 | r3`
+///
+/// r1为内存地址所在的寄存器，width为0、1、2、3时操作数分别是8、16、32、64位，
+/// 地址必须按操作数宽度对齐，未对齐时产生InaccessibleAddress中断。
+///
+/// 所有原子操作都是顺序一致的。
+pub const ATOMIC: [Option<VcoreInstruction>; 4] = [
+    Some((i_cas, 3)),
+    Some((i_xadd, 3)),
+    Some((i_xchg, 3)),
+    Some((i_fence, 1)),
+];
+
+/// ## 原子操作寻址
+///
+/// 以写方式寻址并检查对齐，返回物理地址，寻址失败时产生中断并返回None
+fn address(core: &mut Vcore, addr: u64, width: u8) -> Option<u64> {
+    let bytes = 1u64 << width;
+    if !addr.is_multiple_of(bytes) {
+        core.fault((
+            InterruptId::InaccessibleAddress,
+            Message::Access(addr, Access::Write).encode(),
+        ));
+        return None;
+    }
+    match core.translate(addr, ReadWrite::Write) {
+        Ok(target) if target + bytes <= core.memory.borrow().size() as u64 => Some(target),
+        Ok(_) => {
+            core.fault((
+                InterruptId::InaccessibleAddress,
+                Message::Access(addr, Access::Write).encode(),
+            ));
+            None
+        }
+        Err(err) => {
+            core.fault(err);
+            None
+        }
+    }
+}
+
+/// ## 原子的读-改-写
+///
+/// `f`根据原值返回新值，返回None时不写入。
+///
+/// 写入成功时返回`Ok(原值)`，否则返回`Err(原值)`
+///
+/// 调用者保证`ptr`按`width`对齐且指向主内存映射
+fn update(ptr: *mut u8, width: u8, mut f: impl FnMut(u64) -> Option<u64>) -> Result<u64, u64> {
+    const SC: Ordering = Ordering::SeqCst;
+    unsafe {
+        match width {
+            0 => AtomicU8::from_ptr(ptr)
+                .fetch_update(SC, SC, |v| f(v as u64).map(|n| n as u8))
+                .map(|v| v as u64)
+                .map_err(|v| v as u64),
+            1 => AtomicU16::from_ptr(ptr as *mut u16)
+                .fetch_update(SC, SC, |v| f(v as u64).map(|n| n as u16))
+                .map(|v| v as u64)
+                .map_err(|v| v as u64),
+            2 => AtomicU32::from_ptr(ptr as *mut u32)
+                .fetch_update(SC, SC, |v| f(v as u64).map(|n| n as u32))
+                .map(|v| v as u64)
+                .map_err(|v| v as u64),
+            _ => AtomicU64::from_ptr(ptr as *mut u64).fetch_update(SC, SC, f),
+        }
+    }
+}
+
+/// ## 原子指令的公共部分
+///
+/// 解析宽度和地址，对`x[r2]`和内存进行原子操作，原值写入`x[r2]`
+fn atomic_op(
+    inst: &[u8],
+    core: &mut Vcore,
+    mut f: impl FnMut(u64, u64) -> Option<u64>,
+) -> Option<Result<u64, u64>> {
+    let width = inst[2] >> 4;
+    if width > 3 {
+        core.instruction_fault(InterruptId::InvalidInstruction);
+        return None;
+    }
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let target = address(core, core.regs.x[r1], width)?;
+    let ptr = unsafe { core.memory.borrow().pointer.add(target as usize) };
+    let operand = core.regs.x[r2];
+    let mut new = 0;
+    let res = update(ptr, width, |old| {
+        let res = f(old, operand);
+        new = res.unwrap_or(old);
+        res
+    });
+    let old = match res {
+        Ok(old) | Err(old) => old,
+    };
+    core.regs.x[r2] = old;
+    let old = &old.to_le_bytes()[..1 << width];
+    core.watch_loaded(target, old);
+    if res.is_ok() {
+        core.watch_stored(target, old, &new.to_le_bytes()[..1 << width]);
+    }
+    Some(res)
+}
+
+/// ## 比较并交换
+///
+/// `cas`，若`[x[r1]]`等于`x[r2]`则将`x[r3]`写入`[x[r1]]`并设置Equal标志，否则复位Equal标志；
+/// 内存中的原值写入`x[r2]`
+pub fn i_cas(inst: &[u8], core: &mut Vcore) -> u64 {
+    let new = core.regs.x[(inst[2] & 0x0f) as usize];
+    match atomic_op(
+        inst,
+        core,
+        |old, expected| {
+            if old == expected {
+                Some(new)
+            } else {
+                None
+            }
+        },
+    ) {
+        Some(Ok(_)) => core.regs.flag.bit_set(FlagRegFlag::Equal),
+        Some(Err(_)) => core.regs.flag.bit_reset(FlagRegFlag::Equal),
+        None => return 0,
+    }
+    3
+}
+
+/// ## 原子加
+///
+/// `xadd`，`[x[r1]] += x[r2]`，内存中的原值写入`x[r2]`，忽略r3
+pub fn i_xadd(inst: &[u8], core: &mut Vcore) -> u64 {
+    match atomic_op(inst, core, |old, addend| Some(old.wrapping_add(addend))) {
+        Some(_) => 3,
+        None => 0,
+    }
+}
+
+/// ## 原子交换
+///
+/// `xchg`，交换`[x[r1]]`与`x[r2]`，忽略r3
+pub fn i_xchg(inst: &[u8], core: &mut Vcore) -> u64 {
+    match atomic_op(inst, core, |_, new| Some(new)) {
+        Some(_) => 3,
+        None => 0,
+    }
+}
+
+/// ## 内存屏障
+///
+/// `fence`之前的所有内存访问在之后的内存访问之前完成
+pub fn i_fence(_inst: &[u8], _core: &mut Vcore) -> u64 {
+    atomic::fence(Ordering::SeqCst);
+    1
+}
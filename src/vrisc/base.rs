@@ -1,8 +1,9 @@
 use crate::utils::{memory::ReadWrite, shared::Addressable};
 
+use super::extension;
 use super::vcore::{
     intcontroller::InterruptId,
-    regs_flags::{ConditionCode, FlagRegFlag},
+    regs_flags::{ConditionCode, FlagRegFlag, SP},
     BitOptions, Vcore, VcoreInstruction,
 };
 
@@ -31,8 +32,8 @@ pub const BASE: [Option<VcoreInstruction>; 64] = [
     Some((i_ir, 2)),
     Some((i_sysc, 1)),
     Some((i_sysr, 1)),
-    None,
-    None,
+    Some((i_call, 10)),
+    Some((i_ret, 1)),
     None,
     None,
     None,
@@ -45,8 +46,8 @@ pub const BASE: [Option<VcoreInstruction>; 64] = [
     Some((i_stm, 3)),
     Some((i_in, 4)),
     Some((i_out, 4)),
-    None,
-    None,
+    Some((i_push, 2)),
+    Some((i_pop, 2)),
     None,
     None,
     None,
@@ -69,9 +70,9 @@ pub const BASE: [Option<VcoreInstruction>; 64] = [
     Some((i_sipdump, 2)),
     Some((i_sflagdump, 2)),
     Some((i_cpuid, 1)),
-    None,
-    None,
-    None,
+    Some((i_initext, 2)),
+    Some((i_destext, 2)),
+    Some((i_imask, 2)),
 ];
 
 trait Oprand {
@@ -510,72 +511,78 @@ pub fn i_xor(inst: &[u8], core: &mut Vcore) -> u64 {
     3
 }
 
+/// ## 转移指令的目标地址
+///
+/// `jc`、`cc`和`call`的`inst[1]`低4位为寻址方式，立即数从`inst[2]`开始（小端）：
+///
+/// 寻址方式 | 立即数
+/// :-:|:-
+/// 0、1、2 | 16、32、64位绝对地址
+/// 3、4、5 | 16、32、64位有符号偏移，加上本条指令的地址得到目标地址
+///
+/// 寻址方式不合法时返回None
+fn branch_target(inst: &[u8], ip: u64) -> Option<u64> {
+    let len = match inst[1].lower() {
+        0 | 3 => 2,
+        1 | 4 => 4,
+        2 | 5 => 8,
+        _ => return None,
+    };
+    let mut imm = [0u8; 8];
+    imm[..len].copy_from_slice(&inst[2..2 + len]);
+    let imm = u64::from_le_bytes(imm);
+    Some(match inst[1].lower() {
+        3 => ip.wrapping_add(imm as i16 as u64),
+        4 => ip.wrapping_add(imm as i32 as u64),
+        5 => ip.wrapping_add(imm),
+        _ => imm,
+    })
+}
+
 pub fn i_jc(inst: &[u8], core: &mut Vcore) -> u64 {
     if core
         .regs
         .flag
         .satisfies_condition(ConditionCode::new(inst[1].higher()))
     {
-        match inst[1].lower() {
-            0 => {
-                core.regs.ip = ((inst[2] as u16) | ((inst[3] as u16) << 8)) as u64;
-            }
-            1 => {
-                core.regs.ip = ((inst[2] as u32)
-                    | ((inst[3] as u32) << 8)
-                    | ((inst[4] as u32) << 16)
-                    | ((inst[5] as u32) << 24)) as u64;
+        match branch_target(inst, core.regs.ip) {
+            Some(target) => {
+                core.regs.ip = target;
+                core.transferred = true;
             }
-            2 => {
-                core.regs.ip = ((inst[2] as u64)
-                    | ((inst[3] as u64) << 8)
-                    | ((inst[4] as u64) << 16)
-                    | ((inst[5] as u64) << 24)
-                    | ((inst[6] as u64) << 32)
-                    | ((inst[7] as u64) << 40)
-                    | ((inst[8] as u64) << 48)
-                    | ((inst[9] as u64) << 56)) as u64;
-                println!("{:x}", core.regs.ip);
+            None => {
+                core.instruction_fault(InterruptId::InvalidInstruction);
             }
-            _ => (),
         }
-        core.transferred = true;
+        0
+    } else {
+        10
     }
-    0
 }
 
+/// ## 条件调用
+///
+/// 返回地址（下一条指令的地址）存入ipdump，用`r`返回
 pub fn i_cc(inst: &[u8], core: &mut Vcore) -> u64 {
     if core
         .regs
         .flag
         .satisfies_condition(ConditionCode::new(inst[1].higher()))
     {
-        core.regs.ipdump = core.regs.ip;
-        match inst[1].lower() {
-            0 => {
-                core.regs.ip = ((inst[2] as u16) | ((inst[3] as u16) << 8)) as u64;
+        match branch_target(inst, core.regs.ip) {
+            Some(target) => {
+                core.regs.ipdump = core.regs.ip + 10;
+                core.regs.ip = target;
+                core.transferred = true;
             }
-            1 => {
-                core.regs.ip = ((inst[2] as u32)
-                    | ((inst[3] as u32) << 8)
-                    | ((inst[4] as u32) << 16)
-                    | ((inst[5] as u32) << 24)) as u64;
+            None => {
+                core.instruction_fault(InterruptId::InvalidInstruction);
             }
-            2 => {
-                core.regs.ip = ((inst[2] as u64)
-                    | ((inst[3] as u64) << 8)
-                    | ((inst[4] as u64) << 16)
-                    | ((inst[5] as u64) << 24)
-                    | ((inst[6] as u64) << 32)
-                    | ((inst[7] as u64) << 40)
-                    | ((inst[8] as u64) << 48)
-                    | ((inst[9] as u64) << 56)) as u64;
-            }
-            _ => (),
         }
-        core.transferred = true;
+        0
+    } else {
+        10
     }
-    0
 }
 
 pub fn i_r(_inst: &[u8], core: &mut Vcore) -> u64 {
@@ -584,18 +591,22 @@ pub fn i_r(_inst: &[u8], core: &mut Vcore) -> u64 {
     0
 }
 
+/// ## 循环
+///
+/// `loop <reg> <offset>`，寄存器不为0时跳转。
+///
+/// offset为32位原码表示的偏移（第31位为符号位），加上本条指令的地址得到目标地址
 pub fn i_loop(inst: &[u8], core: &mut Vcore) -> u64 {
-    if core.regs.x[inst[1] as usize] != 0 {
-        let target = (inst[2] as u32)
-            | ((inst[3] as u32) << 8)
-            | ((inst[4] as u32) << 16)
-            | ((inst[5] as u32) << 24);
-        let target = (if (target & (1 << 31)) != 0 {
-            -(target as i32)
+    if core.regs.x[inst[1].lower() as usize] != 0 {
+        let offset = (inst[2] as u64)
+            | ((inst[3] as u64) << 8)
+            | ((inst[4] as u64) << 16)
+            | ((inst[5] as u64) << 24);
+        core.regs.ip = if (offset & (1 << 31)) != 0 {
+            core.regs.ip.wrapping_sub(offset & 0x7fff_ffff)
         } else {
-            target as i32
-        }) as i64 as u64;
-        core.regs.ip = target;
+            core.regs.ip.wrapping_add(offset)
+        };
         core.transferred = true;
         0
     } else {
@@ -613,9 +624,23 @@ pub fn i_ir(inst: &[u8], core: &mut Vcore) -> u64 {
         core.regs.ip = core.regs.ipdump;
         core.regs.flag = core.regs.flagdump;
         core.transferred = true;
+    } else if inst[1] == 2 {
+        // 从中断压栈的状态返回
+        let sp = core.regs.x[SP];
+        match core
+            .read_u64(sp)
+            .and_then(|ip| Ok((ip, core.read_u64(sp.wrapping_add(8))?)))
+        {
+            Ok((ip, flag)) => {
+                core.regs.x[SP] = sp.wrapping_add(16);
+                core.regs.ip = ip;
+                core.regs.flag = flag;
+                core.transferred = true;
+            }
+            Err(err) => core.fault(err),
+        }
     } else {
-        core.intctler.interrupt(InterruptId::InvalidInstruction);
-        core.regs.imsg = core.regs.ip + (core.ip_increment as u64);
+        core.instruction_fault(InterruptId::InvalidInstruction);
     }
     0
 }
@@ -639,6 +664,48 @@ pub fn i_sysr(_inst: &[u8], core: &mut Vcore) -> u64 {
     1
 }
 
+/// ## 使用栈的调用
+///
+/// `call`，编码与`cc`相同，条件满足时将下一条指令的地址压栈后跳转，
+/// 压栈失败时产生异常，不跳转
+pub fn i_call(inst: &[u8], core: &mut Vcore) -> u64 {
+    if !core
+        .regs
+        .flag
+        .satisfies_condition(ConditionCode::new(inst[1].higher()))
+    {
+        return 10;
+    }
+    let target = match branch_target(inst, core.regs.ip) {
+        Some(target) => target,
+        None => {
+            core.instruction_fault(InterruptId::InvalidInstruction);
+            return 0;
+        }
+    };
+    if let Err(err) = core.push(core.regs.ip + 10) {
+        core.fault(err);
+        return 0;
+    }
+    core.regs.ip = target;
+    core.transferred = true;
+    0
+}
+
+/// ## 使用栈的返回
+///
+/// `ret`，从栈中弹出返回地址并跳转
+pub fn i_ret(_inst: &[u8], core: &mut Vcore) -> u64 {
+    match core.pop() {
+        Ok(ip) => {
+            core.regs.ip = ip;
+            core.transferred = true;
+        }
+        Err(err) => core.fault(err),
+    }
+    0
+}
+
 pub fn i_ldi(inst: &[u8], core: &mut Vcore) -> u64 {
     let reg_before = core.regs.x[inst[1].higher() as usize];
     match inst[1].lower() {
@@ -685,45 +752,14 @@ pub fn i_ldi(inst: &[u8], core: &mut Vcore) -> u64 {
 
 pub fn i_ldm(inst: &[u8], core: &mut Vcore) -> u64 {
     let src = core.regs.x[inst[1].lower() as usize];
-    let src = match core.memory.address(
-        src,
-        core.regs.flag,
-        core.regs.kpt,
-        core.regs.upt,
-        ReadWrite::Read,
-    ) {
+    let src = match core.translate(src, ReadWrite::Read) {
         Ok(addr) => addr,
-        Err(err) => match err {
-            crate::utils::memory::AddressError::OverSized(addr) => {
-                core.intctler.interrupt(InterruptId::InaccessibleAddress);
-                core.regs.imsg = addr;
-                // 无法访问的内存的情况下，要么地址超过内存大小，要么页被交换
-                // 因此要么处理之后重新运行，要么进程直接停止
-                // 所以返回0即可
-                return 0;
-            }
-            crate::utils::memory::AddressError::WrongPrivilege => {
-                core.intctler.interrupt(InterruptId::WrongPrivilege);
-                core.regs.imsg = src;
-                // 跨特权级访问，无法恢复
-                // 程序直接停止，不能继续运行
-                return 0;
-            }
-            crate::utils::memory::AddressError::Unreadable => {
-                core.intctler.interrupt(InterruptId::PageOrTableUnreadable);
-                core.regs.imsg = core.regs.imsg;
-                return 0;
-            }
-            crate::utils::memory::AddressError::Unwritable => {
-                panic!("出现了意外情况，在读寻址时返回了不可写错误")
-            }
-            crate::utils::memory::AddressError::Ineffective => {
-                core.intctler.interrupt(InterruptId::InaccessibleAddress);
-                core.regs.imsg = src;
-                return 0;
-            }
-        },
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
     };
+    core.watch_read(src, 1 << inst[2].min(3));
     let src = core.memory().borrow().slice(src, 8);
     let src = (src[0] as u64)
         | ((src[1] as u64) << 8)
@@ -756,77 +792,21 @@ pub fn i_ldm(inst: &[u8], core: &mut Vcore) -> u64 {
 }
 
 pub fn i_stm(inst: &[u8], core: &mut Vcore) -> u64 {
-    let src = core.regs.x[inst[1].lower() as usize];
-    let mut src = match core.memory.address(
-        src,
-        core.regs.flag,
-        core.regs.kpt,
-        core.regs.upt,
-        ReadWrite::Write,
-    ) {
+    let dst = core.regs.x[inst[1].higher() as usize];
+    let dst = match core.translate(dst, ReadWrite::Write) {
         Ok(addr) => addr,
-        Err(err) => match err {
-            crate::utils::memory::AddressError::OverSized(addr) => {
-                core.intctler.interrupt(InterruptId::InaccessibleAddress);
-                core.regs.imsg = addr;
-                // 无法访问的内存的情况下，要么地址超过内存大小，要么页被交换
-                // 因此要么处理之后重新运行，要么进程直接停止
-                // 所以返回0即可
-                return 0;
-            }
-            crate::utils::memory::AddressError::WrongPrivilege => {
-                core.intctler.interrupt(InterruptId::WrongPrivilege);
-                core.regs.imsg = src;
-                // 跨特权级访问，无法恢复
-                // 程序直接停止，不能继续运行
-                return 0;
-            }
-            crate::utils::memory::AddressError::Unreadable => {
-                panic!("出现了意外情况，在写寻址时返回了不可读错误")
-            }
-            crate::utils::memory::AddressError::Unwritable => {
-                core.intctler.interrupt(InterruptId::PageOrTableUnwritable);
-                core.regs.imsg = core.regs.imsg;
-                return 0;
-            }
-            crate::utils::memory::AddressError::Ineffective => {
-                core.intctler.interrupt(InterruptId::InaccessibleAddress);
-                core.regs.imsg = src;
-                return 0;
-            }
-        },
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
     };
+    let src = core.regs.x[inst[1].lower() as usize];
     let src = match inst[2] {
-        0 => vec![(src & 0xff) as u8],
-        1 => {
-            let mut v = Vec::new();
-            for _ in 0..2 {
-                v.push((src & 0xff) as u8);
-                src >>= 8;
-            }
-            v
-        }
-        2 => {
-            let mut v = Vec::new();
-            for _ in 0..4 {
-                v.push((src & 0xff) as u8);
-                src >>= 8;
-            }
-            v
-        }
-        3 => {
-            let mut v = Vec::new();
-            for _ in 0..8 {
-                v.push((src & 0xff) as u8);
-                src >>= 8;
-            }
-            v
-        }
+        0..=3 => src.to_le_bytes()[..1 << inst[2]].to_vec(),
         _ => vec![],
     };
-    core.memory
-        .borrow_mut()
-        .write_slice(core.regs.x[inst[1].higher() as usize], &src);
+    core.watch_write(dst, &src);
+    core.memory.borrow_mut().write_slice(dst, &src);
     core.regs.flag.mark_symbol(
         core.regs.x[inst[1].lower() as usize],
         core.regs.x[inst[1].lower() as usize],
@@ -855,6 +835,7 @@ pub fn i_in(inst: &[u8], core: &mut Vcore) -> u64 {
     } else {
         0
     };
+    core.watch_dma();
     4
 }
 
@@ -873,9 +854,39 @@ pub fn i_out(inst: &[u8], core: &mut Vcore) -> u64 {
     if let Some(port) = core.io_ports.get_mut(&tar) {
         port.core_push(core.regs.x[src as usize]);
     }
+    core.watch_dma();
     4
 }
 
+/// ## 压栈
+///
+/// `push <reg>`，sp减8后将寄存器的值写入`[sp]`，栈可以跨页
+pub fn i_push(inst: &[u8], core: &mut Vcore) -> u64 {
+    match core.push(core.regs.x[inst[1].lower() as usize]) {
+        Ok(()) => 2,
+        Err(err) => {
+            core.fault(err);
+            0
+        }
+    }
+}
+
+/// ## 出栈
+///
+/// `pop <reg>`，读取`[sp]`到寄存器后sp加8，`pop x15`得到的是栈中的值
+pub fn i_pop(inst: &[u8], core: &mut Vcore) -> u64 {
+    match core.pop() {
+        Ok(value) => {
+            core.regs.x[inst[1].lower() as usize] = value;
+            2
+        }
+        Err(err) => {
+            core.fault(err);
+            0
+        }
+    }
+}
+
 pub fn i_ei(_inst: &[u8], core: &mut Vcore) -> u64 {
     if !core.privilege_test() {
         return 0;
@@ -992,6 +1003,7 @@ pub fn i_cpuid(_inst: &[u8], core: &mut Vcore) -> u64 {
                     ReadWrite::Read,
                 )
                 .unwrap();
+            core.watch_read(tar, i as u64);
             let s = core.memory().borrow().slice(tar, i as u64);
             let s = String::from_utf8_lossy(s).to_string();
             core.deliver_string(s);
@@ -999,15 +1011,87 @@ pub fn i_cpuid(_inst: &[u8], core: &mut Vcore) -> u64 {
         4 => {
             core.regs.x[0] = 1;
         }
+        5 => {
+            // 扩展指令集
+            core.regs.x[0] = extension::available();
+            core.regs.x[1] = core.extensions;
+        }
+        6 => {
+            // 扩展指令集信息，x1为扩展指令集id，超出u8的id不截断，同样视为不存在
+            let ext = u8::try_from(core.regs.x[1])
+                .ok()
+                .and_then(extension::extension);
+            if let Some(ext) = ext {
+                let mut name = 0u64;
+                for (i, c) in ext.name.bytes().take(8).enumerate() {
+                    name |= (c as u64) << (i * 8);
+                }
+                core.regs.x[0] = ext.base as u64;
+                core.regs.x[1] = ext.instructions.len() as u64;
+                core.regs.x[2] = name;
+            } else {
+                core.regs.x[0] = 0;
+                core.regs.x[1] = 0;
+                core.regs.x[2] = 0;
+            }
+        }
+        7 => {
+            // 浮点扩展指令集：支持的格式(f32, f64)、浮点寄存器数量、支持的舍入模式
+            core.regs.x[0] = 0b11;
+            core.regs.x[1] = core.fregs.f.len() as u64;
+            core.regs.x[2] = 0b1111;
+        }
         _ => (),
     }
     1
 }
 
-pub fn i_initext(_inst: &[u8], _core: &mut Vcore) -> u64 {
+/// ## 加载扩展指令集
+///
+/// `initext <id>`，特权指令，扩展指令集不存在或无法加载时产生InvalidInstruction中断
+pub fn i_initext(inst: &[u8], core: &mut Vcore) -> u64 {
+    if !core.privilege_test() {
+        return 0;
+    }
+    if !core.load_extension(inst[1]) {
+        core.instruction_fault(InterruptId::InvalidInstruction);
+        return 0;
+    }
     2
 }
 
-pub fn i_destext(_inst: &[u8], _core: &mut Vcore) -> u64 {
-    1
+/// ## 卸载扩展指令集
+///
+/// `destext <id>`，特权指令，扩展指令集不存在或未加载时产生InvalidInstruction中断
+pub fn i_destext(inst: &[u8], core: &mut Vcore) -> u64 {
+    if !core.privilege_test() {
+        return 0;
+    }
+    if !core.unload_extension(inst[1]) {
+        core.instruction_fault(InterruptId::InvalidInstruction);
+        return 0;
+    }
+    2
+}
+
+/// ## 中断屏蔽字
+///
+/// `imask`，`opcode | op << 4 | r`
+///
+/// op为0时将`x[r]`载入中断屏蔽字，为1时将中断屏蔽字存入`x[r]`。
+/// 屏蔽字的第n位置位时n号中断不会进入而是保持待处理，异常不能被屏蔽
+pub fn i_imask(inst: &[u8], core: &mut Vcore) -> u64 {
+    if !core.privilege_test() {
+        return 0;
+    }
+    let reg = inst[1].lower() as usize;
+    match inst[1].higher() {
+        0 => core.intctler.set_mask(core.regs.x[reg]),
+        1 => core.regs.x[reg] = core.intctler.mask(),
+        _ => {
+            core.instruction_fault(InterruptId::InvalidInstruction);
+            return 0;
+        }
+    }
+    2
 }
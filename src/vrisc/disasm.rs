@@ -0,0 +1,665 @@
+use std::fmt;
+
+use super::{base::BASE, vcore::regs_flags::ConditionCode};
+
+/// ## 基本指令集的助记符
+///
+/// 下标为操作码，与`BASE`一一对应
+pub const MNEMONICS: [Option<&str>; 64] = [
+    Some("nop"),
+    Some("add"),
+    Some("sub"),
+    Some("inc"),
+    Some("dec"),
+    Some("shl"),
+    Some("shr"),
+    Some("rol"),
+    Some("ror"),
+    Some("cmp"),
+    Some("and"),
+    Some("or"),
+    Some("not"),
+    Some("xor"),
+    None,
+    None,
+    Some("jc"),
+    Some("cc"),
+    Some("r"),
+    Some("loop"),
+    Some("ir"),
+    Some("sysc"),
+    Some("sysr"),
+    Some("call"),
+    Some("ret"),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some("ldi"),
+    Some("ldm"),
+    Some("stm"),
+    Some("in"),
+    Some("out"),
+    Some("push"),
+    Some("pop"),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some("ei"),
+    Some("di"),
+    Some("ep"),
+    Some("dp"),
+    Some("livt"),
+    Some("lkpt"),
+    Some("lupt"),
+    Some("lscp"),
+    Some("lipdump"),
+    Some("lflagdump"),
+    Some("sipdump"),
+    Some("sflagdump"),
+    Some("cpuid"),
+    Some("initext"),
+    Some("destext"),
+    Some("imask"),
+];
+
+/// 操作数大小字段0-3（8、16、32、64位）在助记符中的后缀
+pub const SIZES: [&str; 4] = ["b", "w", "d", "q"];
+
+/// 指令的最大长度，转移指令总是这个长度，立即数不足8字节时以0补齐
+pub const MAX_LENGTH: usize = 10;
+
+/// ## 转移指令的寻址方式
+///
+/// `jc`、`cc`和`call`中`inst[1]`的低4位
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BranchMode {
+    Abs16 = 0,
+    Abs32 = 1,
+    Abs64 = 2,
+    Rel16 = 3,
+    Rel32 = 4,
+    Rel64 = 5,
+}
+
+impl BranchMode {
+    pub fn new(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(Self::Abs16),
+            1 => Some(Self::Abs32),
+            2 => Some(Self::Abs64),
+            3 => Some(Self::Rel16),
+            4 => Some(Self::Rel32),
+            5 => Some(Self::Rel64),
+            _ => None,
+        }
+    }
+
+    /// 按`name`的结果解析
+    pub fn parse(name: &str) -> Option<Self> {
+        (0..6)
+            .filter_map(Self::new)
+            .find(|mode| mode.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Abs16 => "abs16",
+            Self::Abs32 => "abs32",
+            Self::Abs64 => "abs64",
+            Self::Rel16 => "rel16",
+            Self::Rel32 => "rel32",
+            Self::Rel64 => "rel64",
+        }
+    }
+
+    /// 立即数的字节数
+    pub fn width(&self) -> usize {
+        match self {
+            Self::Abs16 | Self::Rel16 => 2,
+            Self::Abs32 | Self::Rel32 => 4,
+            Self::Abs64 | Self::Rel64 => 8,
+        }
+    }
+
+    pub fn relative(&self) -> bool {
+        matches!(self, Self::Rel16 | Self::Rel32 | Self::Rel64)
+    }
+}
+
+/// `in`、`out`的端口
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Port {
+    /// 端口号在寄存器中
+    Register(u8),
+    /// 立即数端口号，低8位就是`inst[1]`，所以低4位必须等于数据寄存器，第4-7位不能为0
+    Immediate(u16),
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Port::Register(reg) => write!(f, "x{}", reg),
+            Port::Immediate(port) => write!(f, "{:#x}", port),
+        }
+    }
+}
+
+/// ## 基本指令集中的一条指令
+///
+/// 寄存器是x0-x15的编号，`size`是操作数大小字段（见`SIZES`），
+/// 转移目标是绝对地址，相对寻址的偏移在编码时计算
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Operation {
+    /// 没有操作数的指令，如`nop`、`ret`、`cpuid`
+    Plain(u8),
+    /// `add`、`sub`、`and`、`or`、`xor`：`dest = a op b`
+    Arith {
+        opcode: u8,
+        size: u8,
+        dest: u8,
+        a: u8,
+        b: u8,
+    },
+    /// `inc`、`dec`
+    Unary {
+        opcode: u8,
+        size: u8,
+        reg: u8,
+    },
+    /// `shl`、`shr`、`rol`、`ror`：`reg`移动`x[bits]`位
+    Shift {
+        opcode: u8,
+        size: u8,
+        reg: u8,
+        bits: u8,
+    },
+    Cmp {
+        a: u8,
+        b: u8,
+    },
+    Not {
+        dest: u8,
+        src: u8,
+    },
+    /// `jc`、`cc`、`call`，`cond`是`ConditionCode`
+    Branch {
+        opcode: u8,
+        cond: u8,
+        mode: BranchMode,
+        target: u64,
+    },
+    /// 寄存器不为0时跳转到`target`
+    Loop {
+        reg: u8,
+        target: u64,
+    },
+    Ir(u8),
+    Ldi {
+        size: u8,
+        reg: u8,
+        imm: u64,
+    },
+    /// `x[dest] = [x[addr]]`
+    Ldm {
+        size: u8,
+        dest: u8,
+        addr: u8,
+    },
+    /// `[x[addr]] = x[src]`
+    Stm {
+        size: u8,
+        addr: u8,
+        src: u8,
+    },
+    /// `in`、`out`，`reg`是数据寄存器
+    Io {
+        opcode: u8,
+        reg: u8,
+        port: Port,
+    },
+    /// 只有一个寄存器操作数的指令，如`push`、`livt`
+    Register {
+        opcode: u8,
+        reg: u8,
+    },
+    /// `initext`、`destext`
+    Extension {
+        opcode: u8,
+        id: u8,
+    },
+    /// `store`为false时把`x[reg]`载入中断屏蔽字，为true时把屏蔽字存入`x[reg]`
+    Imask {
+        store: bool,
+        reg: u8,
+    },
+}
+
+const JC: u8 = 0x10;
+const LOOP: u8 = 0x13;
+const IR: u8 = 0x14;
+const LDI: u8 = 0x20;
+const LDM: u8 = 0x21;
+const STM: u8 = 0x22;
+const IN: u8 = 0x23;
+const CMP: u8 = 0x09;
+const NOT: u8 = 0x0c;
+const IMASK: u8 = 0x3f;
+
+impl Operation {
+    /// ## 解码位于`address`的一条指令
+    ///
+    /// 与核心执行时一样解释指令，核心不使用的位（如转移指令的填充字节）被忽略，
+    /// 所以重新编码得到的字节可能与`bytes`不同，但执行的效果相同。
+    ///
+    /// 返回指令和长度。操作码不存在、大小或寻址方式等字段不合法、`bytes`不足一条指令时返回None
+    pub fn decode(bytes: &[u8], address: u64) -> Option<(Self, usize)> {
+        let opcode = *bytes.first()?;
+        let (_, len) = (*BASE.get(opcode as usize)?)?;
+        let len = if opcode == LDI {
+            // 表中是最大长度，实际长度由立即数大小决定
+            2 + (1usize << size(*bytes.get(1)? & 0x0f)?)
+        } else {
+            len as usize
+        };
+        let inst = bytes.get(..len)?;
+        let (h1, l1) = inst.get(1).map_or((0, 0), |&b| (b >> 4, b & 0x0f));
+        let operation = match opcode {
+            0x01 | 0x02 | 0x0a | 0x0b | 0x0d => Self::Arith {
+                opcode,
+                size: size(inst[2] >> 4)?,
+                dest: inst[2] & 0x0f,
+                a: l1,
+                b: h1,
+            },
+            0x03 | 0x04 => Self::Unary {
+                opcode,
+                size: size(h1)?,
+                reg: l1,
+            },
+            0x05..=0x08 => Self::Shift {
+                opcode,
+                size: size(inst[2])?,
+                reg: h1,
+                bits: l1,
+            },
+            CMP => Self::Cmp { a: l1, b: h1 },
+            NOT => Self::Not { dest: h1, src: l1 },
+            JC | 0x11 | 0x17 => {
+                if h1 > ConditionCode::NonSmaller as u8 {
+                    return None;
+                }
+                let mode = BranchMode::new(l1)?;
+                let imm = le(&inst[2..2 + mode.width()]);
+                let target = match mode.width() {
+                    _ if !mode.relative() => imm,
+                    2 => address.wrapping_add(imm as i16 as u64),
+                    4 => address.wrapping_add(imm as i32 as u64),
+                    _ => address.wrapping_add(imm),
+                };
+                Self::Branch {
+                    opcode,
+                    cond: h1,
+                    mode,
+                    target,
+                }
+            }
+            LOOP => {
+                let offset = le(&inst[2..6]);
+                let target = if offset & (1 << 31) != 0 {
+                    address.wrapping_sub(offset & 0x7fff_ffff)
+                } else {
+                    address.wrapping_add(offset)
+                };
+                Self::Loop { reg: l1, target }
+            }
+            IR => Self::Ir(inst[1]),
+            LDI => Self::Ldi {
+                size: l1,
+                reg: h1,
+                imm: le(&inst[2..]),
+            },
+            LDM => Self::Ldm {
+                size: size(inst[2])?,
+                dest: h1,
+                addr: l1,
+            },
+            STM => Self::Stm {
+                size: size(inst[2])?,
+                addr: h1,
+                src: l1,
+            },
+            IN | 0x24 => Self::Io {
+                opcode,
+                reg: l1,
+                port: if h1 != 0 {
+                    Port::Immediate(((inst[2] as u16) << 8) | inst[1] as u16)
+                } else {
+                    Port::Register(inst[2] & 0x0f)
+                },
+            },
+            0x25 | 0x26 | 0x34..=0x3b => Self::Register { opcode, reg: l1 },
+            0x3d | 0x3e => Self::Extension {
+                opcode,
+                id: inst[1],
+            },
+            IMASK if h1 <= 1 => Self::Imask {
+                store: h1 == 1,
+                reg: l1,
+            },
+            IMASK => return None,
+            _ => Self::Plain(opcode),
+        };
+        Some((operation, len))
+    }
+
+    /// ## 把位于`address`的指令编码
+    ///
+    /// 寄存器编号超过15、大小或条件码不合法、立即数或转移偏移超出范围、
+    /// 立即数端口号不合法时返回None
+    pub fn encode(&self, address: u64) -> Option<Vec<u8>> {
+        let regs: &[u8] = match *self {
+            Self::Arith { dest, a, b, .. } => &[dest, a, b],
+            Self::Unary { reg, .. }
+            | Self::Loop { reg, .. }
+            | Self::Ldi { reg, .. }
+            | Self::Io { reg, .. }
+            | Self::Register { reg, .. }
+            | Self::Imask { reg, .. } => &[reg],
+            Self::Shift { reg, bits, .. } => &[reg, bits],
+            Self::Cmp { a, b } => &[a, b],
+            Self::Not { dest, src } => &[dest, src],
+            Self::Ldm { dest, addr, .. } => &[dest, addr],
+            Self::Stm { addr, src, .. } => &[addr, src],
+            _ => &[],
+        };
+        let (size, cond) = match *self {
+            Self::Arith { size, .. }
+            | Self::Unary { size, .. }
+            | Self::Shift { size, .. }
+            | Self::Ldi { size, .. }
+            | Self::Ldm { size, .. }
+            | Self::Stm { size, .. } => (size, 0),
+            Self::Branch { cond, .. } => (0, cond),
+            _ => (0, 0),
+        };
+        if regs.iter().any(|&reg| reg > 15) || size > 3 || cond > ConditionCode::NonSmaller as u8 {
+            return None;
+        }
+        let bytes = match *self {
+            Self::Plain(opcode) => vec![opcode],
+            Self::Arith {
+                opcode,
+                size,
+                dest,
+                a,
+                b,
+            } => vec![opcode, b << 4 | a, size << 4 | dest],
+            Self::Unary { opcode, size, reg } => vec![opcode, size << 4 | reg],
+            Self::Shift {
+                opcode,
+                size,
+                reg,
+                bits,
+            } => vec![opcode, reg << 4 | bits, size],
+            Self::Cmp { a, b } => vec![CMP, b << 4 | a],
+            Self::Not { dest, src } => vec![NOT, dest << 4 | src],
+            Self::Branch {
+                opcode,
+                cond,
+                mode,
+                target,
+            } => {
+                let imm = if mode.relative() {
+                    let offset = target.wrapping_sub(address) as i64;
+                    let bits = 8 * mode.width() as u32;
+                    if bits < 64 && (offset >> (bits - 1)) != 0 && (offset >> (bits - 1)) != -1 {
+                        return None;
+                    }
+                    offset as u64
+                } else {
+                    if mode.width() < 8 && target >> (8 * mode.width()) != 0 {
+                        return None;
+                    }
+                    target
+                };
+                let mut bytes = vec![opcode, cond << 4 | mode as u8];
+                bytes.extend(&imm.to_le_bytes()[..mode.width()]);
+                bytes.resize(MAX_LENGTH, 0);
+                bytes
+            }
+            Self::Loop { reg, target } => {
+                let offset = match target.wrapping_sub(address) as i64 {
+                    offset @ 0..=0x7fff_ffff => offset as u64,
+                    offset @ -0x7fff_ffff..=-1 => offset.unsigned_abs() | 1 << 31,
+                    _ => return None,
+                };
+                let mut bytes = vec![LOOP, reg];
+                bytes.extend(&offset.to_le_bytes()[..4]);
+                bytes
+            }
+            Self::Ir(n) => vec![IR, n],
+            Self::Ldi { size, reg, imm } => {
+                let width = 1usize << size;
+                if width < 8 && imm >> (8 * width) != 0 {
+                    return None;
+                }
+                let mut bytes = vec![LDI, reg << 4 | size];
+                bytes.extend(&imm.to_le_bytes()[..width]);
+                bytes
+            }
+            Self::Ldm { size, dest, addr } => vec![LDM, dest << 4 | addr, size],
+            Self::Stm { size, addr, src } => vec![STM, addr << 4 | src, size],
+            Self::Io { opcode, reg, port } => match port {
+                Port::Register(port) if port <= 15 => vec![opcode, reg, port, 0],
+                Port::Immediate(port) if port & 0x0f == reg as u16 && port & 0xf0 != 0 => {
+                    vec![opcode, port as u8, (port >> 8) as u8, 0]
+                }
+                _ => return None,
+            },
+            Self::Register { opcode, reg } => vec![opcode, reg],
+            Self::Extension { opcode, id } => vec![opcode, id],
+            Self::Imask { store, reg } => vec![IMASK, (store as u8) << 4 | reg],
+        };
+        Some(bytes)
+    }
+
+    pub fn opcode(&self) -> u8 {
+        match *self {
+            Self::Plain(opcode)
+            | Self::Arith { opcode, .. }
+            | Self::Unary { opcode, .. }
+            | Self::Shift { opcode, .. }
+            | Self::Branch { opcode, .. }
+            | Self::Io { opcode, .. }
+            | Self::Register { opcode, .. }
+            | Self::Extension { opcode, .. } => opcode,
+            Self::Cmp { .. } => CMP,
+            Self::Not { .. } => NOT,
+            Self::Loop { .. } => LOOP,
+            Self::Ir(_) => IR,
+            Self::Ldi { .. } => LDI,
+            Self::Ldm { .. } => LDM,
+            Self::Stm { .. } => STM,
+            Self::Imask { .. } => IMASK,
+        }
+    }
+
+    /// 转移指令（`jc`、`cc`、`call`、`loop`）的目标地址
+    pub fn target(&self) -> Option<u64> {
+        match *self {
+            Self::Branch { target, .. } | Self::Loop { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// 汇编形式，转移目标显示为`label`
+    pub fn with_label(&self, label: &str) -> String {
+        let text = self.to_string();
+        match self.target() {
+            Some(target) => {
+                let text = text.strip_suffix(&format!("{:#x}", target)).unwrap();
+                format!("{}{}", text, label)
+            }
+            None => text,
+        }
+    }
+}
+
+/// ## 汇编形式
+///
+/// 目的操作数在前，如`add.q x3, x1, x2`、`stm.d [x2], x1`、`jc.ne 0x1000`。
+/// 转移指令默认是32位相对寻址，其它寻址方式写在目标之前，如`call abs64 0x1000`
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = MNEMONICS[self.opcode() as usize].unwrap();
+        match *self {
+            Self::Plain(_) => write!(f, "{}", mnemonic),
+            Self::Arith {
+                size, dest, a, b, ..
+            } => write!(
+                f,
+                "{}.{} x{}, x{}, x{}",
+                mnemonic, SIZES[size as usize], dest, a, b
+            ),
+            Self::Unary { size, reg, .. } => {
+                write!(f, "{}.{} x{}", mnemonic, SIZES[size as usize], reg)
+            }
+            Self::Shift {
+                size, reg, bits, ..
+            } => write!(
+                f,
+                "{}.{} x{}, x{}",
+                mnemonic, SIZES[size as usize], reg, bits
+            ),
+            Self::Cmp { a, b } => write!(f, "{} x{}, x{}", mnemonic, a, b),
+            Self::Not { dest, src } => write!(f, "{} x{}, x{}", mnemonic, dest, src),
+            Self::Branch {
+                cond, mode, target, ..
+            } => {
+                write!(f, "{}", mnemonic)?;
+                let suffix = ConditionCode::new(cond).suffix();
+                if !suffix.is_empty() {
+                    write!(f, ".{}", suffix)?;
+                }
+                if mode != BranchMode::Rel32 {
+                    write!(f, " {}", mode.name())?;
+                }
+                write!(f, " {:#x}", target)
+            }
+            Self::Loop { reg, target } => write!(f, "{} x{}, {:#x}", mnemonic, reg, target),
+            Self::Ir(n) => write!(f, "{} {}", mnemonic, n),
+            Self::Ldi { size, reg, imm } => write!(
+                f,
+                "{}.{} x{}, {:#x}",
+                mnemonic, SIZES[size as usize], reg, imm
+            ),
+            Self::Ldm { size, dest, addr } => write!(
+                f,
+                "{}.{} x{}, [x{}]",
+                mnemonic, SIZES[size as usize], dest, addr
+            ),
+            Self::Stm { size, addr, src } => write!(
+                f,
+                "{}.{} [x{}], x{}",
+                mnemonic, SIZES[size as usize], addr, src
+            ),
+            Self::Io {
+                opcode, reg, port, ..
+            } => {
+                if opcode == IN {
+                    write!(f, "{} x{}, {}", mnemonic, reg, port)
+                } else {
+                    write!(f, "{} {}, x{}", mnemonic, port, reg)
+                }
+            }
+            Self::Register { reg, .. } => write!(f, "{} x{}", mnemonic, reg),
+            Self::Extension { id, .. } => write!(f, "{} {}", mnemonic, id),
+            Self::Imask { store, reg } => {
+                let op = if store { "get" } else { "set" };
+                write!(f, "{}.{} x{}", mnemonic, op, reg)
+            }
+        }
+    }
+}
+
+/// ## 反汇编得到的一条指令
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Instruction {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+    /// 无法解码时为None，此时`bytes`只有一个字节，显示为`.byte`
+    pub operation: Option<Operation>,
+}
+
+impl Instruction {
+    /// 解码位于`address`的`bytes`开头的一条指令
+    pub fn decode(bytes: &[u8], address: u64) -> Self {
+        match Operation::decode(bytes, address) {
+            Some((operation, len)) => Self {
+                address,
+                bytes: bytes[..len].to_vec(),
+                operation: Some(operation),
+            },
+            None => Self {
+                address,
+                bytes: bytes[..bytes.len().min(1)].to_vec(),
+                operation: None,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.operation {
+            Some(operation) => write!(f, "{}", operation),
+            None => write!(f, ".byte {:#04x}", self.bytes[0]),
+        }
+    }
+}
+
+/// ## 反汇编
+///
+/// 把从`address`开始的`bytes`依次解码为指令，无法解码的字节逐个作为`.byte`，
+/// 所以结果的所有字节连起来就是`bytes`
+pub fn disassemble(bytes: &[u8], address: u64) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let inst = Instruction::decode(&bytes[offset..], address.wrapping_add(offset as u64));
+        offset += inst.len();
+        instructions.push(inst);
+    }
+    instructions
+}
+
+fn size(size: u8) -> Option<u8> {
+    (size <= 3).then_some(size)
+}
+
+/// 最多8字节，小端
+fn le(bytes: &[u8]) -> u64 {
+    let mut value = [0u8; 8];
+    value[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(value)
+}
@@ -0,0 +1,69 @@
+use super::{atomic, float, math, string, vcore::VcoreInstruction};
+
+/// ## 扩展指令集
+///
+/// 扩展指令集在运行时由`initext`指令加载到指令空间的`base..base+instructions.len()`中，
+/// 由`destext`指令卸载。
+///
+/// 每个扩展指令集在指令空间中的位置是固定的，汇编器可以直接使用固定的操作码。
+pub struct Extension {
+    /// 扩展指令集名，不超过8字节，通过`cpuid`报告给客户程序
+    pub name: &'static str,
+
+    /// 加载到指令空间中的起始位置，不小于64
+    pub base: u8,
+
+    pub instructions: &'static [Option<VcoreInstruction>],
+}
+
+/// ## 扩展指令集注册表
+///
+/// 下标即为扩展指令集id，`initext`、`destext`和`cpuid`都使用这个id
+///
+/// id | 名字 | 操作码
+/// :-:|:-:|:-
+/// 0 | math | 0x40-0x47
+/// 1 | float | 0x48-0x55
+/// 2 | atomic | 0x56-0x59
+/// 3 | string | 0x5a-0x5c
+pub const EXTENSIONS: [Extension; 4] = [
+    Extension {
+        name: "math",
+        base: 0x40,
+        instructions: &math::MATH,
+    },
+    Extension {
+        name: "float",
+        base: 0x48,
+        instructions: &float::FLOAT,
+    },
+    Extension {
+        name: "atomic",
+        base: 0x56,
+        instructions: &atomic::ATOMIC,
+    },
+    Extension {
+        name: "string",
+        base: 0x5a,
+        instructions: &string::STRING,
+    },
+];
+
+// 已加载的扩展指令集和`cpuid`报告的可用扩展指令集都是64位的位图
+const _: () = assert!(EXTENSIONS.len() <= 64);
+
+/// 按id查找扩展指令集
+pub fn extension(id: u8) -> Option<&'static Extension> {
+    EXTENSIONS.get(id as usize)
+}
+
+/// ## 可用的扩展指令集
+///
+/// 第i位为1表示id为i的扩展指令集可用
+pub fn available() -> u64 {
+    let mut res = 0u64;
+    for i in 0..EXTENSIONS.len() {
+        res |= 1 << i;
+    }
+    res
+}
@@ -0,0 +1,458 @@
+use std::{
+    cmp::Ordering,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+use super::vcore::{
+    intcontroller::InterruptId, regs_flags::FlagRegFlag, BitOptions, Vcore, VcoreInstruction,
+};
+
+/// ## 浮点扩展指令集
+///
+/// 浮点指令操作独立的浮点寄存器组`f0`-`f15`（见`FloatRegisters`），
+/// f32存放在浮点寄存器的低32位，高32位为0。
+///
+/// 运算指令为3字节，编码与`add`相同：
+///
+/// `opcode | r2 << 4 | r1 | fmt << 4 | r3`
+///
+/// 计算`f[r3] = f[r1] op f[r2]`，fmt为0表示f32，为1表示f64。
+///
+/// 运算结果按`fcsr`中的舍入模式舍入，浮点运算不影响flag寄存器，
+/// 只有`fcmp`和`fcvtfi`设置flag寄存器的标志位。
+pub const FLOAT: [Option<VcoreInstruction>; 14] = [
+    Some((i_fadd, 3)),
+    Some((i_fsub, 3)),
+    Some((i_fmul, 3)),
+    Some((i_fdiv, 3)),
+    Some((i_fsqrt, 3)),
+    Some((i_fcmp, 3)),
+    Some((i_fmov, 2)),
+    Some((i_fcvt, 3)),
+    Some((i_fcvtfi, 3)),
+    Some((i_fcvtif, 3)),
+    Some((i_fmvxf, 2)),
+    Some((i_fmvfx, 2)),
+    Some((i_frcsr, 2)),
+    Some((i_fwcsr, 2)),
+];
+
+/// 就近舍入，偶数优先
+pub const ROUND_NEAREST: u64 = 0;
+/// 向0舍入
+pub const ROUND_ZERO: u64 = 1;
+/// 向负无穷舍入
+pub const ROUND_DOWN: u64 = 2;
+/// 向正无穷舍入
+pub const ROUND_UP: u64 = 3;
+
+/// ## 浮点数
+///
+/// 用于让f32和f64共用指令的实现
+trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+
+    fn load(bits: u64) -> Self;
+    fn store(self) -> u64;
+    fn div(self, rhs: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn mul_add(self, a: Self, b: Self) -> Self;
+    fn next_up(self) -> Self;
+    fn next_down(self) -> Self;
+    fn is_finite(self) -> bool;
+    fn to_f64(self) -> f64;
+    fn from_i128(x: i128) -> Self;
+}
+
+impl Float for f32 {
+    const ZERO: Self = 0.0;
+
+    fn load(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+
+    fn store(self) -> u64 {
+        self.to_bits() as u64
+    }
+
+    fn div(self, rhs: Self) -> Self {
+        self / rhs
+    }
+
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        f32::mul_add(self, a, b)
+    }
+
+    fn next_up(self) -> Self {
+        f32::next_up(self)
+    }
+
+    fn next_down(self) -> Self {
+        f32::next_down(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f32::is_finite(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_i128(x: i128) -> Self {
+        x as f32
+    }
+}
+
+impl Float for f64 {
+    const ZERO: Self = 0.0;
+
+    fn load(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+
+    fn store(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn div(self, rhs: Self) -> Self {
+        self / rhs
+    }
+
+    fn sqrt(self) -> Self {
+        f64::sqrt(self)
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        f64::mul_add(self, a, b)
+    }
+
+    fn next_up(self) -> Self {
+        f64::next_up(self)
+    }
+
+    fn next_down(self) -> Self {
+        f64::next_down(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_i128(x: i128) -> Self {
+        x as f64
+    }
+}
+
+/// ## 按舍入模式修正结果
+///
+/// `res`是就近舍入的结果，`err`是精确值与`res`的大小关系
+fn directed<F: Float>(res: F, err: Ordering, mode: u64) -> F {
+    match err {
+        Ordering::Greater if mode == ROUND_UP || (mode == ROUND_ZERO && res < F::ZERO) => {
+            res.next_up()
+        }
+        Ordering::Less if mode == ROUND_DOWN || (mode == ROUND_ZERO && res > F::ZERO) => {
+            res.next_down()
+        }
+        _ => res,
+    }
+}
+
+/// ## 上溢时精确值与结果的大小关系
+///
+/// 有限数运算得到无穷时，精确值是有限的，比+inf小、比-inf大
+fn overflowed<F: Float>(res: F) -> Ordering {
+    if res > F::ZERO {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+/// 误差的符号
+fn sign<F: Float>(e: F) -> Ordering {
+    e.partial_cmp(&F::ZERO).unwrap_or(Ordering::Equal)
+}
+
+fn add<F: Float>(a: F, b: F, mode: u64) -> F {
+    let s = a + b;
+    if !s.is_finite() {
+        if a.is_finite() && b.is_finite() {
+            return directed(s, overflowed(s), mode);
+        }
+        return s;
+    }
+    // TwoSum，误差可以精确表示
+    let bb = s - a;
+    let e = (a - (s - bb)) + (b - bb);
+    directed(s, sign(e), mode)
+}
+
+fn mul<F: Float>(a: F, b: F, mode: u64) -> F {
+    let p = a * b;
+    if !p.is_finite() {
+        if a.is_finite() && b.is_finite() {
+            return directed(p, overflowed(p), mode);
+        }
+        return p;
+    }
+    directed(p, sign(a.mul_add(b, -p)), mode)
+}
+
+fn div<F: Float>(a: F, b: F, mode: u64) -> F {
+    let q = a.div(b);
+    if !q.is_finite() {
+        if a.is_finite() && b.is_finite() && b != F::ZERO {
+            return directed(q, overflowed(q), mode);
+        }
+        return q;
+    }
+    // a - q * b与a / b - q同号当且仅当b为正
+    let r = (-q).mul_add(b, a);
+    let err = if b > F::ZERO {
+        sign(r)
+    } else {
+        sign(r).reverse()
+    };
+    directed(q, err, mode)
+}
+
+fn sqrt<F: Float>(a: F, mode: u64) -> F {
+    let s = a.sqrt();
+    if !s.is_finite() || s == F::ZERO {
+        return s;
+    }
+    directed(s, sign((-s).mul_add(s, a)), mode)
+}
+
+/// ## 浮点运算
+///
+/// 按fmt选择f32或f64，fmt不合法时产生InvalidInstruction中断
+fn arith(
+    inst: &[u8],
+    core: &mut Vcore,
+    op32: fn(f32, f32, u64) -> f32,
+    op64: fn(f64, f64, u64) -> f64,
+) -> u64 {
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let r3 = (inst[2] & 0x0f) as usize;
+    let mode = core.fregs.rounding_mode();
+    let (a, b) = (core.fregs.f[r1], core.fregs.f[r2]);
+    core.fregs.f[r3] = match inst[2] >> 4 {
+        0 => op32(f32::load(a), f32::load(b), mode).store(),
+        1 => op64(f64::load(a), f64::load(b), mode).store(),
+        _ => return invalid(core),
+    };
+    3
+}
+
+fn invalid(core: &mut Vcore) -> u64 {
+    core.instruction_fault(InterruptId::InvalidInstruction);
+    0
+}
+
+pub fn i_fadd(inst: &[u8], core: &mut Vcore) -> u64 {
+    arith(inst, core, add, add)
+}
+
+pub fn i_fsub(inst: &[u8], core: &mut Vcore) -> u64 {
+    arith(inst, core, |a, b, m| add(a, -b, m), |a, b, m| add(a, -b, m))
+}
+
+pub fn i_fmul(inst: &[u8], core: &mut Vcore) -> u64 {
+    arith(inst, core, mul, mul)
+}
+
+pub fn i_fdiv(inst: &[u8], core: &mut Vcore) -> u64 {
+    arith(inst, core, div, div)
+}
+
+/// `fsqrt`，`f[r3] = sqrt(f[r1])`，忽略r2
+pub fn i_fsqrt(inst: &[u8], core: &mut Vcore) -> u64 {
+    arith(inst, core, |a, _, m| sqrt(a, m), |a, _, m| sqrt(a, m))
+}
+
+/// ## 浮点比较
+///
+/// 比较f[r1]与f[r2]，设置Equal、Bigger、Smaller标志；
+/// 任一操作数为NaN时三者均复位，并设置Overflow标志表示无序
+pub fn i_fcmp(inst: &[u8], core: &mut Vcore) -> u64 {
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let (a, b) = (core.fregs.f[r1], core.fregs.f[r2]);
+    let ord = match inst[2] >> 4 {
+        0 => f32::load(a).partial_cmp(&f32::load(b)),
+        1 => f64::load(a).partial_cmp(&f64::load(b)),
+        _ => return invalid(core),
+    };
+    let flag = &mut core.regs.flag;
+    flag.bit_reset(FlagRegFlag::Equal);
+    flag.bit_reset(FlagRegFlag::Bigger);
+    flag.bit_reset(FlagRegFlag::Smaller);
+    flag.bit_reset(FlagRegFlag::Overflow);
+    match ord {
+        Some(Ordering::Equal) => flag.bit_set(FlagRegFlag::Equal),
+        Some(Ordering::Greater) => flag.bit_set(FlagRegFlag::Bigger),
+        Some(Ordering::Less) => flag.bit_set(FlagRegFlag::Smaller),
+        None => flag.bit_set(FlagRegFlag::Overflow),
+    }
+    3
+}
+
+/// `fmov`，`f[r2] = f[r1]`
+pub fn i_fmov(inst: &[u8], core: &mut Vcore) -> u64 {
+    core.fregs.f[(inst[1] >> 4) as usize] = core.fregs.f[(inst[1] & 0x0f) as usize];
+    2
+}
+
+/// ## 浮点格式转换
+///
+/// `fcvt`，将f[r1]转换为fmt格式存入f[r2]，fmt（`inst[2]`的高4位，与运算指令相同）为0时f64转f32，
+/// 为1时f32转f64，`inst[2]`的低4位被忽略
+pub fn i_fcvt(inst: &[u8], core: &mut Vcore) -> u64 {
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let src = core.fregs.f[r1];
+    core.fregs.f[r2] = match inst[2] >> 4 {
+        0 => {
+            let v = f64::load(src);
+            let r = v as f32;
+            let err = if v.is_finite() && !r.is_finite() {
+                overflowed(r)
+            } else {
+                sign(v - r as f64)
+            };
+            directed(r, err, core.fregs.rounding_mode()).store()
+        }
+        1 => (f32::load(src) as f64).store(),
+        _ => return invalid(core),
+    };
+    3
+}
+
+/// ## 解析整数转换的格式
+///
+/// `inst[2]`的第0位为浮点格式，第1位为1表示有符号整数，第4、5位为整数宽度（同`add`）
+fn conversion(byte: u8) -> (u8, bool, u32) {
+    (byte & 1, byte & 2 != 0, 8 << ((byte >> 4) & 3))
+}
+
+/// ## 浮点数转整数
+///
+/// `fcvtfi`，将f[r1]按舍入模式取整后存入x[r2]，
+/// 超出整数范围时取最接近的可表示值，为NaN时结果为0，这两种情况都设置Overflow标志
+pub fn i_fcvtfi(inst: &[u8], core: &mut Vcore) -> u64 {
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let (fmt, signed, bits) = conversion(inst[2]);
+    let v = if fmt == 0 {
+        f32::load(core.fregs.f[r1]).to_f64()
+    } else {
+        f64::load(core.fregs.f[r1])
+    };
+    let v = match core.fregs.rounding_mode() {
+        ROUND_ZERO => v.trunc(),
+        ROUND_DOWN => v.floor(),
+        ROUND_UP => v.ceil(),
+        _ => v.round_ties_even(),
+    };
+    let (min, max) = if signed {
+        (
+            -((1i128 << (bits - 1)) as f64),
+            (1i128 << (bits - 1)) as f64,
+        )
+    } else {
+        (0.0, (1i128 << bits) as f64)
+    };
+    // 取值范围为[min, max)，min与max都是2的幂，可以精确表示
+    let (res, overflow) = if v.is_nan() {
+        (0, true)
+    } else if v < min {
+        (min as i128, true)
+    } else if v >= max {
+        (max as i128 - 1, true)
+    } else {
+        (v as i128, false)
+    };
+    let mask = if bits == 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    };
+    core.regs.x[r2] = (res as u64) & mask;
+    if overflow {
+        core.regs.flag.bit_set(FlagRegFlag::Overflow);
+    } else {
+        core.regs.flag.bit_reset(FlagRegFlag::Overflow);
+    }
+    3
+}
+
+fn from_int<F: Float>(x: i128, mode: u64) -> F {
+    let r = F::from_i128(x);
+    // 整数不超过64位，舍入结果转回i128不会溢出
+    directed(r, x.cmp(&(r.to_f64() as i128)), mode)
+}
+
+/// ## 整数转浮点数
+///
+/// `fcvtif`，将x[r1]按舍入模式转换后存入f[r2]
+pub fn i_fcvtif(inst: &[u8], core: &mut Vcore) -> u64 {
+    let r1 = (inst[1] & 0x0f) as usize;
+    let r2 = (inst[1] >> 4) as usize;
+    let (fmt, signed, bits) = conversion(inst[2]);
+    let x = core.regs.x[r1] << (64 - bits);
+    let x = if signed {
+        ((x as i64) >> (64 - bits)) as i128
+    } else {
+        (x >> (64 - bits)) as i128
+    };
+    let mode = core.fregs.rounding_mode();
+    core.fregs.f[r2] = if fmt == 0 {
+        from_int::<f32>(x, mode).store()
+    } else {
+        from_int::<f64>(x, mode).store()
+    };
+    3
+}
+
+/// `fmvxf`，将x[r1]的位模式复制到f[r2]
+pub fn i_fmvxf(inst: &[u8], core: &mut Vcore) -> u64 {
+    core.fregs.f[(inst[1] >> 4) as usize] = core.regs.x[(inst[1] & 0x0f) as usize];
+    2
+}
+
+/// `fmvfx`，将f[r1]的位模式复制到x[r2]
+pub fn i_fmvfx(inst: &[u8], core: &mut Vcore) -> u64 {
+    core.regs.x[(inst[1] >> 4) as usize] = core.fregs.f[(inst[1] & 0x0f) as usize];
+    2
+}
+
+/// `frcsr`，读取fcsr到x[r1]
+pub fn i_frcsr(inst: &[u8], core: &mut Vcore) -> u64 {
+    core.regs.x[(inst[1] & 0x0f) as usize] = core.fregs.fcsr;
+    2
+}
+
+/// `fwcsr`，将x[r1]写入fcsr
+pub fn i_fwcsr(inst: &[u8], core: &mut Vcore) -> u64 {
+    core.fregs.fcsr = core.regs.x[(inst[1] & 0x0f) as usize];
+    2
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use super::disasm::{disassemble, Instruction, Operation, MAX_LENGTH};
+
+const CC: u8 = 0x11;
+const CALL: u8 = 0x17;
+
+/// `cc`和`call`
+fn is_call(operation: &Operation) -> bool {
+    matches!(operation, Operation::Branch { opcode, .. } if *opcode == CC || *opcode == CALL)
+}
+
+/// ## ROM镜像的反汇编清单
+///
+/// 从镜像开头线性反汇编。镜像内、位于指令开头的转移目标作为标签：
+/// 镜像的起始地址为`start`，`call`、`cc`的目标为`sub_<地址>`，其它转移的目标为`loc_<地址>`。
+///
+/// 调用图中每条指令属于它之前最近的函数入口（`start`或被调用的地址）
+pub struct Listing {
+    pub instructions: Vec<Instruction>,
+    /// 地址 -> 标签
+    pub labels: BTreeMap<u64, String>,
+    /// 函数入口 -> 它调用的地址，被调用的地址可能在镜像外
+    pub calls: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl Listing {
+    /// 反汇编加载到`base`的镜像`rom`
+    pub fn new(rom: &[u8], base: u64) -> Self {
+        let instructions = disassemble(rom, base);
+        let starts: BTreeSet<u64> = instructions.iter().map(|inst| inst.address).collect();
+        let operations = || instructions.iter().filter_map(|inst| inst.operation);
+
+        let mut labels = BTreeMap::new();
+        labels.insert(base, "start".to_string());
+        for operation in operations() {
+            let Some(target) = operation.target().filter(|target| starts.contains(target)) else {
+                continue;
+            };
+            if target == base {
+                continue;
+            }
+            if is_call(&operation) {
+                labels.insert(target, format!("sub_{:x}", target));
+            } else {
+                labels
+                    .entry(target)
+                    .or_insert_with(|| format!("loc_{:x}", target));
+            }
+        }
+
+        let mut calls: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        calls.insert(base, BTreeSet::new());
+        for operation in operations().filter(is_call) {
+            let target = operation.target().unwrap();
+            if starts.contains(&target) {
+                calls.entry(target).or_default();
+            }
+        }
+        for inst in instructions.iter() {
+            let Some(operation) = inst.operation.filter(is_call) else {
+                continue;
+            };
+            let (_, callees) = calls.range_mut(..=inst.address).next_back().unwrap();
+            callees.insert(operation.target().unwrap());
+        }
+
+        Self {
+            instructions,
+            labels,
+            calls,
+        }
+    }
+
+    /// 地址的标签，没有标签时为十六进制地址
+    pub fn name(&self, address: u64) -> String {
+        self.labels
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| format!("{:#x}", address))
+    }
+
+    /// ## 输出清单
+    ///
+    /// 每行为地址、指令的字节和汇编形式，转移目标有标签时显示标签。
+    /// 连续3条以上没有标签的`nop`只显示第一条，其余用`*`代替
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut repeated = false;
+        for (i, inst) in self.instructions.iter().enumerate() {
+            let label = self.labels.get(&inst.address);
+            let nop = |inst: &Instruction| inst.operation == Some(Operation::Plain(0));
+            if label.is_none() && nop(inst) && i > 0 && nop(&self.instructions[i - 1]) {
+                let next = self.instructions.get(i + 1);
+                if next.is_some_and(|next| nop(next) && !self.labels.contains_key(&next.address)) {
+                    if !repeated {
+                        writeln!(out, "    *")?;
+                        repeated = true;
+                    }
+                    continue;
+                }
+            }
+            repeated = false;
+            if let Some(label) = label {
+                writeln!(out, "{}:", label)?;
+            }
+            let bytes: Vec<String> = inst.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let text = match inst.operation {
+                Some(operation) => match operation.target().and_then(|t| self.labels.get(&t)) {
+                    Some(target) => operation.with_label(target),
+                    None => operation.to_string(),
+                },
+                None => inst.to_string(),
+            };
+            writeln!(
+                out,
+                "    {:08x}  {:width$}  {}",
+                inst.address,
+                bytes.join(" "),
+                text,
+                width = 3 * MAX_LENGTH - 1
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 输出调用图，每行为`函数 -> 被调用的函数`
+    pub fn write_call_graph(&self, out: &mut impl Write) -> io::Result<()> {
+        for (caller, callees) in self.calls.iter() {
+            let caller = self.name(*caller);
+            if callees.is_empty() {
+                writeln!(out, "{}", caller)?;
+            } else {
+                let callees: Vec<String> = callees.iter().map(|&c| self.name(c)).collect();
+                writeln!(out, "{} -> {}", caller, callees.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
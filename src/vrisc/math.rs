@@ -0,0 +1,180 @@
+use super::vcore::{
+    intcontroller::InterruptId, regs_flags::FlagRegFlag, BitOptions, Vcore, VcoreInstruction,
+};
+
+/// ## 整数乘除扩展指令集
+///
+/// 所有指令都是3字节，编码与`add`相同：
+///
+/// `opcode | r2 << 4 | r1 | width << 4 | r3`
+///
+/// 计算`r3 = r1 op r2`，width为0、1、2、3时操作数分别是8、16、32、64位，
+/// 结果截断至操作数宽度后写入r3（高位清零）。
+///
+/// 结果超出操作数宽度时设置Overflow标志，除数为0时产生DivideByZero中断。
+pub const MATH: [Option<VcoreInstruction>; 8] = [
+    Some((i_mul, 3)),
+    Some((i_imul, 3)),
+    Some((i_mulh, 3)),
+    Some((i_imulh, 3)),
+    Some((i_div, 3)),
+    Some((i_idiv, 3)),
+    Some((i_rem, 3)),
+    Some((i_irem, 3)),
+];
+
+/// ## 解析操作数
+///
+/// 返回(r1, r2, r3, 操作数位数)，宽度不合法时产生InvalidInstruction中断并返回None
+fn operands(inst: &[u8], core: &mut Vcore) -> Option<(u64, u64, usize, u32)> {
+    let width = inst[2] >> 4;
+    if width > 3 {
+        core.instruction_fault(InterruptId::InvalidInstruction);
+        return None;
+    }
+    Some((
+        core.regs.x[(inst[1] & 0x0f) as usize],
+        core.regs.x[(inst[1] >> 4) as usize],
+        (inst[2] & 0x0f) as usize,
+        8 << width,
+    ))
+}
+
+/// 取低`bits`位
+#[inline]
+fn unsigned(x: u64, bits: u32) -> u64 {
+    if bits == 64 {
+        x
+    } else {
+        x & ((1 << bits) - 1)
+    }
+}
+
+/// 取低`bits`位并符号扩展
+#[inline]
+fn signed(x: u64, bits: u32) -> i64 {
+    ((x << (64 - bits)) as i64) >> (64 - bits)
+}
+
+/// 写入结果并设置标志位
+fn store(core: &mut Vcore, r3: usize, result: u64, bits: u32, overflow: bool) {
+    let before = core.regs.x[r3];
+    core.regs.x[r3] = unsigned(result, bits);
+    core.regs.flag.mark_symbol(before, core.regs.x[r3]);
+    if overflow {
+        core.regs.flag.bit_set(FlagRegFlag::Overflow);
+    } else {
+        core.regs.flag.bit_reset(FlagRegFlag::Overflow);
+    }
+}
+
+/// ## 除数为0
+///
+/// 产生DivideByZero中断，imsg中为此指令的操作码，不执行此指令
+fn divide_by_zero(core: &mut Vcore) -> u64 {
+    core.instruction_fault(InterruptId::DivideByZero);
+    0
+}
+
+/// 无符号乘法，取低位
+pub fn i_mul(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let res = (unsigned(a, bits) as u128) * (unsigned(b, bits) as u128);
+    store(core, r3, res as u64, bits, (res >> bits) != 0);
+    3
+}
+
+/// 有符号乘法，取低位
+pub fn i_imul(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let res = (signed(a, bits) as i128) * (signed(b, bits) as i128);
+    let low = signed(res as u64, bits) as i128;
+    store(core, r3, res as u64, bits, low != res);
+    3
+}
+
+/// 无符号乘法，取高位
+pub fn i_mulh(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let res = (unsigned(a, bits) as u128) * (unsigned(b, bits) as u128);
+    store(core, r3, (res >> bits) as u64, bits, false);
+    3
+}
+
+/// 有符号乘法，取高位
+pub fn i_imulh(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let res = (signed(a, bits) as i128) * (signed(b, bits) as i128);
+    store(core, r3, (res >> bits) as u64, bits, false);
+    3
+}
+
+/// 无符号除法
+pub fn i_div(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    if unsigned(b, bits) == 0 {
+        return divide_by_zero(core);
+    }
+    store(core, r3, unsigned(a, bits) / unsigned(b, bits), bits, false);
+    3
+}
+
+/// 有符号除法，向0取整
+///
+/// 最小值除以-1时结果为最小值并设置Overflow标志
+pub fn i_idiv(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let (a, b) = (signed(a, bits), signed(b, bits));
+    if b == 0 {
+        return divide_by_zero(core);
+    }
+    let res = (a as i128) / (b as i128);
+    let low = signed(res as u64, bits) as i128;
+    store(core, r3, res as u64, bits, low != res);
+    3
+}
+
+/// 无符号取余
+pub fn i_rem(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    if unsigned(b, bits) == 0 {
+        return divide_by_zero(core);
+    }
+    store(core, r3, unsigned(a, bits) % unsigned(b, bits), bits, false);
+    3
+}
+
+/// 有符号取余，结果的符号与被除数相同
+pub fn i_irem(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, r3, bits) = match operands(inst, core) {
+        Some(opr) => opr,
+        None => return 0,
+    };
+    let (a, b) = (signed(a, bits), signed(b, bits));
+    if b == 0 {
+        return divide_by_zero(core);
+    }
+    store(core, r3, ((a as i128) % (b as i128)) as u64, bits, false);
+    3
+}
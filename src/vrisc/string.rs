@@ -0,0 +1,174 @@
+use crate::utils::{memory::ReadWrite, shared::Addressable};
+
+use super::vcore::{regs_flags::FlagRegFlag, BitOptions, Vcore, VcoreInstruction};
+
+/// ## 串指令集
+///
+/// 所有指令都是3字节：
+///
+/// `opcode | r2 << 4 | r1 | r3`
+///
+/// r1、r2为两个地址（或值）所在的寄存器，r3为字节数所在的寄存器。
+///
+/// 每执行一次最多处理到r1、r2所指地址所在页框的末尾，然后更新r1、r2、r3记录进度：
+/// 若还没有处理完，ip不变，下一轮主循环会继续执行这条指令。
+/// 这样在两次执行之间可以进入中断，页错误时寄存器中是已完成的进度，
+/// 处理完中断或页错误后返回这条指令即可继续。
+pub const STRING: [Option<VcoreInstruction>; 3] =
+    [Some((i_movs, 3)), Some((i_stos, 3)), Some((i_cmps, 3))];
+
+/// ## 本次处理的字节数
+///
+/// 不超过剩余字节数，也不越过任意一个地址所在的页框
+fn chunk(len: u64, addrs: &[u64]) -> u64 {
+    addrs
+        .iter()
+        .fold(len, |n, addr| n.min(16 * 1024 - (addr & 0x3fff)))
+}
+
+fn registers(inst: &[u8]) -> (usize, usize, usize) {
+    (
+        (inst[1] & 0x0f) as usize,
+        (inst[1] >> 4) as usize,
+        (inst[2] & 0x0f) as usize,
+    )
+}
+
+/// ## 串复制
+///
+/// `movs`，从`[x[r2]]`复制`x[r3]`字节到`[x[r1]]`，源与目标重叠时结果未定义
+pub fn i_movs(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (dst, src, len) = registers(inst);
+    if core.regs.x[len] == 0 {
+        return 3;
+    }
+    let n = chunk(core.regs.x[len], &[core.regs.x[dst], core.regs.x[src]]);
+    let from = match core.translate_within(core.regs.x[src], n, ReadWrite::Read) {
+        Ok(addr) => addr,
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
+    };
+    let to = match core.translate_within(core.regs.x[dst], n, ReadWrite::Write) {
+        Ok(addr) => addr,
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
+    };
+    core.watch_read(from, n);
+    let data = core.memory.borrow().slice(from, n).to_vec();
+    core.watch_write(to, &data);
+    core.memory
+        .borrow_mut()
+        .slice_mut(to, n)
+        .copy_from_slice(&data);
+    core.regs.x[dst] = core.regs.x[dst].wrapping_add(n);
+    core.regs.x[src] = core.regs.x[src].wrapping_add(n);
+    core.regs.x[len] -= n;
+    if core.regs.x[len] == 0 {
+        3
+    } else {
+        0
+    }
+}
+
+/// ## 串填充
+///
+/// `stos`，将`[x[r1]]`开始的`x[r3]`字节都置为`x[r2]`的低8位，r2不变
+pub fn i_stos(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (dst, value, len) = registers(inst);
+    if core.regs.x[len] == 0 {
+        return 3;
+    }
+    let n = chunk(core.regs.x[len], &[core.regs.x[dst]]);
+    let to = match core.translate_within(core.regs.x[dst], n, ReadWrite::Write) {
+        Ok(addr) => addr,
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
+    };
+    core.watch_write(to, &vec![core.regs.x[value] as u8; n as usize]);
+    core.memory
+        .borrow_mut()
+        .slice_mut(to, n)
+        .fill(core.regs.x[value] as u8);
+    core.regs.x[dst] = core.regs.x[dst].wrapping_add(n);
+    core.regs.x[len] -= n;
+    if core.regs.x[len] == 0 {
+        3
+    } else {
+        0
+    }
+}
+
+/// ## 串比较
+///
+/// `cmps`，按无符号字节比较`[x[r1]]`与`[x[r2]]`开始的`x[r3]`字节。
+///
+/// 全部相等时设置Equal标志，r1、r2指向比较区域之后，r3为0；
+/// 否则复位Equal标志，按第一个不同的字节设置Higher或Lower标志，
+/// r1、r2指向这两个字节，r3为包括它们在内的剩余字节数
+pub fn i_cmps(inst: &[u8], core: &mut Vcore) -> u64 {
+    let (a, b, len) = registers(inst);
+    if core.regs.x[len] == 0 {
+        core.regs.flag.bit_set(FlagRegFlag::Equal);
+        core.regs.flag.bit_reset(FlagRegFlag::Higher);
+        core.regs.flag.bit_reset(FlagRegFlag::Lower);
+        return 3;
+    }
+    let n = chunk(core.regs.x[len], &[core.regs.x[a], core.regs.x[b]]);
+    let lhs = match core.translate_within(core.regs.x[a], n, ReadWrite::Read) {
+        Ok(addr) => addr,
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
+    };
+    let rhs = match core.translate_within(core.regs.x[b], n, ReadWrite::Read) {
+        Ok(addr) => addr,
+        Err(err) => {
+            core.fault(err);
+            return 0;
+        }
+    };
+    core.watch_read(lhs, n);
+    core.watch_read(rhs, n);
+    let lhs = core.memory.borrow().slice(lhs, n);
+    let rhs = core.memory.borrow().slice(rhs, n);
+    let same = lhs
+        .iter()
+        .zip(rhs.iter())
+        .position(|(l, r)| l != r)
+        .unwrap_or(n as usize) as u64;
+    let diff = if same < n {
+        Some(lhs[same as usize] > rhs[same as usize])
+    } else {
+        None
+    };
+    core.regs.x[a] = core.regs.x[a].wrapping_add(same);
+    core.regs.x[b] = core.regs.x[b].wrapping_add(same);
+    core.regs.x[len] -= same;
+    match diff {
+        Some(higher) => {
+            core.regs.flag.bit_reset(FlagRegFlag::Equal);
+            if higher {
+                core.regs.flag.bit_set(FlagRegFlag::Higher);
+                core.regs.flag.bit_reset(FlagRegFlag::Lower);
+            } else {
+                core.regs.flag.bit_set(FlagRegFlag::Lower);
+                core.regs.flag.bit_reset(FlagRegFlag::Higher);
+            }
+            3
+        }
+        None if core.regs.x[len] == 0 => {
+            core.regs.flag.bit_set(FlagRegFlag::Equal);
+            core.regs.flag.bit_reset(FlagRegFlag::Higher);
+            core.regs.flag.bit_reset(FlagRegFlag::Lower);
+            3
+        }
+        None => 0,
+    }
+}
@@ -3,6 +3,7 @@ pub mod dma;
 pub mod intcontroller;
 pub mod iocontroller;
 pub mod regs_flags;
+pub mod timer;
 
 use std::{
     collections::HashMap,
@@ -10,19 +11,25 @@ use std::{
     thread, u8,
 };
 
-use crate::utils::{
-    memory::{AddressError, Memory, ReadWrite},
-    shared::{Addressable, SharedPointer},
+#[cfg(feature = "debugger")]
+use crate::debugger::watchpoint::Site;
+use crate::{
+    debugger::watchpoint::Watchpoints,
+    machine::trace::MemoryAccess,
+    utils::{
+        memory::{AddressError, Memory, ReadWrite},
+        shared::{shared_name, Addressable, SharedPointer},
+    },
 };
 
 use self::{
     addresser::LazyAddress,
-    intcontroller::{InterruptController, InterruptId},
+    intcontroller::{Access, InterruptController, InterruptId, Message},
     iocontroller::IOPortBuffer,
-    regs_flags::{ConditionCode, FlagRegFlag, Registers},
+    regs_flags::{ConditionCode, FlagRegFlag, FloatRegisters, Registers, SP},
 };
 
-use super::base;
+use super::{base, extension};
 
 pub type VcoreInstruction = (fn(&[u8], &mut Vcore) -> u64, u64);
 
@@ -98,10 +105,14 @@ impl BitOptions for u64 {
 
 /// vcore核心
 pub struct Vcore {
+    /// vcore实例标识，用于共享内存名
+    instance: String,
     id: usize,
     /// 代表此vcore中共有多少个核心
     total: usize,
     pub regs: Registers,
+    /// 浮点寄存器，由浮点扩展指令集使用
+    pub fregs: FloatRegisters,
     pub memory: Memory,
     pub intctler: InterruptController,
     pub lazyaddr: LazyAddress,
@@ -125,6 +136,11 @@ pub struct Vcore {
     /// 最初只会加载基本指令集，其它指令集需要在运行时用扩展指令加载。
     pub instruction_space: [Option<VcoreInstruction>; 256],
 
+    /// ## 已加载的扩展指令集
+    ///
+    /// 第i位为1表示id为i的扩展指令集已被加载到指令空间中
+    pub extensions: u64,
+
     /// ## 转移标志
     ///
     /// 在执行转移指令前后，此标志都会置为true，
@@ -144,6 +160,22 @@ pub struct Vcore {
     /// 在debugger开启时有效，
     pub debug_mode: DebugMode,
 
+    /// ## 内存观察点
+    ///
+    /// 在debugger开启时有效，访问内存的指令在寻址后检查，未开启debugger时总是为空
+    pub watchpoints: Watchpoints,
+
+    /// ## 内存访问记录
+    ///
+    /// 跟踪指令执行时为`Some`，收集正在执行的指令寻址后的每次内存访问
+    pub accesses: Option<Vec<MemoryAccess>>,
+
+    /// ## 写入前的内容
+    ///
+    /// 调试时为`Some`，依次收集核心写入内存的(物理地址, 写入前的内容)，用于反向执行
+    #[cfg(feature = "debugger")]
+    pub writes: Option<Vec<(u64, Vec<u8>)>>,
+
     pub io_ports: HashMap<u16, SharedPointer<IOPortBuffer>>,
 
     /// ## 终端显示管道
@@ -151,57 +183,69 @@ pub struct Vcore {
 }
 
 impl Vcore {
-    pub fn new(id: usize, total_core: usize, memory: Memory) -> Self {
+    pub fn new(instance: &str, id: usize, total_core: usize, memory: Memory) -> Self {
         let (tx, rx) = mpsc::channel();
-        thread::spawn(move || loop {
-            let s = { rx.recv().unwrap() };
-            println!("{}", s);
+        thread::spawn(move || {
+            // 核心被销毁后管道关闭，线程随之退出
+            while let Ok(s) = rx.recv() {
+                println!("{}", s);
+            }
         });
         Vcore {
+            instance: instance.to_string(),
             id,
             total: total_core,
             regs: Registers::new(),
+            fregs: FloatRegisters::new(),
             memory,
             intctler: InterruptController::new(),
             lazyaddr: LazyAddress::new(),
             ip_increment: 0,
             instruction_space: [None; 256],
+            extensions: 0,
             transferred: true,
             nopflag: false,
             debug_mode: DebugMode::None,
+            watchpoints: Watchpoints::new(),
+            accesses: None,
+            #[cfg(feature = "debugger")]
+            writes: None,
             io_ports: HashMap::new(),
             termstr_pipe: tx,
         }
     }
 
-    pub fn add_port(&mut self, port: u16, solid: bool, core: usize) {
+    /// 连接端口的共享内存，失败时返回错误信息
+    pub fn add_port(&mut self, port: u16, solid: bool, core: usize) -> Result<(), String> {
+        let name = if solid {
+            format!("VcoreIOPort{}C{}", port, core)
+        } else {
+            format!("VcoreIOPort{}", port)
+        };
         self.io_ports.insert(
             port,
-            SharedPointer::bind(
-                if solid {
-                    format!("VcoreIOPort{}C{}", port, core)
-                } else {
-                    format!("VcoreIOPort{}", port)
-                },
-                1,
-            )
-            .unwrap(),
+            SharedPointer::open(shared_name(&self.instance, &name), 1)?,
         );
+        Ok(())
     }
 
-    /// 初始化指令集和io
-    pub fn init(&mut self) {
+    /// 初始化指令集并连接固定端口
+    pub fn init(&mut self) -> Result<(), String> {
         self.instruction_space[..64].copy_from_slice(&base::BASE);
         for i in 0..256 {
-            self.add_port(i, true, self.id());
+            self.add_port(i, true, self.id())?;
         }
+        Ok(())
     }
 
+    /// 连接设备的端口并发出设备连接中断，端口的共享内存连接失败时返回错误信息
     #[inline]
-    pub fn link_device(&mut self, port: u16) {
-        self.add_port(port, false, self.id());
-        self.intctler.interrupt(InterruptId::Device);
+    pub fn link_device(&mut self, port: u16) -> Result<(), String> {
+        self.add_port(port, false, self.id())?;
+        self.intctler
+            .interrupt_with(InterruptId::Device, Message::Port(port).encode());
         self.io_ports.get_mut(&0).unwrap().device_push(port as u64);
+        Ok(())
     }
 
     #[inline]
@@ -216,11 +260,35 @@ impl Vcore {
     /// ## 中断跳转
     ///
     /// 当发生中断时，dump寄存器转存ip与flag寄存器状态，进入内核态，
-    /// 关闭中断，ip跳转，中断控制器复位
-    pub fn interrupt_jump(&mut self, intid: InterruptId) {
+    /// 关闭中断，ip跳转，从中断控制器取出这个中断，将它的消息写入imsg
+    ///
+    /// 转存的ip是中断返回后要执行的指令地址：在指令之间进入中断时是下一条指令，
+    /// 指令产生异常时没有增加ip，是出错的指令本身。处理程序从中断向量表中的地址开始执行。
+    ///
+    /// 若flag的InterruptStack位被置位，还会依次将flag和ip压栈（`[sp] = ip, [sp + 8] = flag`），
+    /// 用`ir 2`返回，这样中断可以嵌套。压栈失败时不压栈，改为进入压栈产生的异常，
+    /// 此时imsg为出错的栈地址，dump寄存器中仍是被中断时的状态，原来的中断仍待处理。
+    pub fn interrupt_jump(&mut self, mut intid: InterruptId) {
         if self.regs.flag.bit_get(FlagRegFlag::InterruptEnabled) {
+            let mut stack_fault = false;
+            let ip = self.regs.ip + (self.ip_increment as u64);
             self.regs.flagdump = self.regs.flag;
-            self.regs.ipdump = self.regs.ip;
+            self.regs.ipdump = ip;
+            if self.regs.flag.bit_get(FlagRegFlag::InterruptStack) {
+                let sp = self.regs.x[SP];
+                let flag = self.regs.flag;
+                match self
+                    .write_u64(sp.wrapping_sub(8), flag)
+                    .and_then(|_| self.write_u64(sp.wrapping_sub(16), ip))
+                {
+                    Ok(()) => self.regs.x[SP] = sp.wrapping_sub(16),
+                    Err((id, imsg)) => {
+                        intid = id;
+                        self.regs.imsg = imsg;
+                        stack_fault = true;
+                    }
+                }
+            }
             self.regs.flag.bit_reset(FlagRegFlag::InterruptEnabled);
             self.regs.flag.bit_reset(FlagRegFlag::Privilege);
 
@@ -233,11 +301,14 @@ impl Vcore {
                 addr |= (target[i] as u64) << (i * 8);
             }
             self.regs.ip = addr;
-            self.intctler.reset_intflag();
+            self.ip_increment = 0;
+            if !stack_fault {
+                if let Some(pending) = self.intctler.take() {
+                    self.regs.imsg = pending.msg;
+                }
+            }
             self.transferred = true;
 
-            self.regs.ip += self.ip_increment as u64;
-
             self.nopflag = false;
         }
     }
@@ -246,6 +317,95 @@ impl Vcore {
         &self.memory
     }
 
+    /// 正在执行的指令
+    #[cfg(feature = "debugger")]
+    fn watch_site(&self) -> Site {
+        let ip = self.regs.ip.wrapping_add(self.ip_increment as u64);
+        (self.id, ip, *self.memory.borrow().at(self.lazyaddr.hot_ip))
+    }
+
+    /// 跟踪指令执行时记录一次内存访问
+    #[inline]
+    pub fn trace_access(&mut self, write: bool, addr: u64, data: &[u8]) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(MemoryAccess::new(write, addr, data.to_vec()));
+        }
+    }
+
+    /// 从物理地址`addr`读取`len`字节之前检查观察点并记录访问
+    #[inline]
+    pub fn watch_read(&mut self, addr: u64, len: u64) {
+        if self.accesses.is_none() && !self.watching() {
+            return;
+        }
+        let data = self.memory.borrow().slice(addr, len).to_vec();
+        self.watch_loaded(addr, &data);
+    }
+
+    /// 向物理地址`addr`写入`data`之前检查观察点并记录访问
+    #[inline]
+    pub fn watch_write(&mut self, addr: u64, data: &[u8]) {
+        if self.accesses.is_none() && !self.watching() {
+            return;
+        }
+        let old = self.memory.borrow().slice(addr, data.len() as u64).to_vec();
+        self.watch_stored(addr, &old, data);
+    }
+
+    /// ## 已经从物理地址`addr`读出`data`
+    ///
+    /// 原子指令读写之间内存可能被其它核心改变，更新之后以实际读到的值检查观察点并记录访问
+    pub fn watch_loaded(&mut self, addr: u64, data: &[u8]) {
+        self.trace_access(false, addr, data);
+        #[cfg(feature = "debugger")]
+        if !self.watchpoints.is_empty() {
+            let site = self.watch_site();
+            self.watchpoints.read(addr, data, site);
+        }
+    }
+
+    /// 物理地址`addr`的内容由`old`变为`new`，见`watch_loaded`
+    #[cfg_attr(not(feature = "debugger"), allow(unused_variables))]
+    pub fn watch_stored(&mut self, addr: u64, old: &[u8], new: &[u8]) {
+        self.trace_access(true, addr, new);
+        #[cfg(feature = "debugger")]
+        if let Some(writes) = self.writes.as_mut() {
+            writes.push((addr, old.to_vec()));
+        }
+        #[cfg(feature = "debugger")]
+        if !self.watchpoints.is_empty() {
+            let site = self.watch_site();
+            self.watchpoints.write(addr, old, new, site);
+        }
+    }
+
+    /// 是否有观察点或需要记录写入，未开启debugger时总是false
+    #[inline]
+    fn watching(&self) -> bool {
+        #[cfg(feature = "debugger")]
+        return !self.watchpoints.is_empty() || self.writes.is_some();
+        #[cfg(not(feature = "debugger"))]
+        false
+    }
+
+    /// `in`、`out`之后检查设备是否通过dma改变了被观察的内存，未开启debugger时什么也不做
+    #[inline]
+    pub fn watch_dma(&mut self) {
+        #[cfg(feature = "debugger")]
+        if !self.watchpoints.is_empty() {
+            let site = self.watch_site();
+            let memory = &self.memory;
+            self.watchpoints.dma(
+                |start, len| memory.borrow().slice(start, len).to_vec(),
+                site,
+            );
+        }
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -255,13 +415,59 @@ impl Vcore {
     }
 
     /// ## 复位vcore核心
+    ///
+    /// 已经连接的端口保持不变
     pub fn reset(&mut self) {
         self.regs.reset();
+        self.fregs.reset();
         self.intctler.reset();
         self.ip_increment = 0;
         self.transferred = true;
         self.instruction_space.copy_from_slice(&[None; 256]);
-        self.init();
+        self.instruction_space[..64].copy_from_slice(&base::BASE);
+        self.extensions = 0;
+    }
+
+    /// ## 加载扩展指令集
+    ///
+    /// 扩展指令集不存在、已加载或其所在的指令空间被占用时返回false
+    pub fn load_extension(&mut self, id: u8) -> bool {
+        let ext = match extension::extension(id) {
+            Some(ext) => ext,
+            None => return false,
+        };
+        let range = ext.base as usize..ext.base as usize + ext.instructions.len();
+        if self.extensions & (1 << id) != 0
+            || self.instruction_space[range.clone()]
+                .iter()
+                .any(|inst| inst.is_some())
+        {
+            return false;
+        }
+        self.instruction_space[range].copy_from_slice(ext.instructions);
+        self.extensions |= 1 << id;
+        true
+    }
+
+    /// ## 卸载扩展指令集
+    ///
+    /// 扩展指令集未加载时返回false
+    pub fn unload_extension(&mut self, id: u8) -> bool {
+        let ext = match extension::extension(id) {
+            Some(ext) => ext,
+            None => return false,
+        };
+        if self.extensions & (1 << id) == 0 {
+            return false;
+        }
+        for inst in self.instruction_space
+            [ext.base as usize..ext.base as usize + ext.instructions.len()]
+            .iter_mut()
+        {
+            *inst = None;
+        }
+        self.extensions &= !(1 << id);
+        true
     }
 
     /// ## 特权级检查
@@ -271,13 +477,146 @@ impl Vcore {
     /// 若权限不符，产生中断
     pub fn privilege_test(&mut self) -> bool {
         if self.regs.flag.bit_get(FlagRegFlag::Privilege) {
-            self.intctler.interrupt(InterruptId::WrongPrivilege);
+            self.instruction_fault(InterruptId::WrongPrivilege);
             false
         } else {
             true
         }
     }
 
+    /// ## 寻址
+    ///
+    /// 寻址失败时返回应产生的中断和编码后的消息，由调用者决定是否产生中断
+    pub fn translate(&mut self, addr: u64, rw: ReadWrite) -> Result<u64, (InterruptId, u64)> {
+        self.translate_as(addr, rw, rw.into())
+    }
+
+    /// 以`access`方式访问`addr`时寻址，消息中记录的是`access`
+    fn translate_as(
+        &mut self,
+        addr: u64,
+        rw: ReadWrite,
+        access: Access,
+    ) -> Result<u64, (InterruptId, u64)> {
+        self.memory
+            .address(addr, self.regs.flag, self.regs.kpt, self.regs.upt, rw)
+            .map_err(|error| {
+                let intid = match error {
+                    AddressError::OverSized(_) | AddressError::Ineffective => {
+                        InterruptId::InaccessibleAddress
+                    }
+                    AddressError::WrongPrivilege => InterruptId::WrongPrivilege,
+                    AddressError::Unreadable => InterruptId::PageOrTableUnreadable,
+                    AddressError::Unwritable => InterruptId::PageOrTableUnwritable,
+                };
+                (intid, Message::Access(addr, access).encode())
+            })
+    }
+
+    /// ## 对一段内存寻址
+    ///
+    /// 跨越最小页边界时对两页分别寻址，两页都寻址成功才返回，
+    /// 返回两段物理内存的(起始地址, 长度)
+    fn translate_range(
+        &mut self,
+        addr: u64,
+        len: u64,
+        rw: ReadWrite,
+    ) -> Result<[(u64, u64); 2], (InterruptId, u64)> {
+        let first = (16 * 1024 - (addr & 0x3fff)).min(len);
+        let st = self.translate_within(addr, first, rw)?;
+        if first == len {
+            return Ok([(st, len), (0, 0)]);
+        }
+        let last = self.translate_within(addr.wrapping_add(first), len - first, rw)?;
+        Ok([(st, first), (last, len - first)])
+    }
+
+    /// ## 寻址并检查`len`字节都在内存中
+    ///
+    /// 调用者保证这`len`字节不越过页框边界
+    pub fn translate_within(
+        &mut self,
+        addr: u64,
+        len: u64,
+        rw: ReadWrite,
+    ) -> Result<u64, (InterruptId, u64)> {
+        let target = self.translate(addr, rw)?;
+        if target + len > self.memory.borrow().size() as u64 {
+            return Err((
+                InterruptId::InaccessibleAddress,
+                Message::Access(addr, rw.into()).encode(),
+            ));
+        }
+        Ok(target)
+    }
+
+    /// ## 读取虚拟地址上的u64
+    ///
+    /// 跨页时两页都寻址成功后才读取
+    pub fn read_u64(&mut self, addr: u64) -> Result<u64, (InterruptId, u64)> {
+        let mut bytes = Vec::with_capacity(8);
+        for (st, len) in self.translate_range(addr, 8, ReadWrite::Read)? {
+            self.watch_read(st, len);
+            bytes.extend_from_slice(self.memory.borrow().slice(st, len));
+        }
+        let mut value = 0u64;
+        for (i, byte) in bytes.iter().enumerate() {
+            value |= (*byte as u64) << (i * 8);
+        }
+        Ok(value)
+    }
+
+    /// ## 向虚拟地址写入u64
+    ///
+    /// 跨页时两页都寻址成功后才写入，不会只写入一半
+    pub fn write_u64(&mut self, addr: u64, value: u64) -> Result<(), (InterruptId, u64)> {
+        let bytes = value.to_le_bytes();
+        let mut written = 0;
+        for (st, len) in self.translate_range(addr, 8, ReadWrite::Write)? {
+            self.watch_write(st, &bytes[written..written + len as usize]);
+            self.memory
+                .borrow_mut()
+                .slice_mut(st, len)
+                .copy_from_slice(&bytes[written..written + len as usize]);
+            written += len as usize;
+        }
+        Ok(())
+    }
+
+    /// ## 压栈
+    ///
+    /// 失败时sp不变
+    pub fn push(&mut self, value: u64) -> Result<(), (InterruptId, u64)> {
+        let sp = self.regs.x[SP].wrapping_sub(8);
+        self.write_u64(sp, value)?;
+        self.regs.x[SP] = sp;
+        Ok(())
+    }
+
+    /// ## 出栈
+    ///
+    /// 失败时sp不变
+    pub fn pop(&mut self) -> Result<u64, (InterruptId, u64)> {
+        let value = self.read_u64(self.regs.x[SP])?;
+        self.regs.x[SP] = self.regs.x[SP].wrapping_add(8);
+        Ok(value)
+    }
+
+    /// 产生`translate`等返回的中断
+    pub fn fault(&mut self, (intid, msg): (InterruptId, u64)) {
+        self.intctler.interrupt_with(intid, msg);
+    }
+
+    /// ## 由正在执行的指令产生的异常
+    ///
+    /// 消息为这条指令的操作码
+    pub fn instruction_fault(&mut self, intid: InterruptId) {
+        let opcode = *self.memory.borrow().at(self.lazyaddr.hot_ip);
+        self.intctler
+            .interrupt_with(intid, Message::Instruction(opcode).encode());
+    }
+
     #[inline]
     /// ## 刷新惰性寻址系统
     ///
@@ -287,49 +626,28 @@ impl Vcore {
     ///
     /// 用返回true表示这种情况
     pub fn flush_lazy_address_system(&mut self, debug: bool) -> bool {
-        if (!self.transferred && self.lazyaddr.hot_ip % (16 * 1024) == 0) || debug {
-            if self.lazyaddr.had_run_inst {
-                self.regs.ip += self.ip_increment as u64;
-                self.ip_increment = 0;
-                self.lazyaddr.had_run_inst = false;
-            }
+        // 到达页框边界或执行了跨页的指令后，hot_ip不再可靠，先更新ip寄存器再重新寻址
+        if ((!self.transferred
+            && (self.lazyaddr.hot_ip.is_multiple_of(16 * 1024) || self.lazyaddr.crossed_page))
+            || debug)
+            && self.lazyaddr.had_run_inst
+        {
+            self.regs.ip += self.ip_increment as u64;
+            self.ip_increment = 0;
+            self.lazyaddr.had_run_inst = false;
         }
-        if self.transferred || self.lazyaddr.hot_ip % (16 * 1024) == 0 || self.lazyaddr.crossed_page
+        if self.transferred
+            || self.lazyaddr.hot_ip.is_multiple_of(16 * 1024)
+            || self.lazyaddr.crossed_page
         {
-            self.lazyaddr.hot_ip = match self.memory.address(
-                self.regs.ip,
-                self.regs.flag,
-                self.regs.kpt,
-                self.regs.upt,
-                ReadWrite::Read,
-            ) {
-                Ok(address) => address,
-                Err(error) => match error {
-                    AddressError::OverSized(address) => {
-                        self.intctler.interrupt(InterruptId::InaccessibleAddress);
-                        self.regs.imsg = address;
+            self.lazyaddr.hot_ip =
+                match self.translate_as(self.regs.ip, ReadWrite::Read, Access::Execute) {
+                    Ok(address) => address,
+                    Err(err) => {
+                        self.fault(err);
                         return true;
                     }
-                    AddressError::WrongPrivilege => {
-                        self.intctler.interrupt(InterruptId::WrongPrivilege);
-                        self.regs.imsg = self.regs.ip;
-                        return true;
-                    }
-                    AddressError::Unreadable => {
-                        self.intctler.interrupt(InterruptId::PageOrTableUnreadable);
-                        self.regs.imsg = self.regs.imsg;
-                        return true;
-                    }
-                    AddressError::Unwritable => {
-                        panic!("出现了意外情况，在读寻址时返回了不可写错误")
-                    }
-                    AddressError::Ineffective => {
-                        self.intctler.interrupt(InterruptId::InaccessibleAddress);
-                        self.regs.imsg = self.regs.ip;
-                        return true;
-                    }
-                },
-            };
+                };
             self.transferred = false;
             self.lazyaddr.crossed_page = false;
         }
@@ -366,43 +684,17 @@ impl Vcore {
             )
         } else {
             //指令跨页
-            let firstl = inst_end & (0xffff_ffff_ffff_c000 - inst_st);
-            let lastl = (inst_end - inst_end) & 0xffff_ffff_ffff_c000;
-            inst.copy_from_slice(self.memory().borrow().slice(inst_st, firstl));
-            let last_st = match self.memory.address(
-                self.regs.ip + firstl,
-                self.regs.flag,
-                self.regs.kpt,
-                self.regs.upt,
-                ReadWrite::Read,
-            ) {
-                Ok(address) => address,
-                Err(error) => match error {
-                    AddressError::OverSized(address) => {
-                        self.intctler.interrupt(InterruptId::InaccessibleAddress);
-                        self.regs.imsg = address;
-                        return (Vec::new(), true);
-                    }
-                    AddressError::WrongPrivilege => {
-                        self.intctler.interrupt(InterruptId::WrongPrivilege);
-                        self.regs.imsg = self.regs.ip;
-                        return (Vec::new(), true);
-                    }
-                    AddressError::Unreadable => {
-                        self.intctler.interrupt(InterruptId::PageOrTableUnreadable);
-                        self.regs.imsg = self.regs.imsg;
-                        return (Vec::new(), true);
-                    }
-                    AddressError::Unwritable => {
-                        panic!("出现了意外情况，在读寻址时发生了不可写错误")
-                    }
-                    AddressError::Ineffective => {
-                        self.intctler.interrupt(InterruptId::InaccessibleAddress);
-                        self.regs.imsg = self.regs.ip + firstl;
+            let firstl = 16 * 1024 - (inst_st & 0x3fff);
+            let lastl = instlen - firstl;
+            inst.extend_from_slice(self.memory().borrow().slice(inst_st, firstl));
+            let last_st =
+                match self.translate_as(self.regs.ip + firstl, ReadWrite::Read, Access::Execute) {
+                    Ok(address) => address,
+                    Err(err) => {
+                        self.fault(err);
                         return (Vec::new(), true);
                     }
-                },
-            };
+                };
             inst.append(&mut self.memory.borrow_mut().slice_mut(last_st, lastl).to_vec());
             self.lazyaddr.crossed_page = true;
             (inst, false)
@@ -5,34 +5,48 @@ use std::{
 
 use crate::utils::{
     memory::Memory,
-    shared::{Addressable, SharedPointer},
+    shared::{shared_name, Addressable, SharedPointer},
 };
 
 pub struct DirectMemoryAccess {
+    instance: String,
     dmas: HashMap<u64, SharedPointer<DMAObject>>,
     dma_count: u64,
 }
 
 impl DirectMemoryAccess {
-    pub fn new() -> Self {
+    pub fn new(instance: &str) -> Self {
         Self {
+            instance: instance.to_string(),
             dmas: HashMap::new(),
             dma_count: 1,
         }
     }
 
-    pub fn device_bind(id: u64) -> Option<(SharedPointer<DMAObject>, SharedPointer<u8>)> {
-        let dmaobj = SharedPointer::<DMAObject>::bind(format!("VcoreDMA{}Obj", id), 1).unwrap();
+    pub fn device_bind(
+        instance: &str,
+        id: u64,
+    ) -> Option<(SharedPointer<DMAObject>, SharedPointer<u8>)> {
+        let dmaobj = SharedPointer::<DMAObject>::bind(
+            shared_name(instance, &format!("VcoreDMA{}Obj", id)),
+            1,
+        )
+        .unwrap();
         let size = dmaobj.start + dmaobj.length;
         Some((
             dmaobj,
-            SharedPointer::<u8>::bind(String::from("VcoreVriscMainMemory"), size as usize).unwrap(),
+            SharedPointer::<u8>::bind(shared_name(instance, "VcoreVriscMainMemory"), size as usize)
+                .unwrap(),
         ))
     }
 
     pub fn create_new(&mut self) -> u64 {
         let res = self.dma_count;
-        let dmaobj = SharedPointer::<DMAObject>::new(format!("VcoreDMA{}Obj", res), 1).unwrap();
+        let dmaobj = SharedPointer::<DMAObject>::new(
+            shared_name(&self.instance, &format!("VcoreDMA{}Obj", res)),
+            1,
+        )
+        .unwrap();
         self.dmas.insert(res, dmaobj);
         self.dma_count += 1;
         res
@@ -57,6 +71,59 @@ impl DirectMemoryAccess {
     pub fn remove(&mut self, id: u64) {
         self.dmas.remove(&id);
     }
+
+    /// 下一个要创建的dma的id
+    pub fn next_id(&self) -> u64 {
+        self.dma_count
+    }
+
+    /// 所有dma区域，按id排序
+    pub fn regions(&self) -> Vec<DMARegion> {
+        let mut regions: Vec<_> = self
+            .dmas
+            .iter()
+            .map(|(&id, obj)| DMARegion {
+                id,
+                start: obj.start,
+                length: obj.length,
+                read: obj.read,
+                write: obj.write,
+            })
+            .collect();
+        regions.sort_by_key(|region| region.id);
+        regions
+    }
+
+    /// 删除所有dma后重新创建`regions`，与`next_id`和`regions`相对
+    pub fn restore(&mut self, next_id: u64, regions: &[DMARegion]) {
+        self.dmas.clear();
+        for region in regions {
+            let mut dmaobj = SharedPointer::<DMAObject>::new(
+                shared_name(&self.instance, &format!("VcoreDMA{}Obj", region.id)),
+                1,
+            )
+            .unwrap();
+            dmaobj.write(
+                0,
+                DMAObject {
+                    start: region.start,
+                    length: region.length,
+                    read: region.read,
+                    write: region.write,
+                },
+            );
+            self.dmas.insert(region.id, dmaobj);
+        }
+        self.dma_count = next_id;
+    }
+
+    /// 所有允许设备写入的dma区域，每一项为(dma id, 起始物理地址, 长度)
+    pub fn writable(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.dmas
+            .iter()
+            .filter(|(_, obj)| obj.write)
+            .map(|(&id, obj)| (id, obj.start, obj.length))
+    }
 }
 
 pub struct DMADevice {
@@ -65,12 +132,16 @@ pub struct DMADevice {
 }
 
 impl DMADevice {
-    pub fn new(dma_id: u64) -> Self {
-        let dmaobj = SharedPointer::<DMAObject>::bind(format!("VcoreDMA{}Obj", dma_id), 1).unwrap();
+    pub fn new(instance: &str, dma_id: u64) -> Self {
+        let dmaobj = SharedPointer::<DMAObject>::bind(
+            shared_name(instance, &format!("VcoreDMA{}Obj", dma_id)),
+            1,
+        )
+        .unwrap();
         let sz = dmaobj.start + dmaobj.length;
         Self {
             obj: dmaobj,
-            mem: Memory::bind(sz as usize),
+            mem: Memory::bind(instance, sz as usize).unwrap(),
         }
     }
 }
@@ -133,10 +204,24 @@ impl DerefMut for DMADevice {
     }
 }
 
+/// ## dma区域
+///
+/// 一个dma对象的内容，用于保存和恢复快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DMARegion {
+    pub id: u64,
+    /// 起始物理地址
+    pub start: u64,
+    pub length: u64,
+    pub read: bool,
+    pub write: bool,
+}
+
 /// ## dma状态
 ///
 /// 用于io控制器的固定端口服务
 /// 确定当前设置的状态并调用对应函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DMAStatus {
     None,
     SetCurrentDMAId,
@@ -1,4 +1,8 @@
-#[derive(Debug, Clone, Copy)]
+use std::collections::VecDeque;
+
+use crate::utils::memory::ReadWrite;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InterruptId {
     NI = 0,
     InaccessibleAddress = 1,
@@ -10,6 +14,8 @@ pub enum InterruptId {
     PageOrTableUnreadable = 7,
     PageOrTableUnwritable = 8,
     DeviceCommunication = 9,
+    DivideByZero = 10,
+    InterProcessor = 11,
 }
 
 impl InterruptId {
@@ -25,47 +31,204 @@ impl InterruptId {
             7 => InterruptId::PageOrTableUnreadable,
             8 => InterruptId::PageOrTableUnwritable,
             9 => InterruptId::DeviceCommunication,
+            10 => InterruptId::DivideByZero,
+            11 => InterruptId::InterProcessor,
             _ => InterruptId::NI,
         }
     }
+
+    /// ## 是否为异常
+    ///
+    /// 异常由正在执行的指令产生，不能被屏蔽
+    pub fn is_exception(&self) -> bool {
+        matches!(
+            self,
+            InterruptId::InaccessibleAddress
+                | InterruptId::InvalidInstruction
+                | InterruptId::WrongPrivilege
+                | InterruptId::InaccessibleIOPort
+                | InterruptId::PageOrTableUnreadable
+                | InterruptId::PageOrTableUnwritable
+                | InterruptId::DivideByZero
+        )
+    }
+
+    /// ## 优先级
+    ///
+    /// 数值越小越优先：异常为0，设备中断为1，时钟为2
+    pub fn priority(&self) -> u8 {
+        match self {
+            InterruptId::Clock => 2,
+            id if id.is_exception() => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// ## 访问方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read = 0,
+    Write = 1,
+    /// 取指令
+    Execute = 2,
+}
+
+impl From<ReadWrite> for Access {
+    fn from(rw: ReadWrite) -> Self {
+        match rw {
+            ReadWrite::Read => Access::Read,
+            ReadWrite::Write => Access::Write,
+        }
+    }
+}
+
+/// ## 中断消息
+///
+/// 每个待处理的中断带有一个消息，进入中断时才编码写入imsg：
+///
+/// | 消息 | 中断 | imsg |
+/// | --- | --- | --- |
+/// | `Access` | InaccessibleAddress、WrongPrivilege、PageOrTableUnreadable、PageOrTableUnwritable | 第0-55位和第63位为出错的虚拟地址，第56-58位为访问方式（0读、1写、2取指令） |
+/// | `Instruction` | InvalidInstruction、WrongPrivilege、DivideByZero | 第56-58位为3，低8位为出错指令的操作码 |
+/// | `Port` | Device、DeviceCommunication、InaccessibleIOPort | 端口号 |
+/// | `Core` | InterProcessor | 发送核间中断的核心号 |
+/// | `None` | Clock等 | 0 |
+///
+/// 分页只使用虚拟地址的低50位和第63位，所以地址中第56-62位不会出现在消息中。
+/// 出错指令的地址就是进入中断时转存的ip（ipdump或栈中的ip）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    None,
+    Access(u64, Access),
+    Instruction(u8),
+    Port(u16),
+    Core(usize),
+}
+
+impl Message {
+    /// 访问方式在imsg中的位置
+    pub const KIND_SHIFT: u64 = 56;
+    /// 访问方式为3表示由指令本身产生
+    pub const KIND_INSTRUCTION: u64 = 3;
+    const ADDRESS_MASK: u64 = (1 << 63) | ((1 << 56) - 1);
+
+    pub fn encode(&self) -> u64 {
+        match *self {
+            Message::None => 0,
+            Message::Access(addr, access) => {
+                (addr & Self::ADDRESS_MASK) | ((access as u64) << Self::KIND_SHIFT)
+            }
+            Message::Instruction(opcode) => {
+                (Self::KIND_INSTRUCTION << Self::KIND_SHIFT) | opcode as u64
+            }
+            Message::Port(port) => port as u64,
+            Message::Core(core) => core as u64,
+        }
+    }
+}
+
+/// ## 待处理的中断
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pending {
+    pub id: InterruptId,
+    /// 进入中断时写入imsg的消息，已经过编码
+    pub msg: u64,
 }
 
 /// 中断控制器
 ///
 /// 负责处理中断
+///
+/// 产生的中断进入待处理队列，每次选出优先级最高且未被屏蔽的中断进入，
+/// 优先级相同时按产生的顺序进入：
+///
+/// * 异常优先级最高，异常不能被屏蔽。同一种异常在进入之前只保留第一次和它的消息：
+///   产生异常的指令没有执行完，处理程序返回后重新执行时会再次产生异常并带上当时的消息；
+/// * 设备中断（Device、DeviceCommunication等）次之，按产生的顺序排队。
+///   同一种中断带着相同的消息（如同一个端口）在进入之前只排队一次，
+///   因此被屏蔽的设备中断不会无限增长；
+/// * 时钟中断优先级最低，进入之前的多次时钟中断合并为一次。
+///
+/// 屏蔽字的第n位置位时，n号中断保持待处理而不进入，直到复位该位。
+///
+/// 每个中断都带有自己的消息（见`Message`），后产生的中断不会覆盖先产生的中断的消息。
 pub struct InterruptController {
-    intflag: bool,
-    /// 在intflag为`true`时使用
-    intid: InterruptId,
+    pending: VecDeque<Pending>,
+    mask: u64,
 }
 
 impl InterruptController {
     pub fn new() -> Self {
         InterruptController {
-            intflag: false,
-            intid: InterruptId::NI,
+            pending: VecDeque::new(),
+            mask: 0,
         }
     }
 
+    /// 产生不带消息的中断，进入中断时imsg为0
     pub fn interrupt(&mut self, intid: InterruptId) {
-        self.intflag = true;
-        self.intid = intid;
+        self.interrupt_with(intid, Message::None.encode());
     }
 
-    pub fn interrupted(&self) -> Option<InterruptId> {
-        if self.intflag {
-            Some(self.intid)
+    /// 产生带有消息的中断，`msg`是编码后的消息，进入中断时写入imsg
+    pub fn interrupt_with(&mut self, intid: InterruptId, msg: u64) {
+        self.push(Pending { id: intid, msg });
+    }
+
+    /// 按类型说明中的规则合并后加入待处理队列
+    fn push(&mut self, pending: Pending) {
+        let merged = if pending.id.is_exception() || pending.id == InterruptId::Clock {
+            self.pending.iter().any(|p| p.id == pending.id)
         } else {
-            None
+            self.pending.contains(&pending)
+        };
+        if !merged {
+            self.pending.push_back(pending);
         }
     }
 
-    pub fn reset_intflag(&mut self) {
-        self.intflag = false;
+    fn masked(&self, intid: InterruptId) -> bool {
+        !intid.is_exception() && self.mask & (1 << intid as u64) != 0
+    }
+
+    /// 下一个要进入的待处理中断在队列中的位置
+    fn next(&self) -> Option<usize> {
+        self.pending
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !self.masked(p.id))
+            .min_by_key(|(_, p)| p.id.priority())
+            .map(|(i, _)| i)
+    }
+
+    /// 下一个要进入的中断
+    pub fn interrupted(&self) -> Option<InterruptId> {
+        self.next().map(|i| self.pending[i].id)
+    }
+
+    /// ## 取出下一个要进入的中断
+    ///
+    /// 与`interrupted`返回的是同一个中断
+    pub fn take(&mut self) -> Option<Pending> {
+        self.next().and_then(|i| self.pending.remove(i))
+    }
+
+    /// 所有待处理的中断，按产生的顺序
+    pub fn pending(&self) -> impl Iterator<Item = &Pending> {
+        self.pending.iter()
+    }
+
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u64) {
+        self.mask = mask;
     }
 
     pub fn reset(&mut self) {
-        self.intflag = false;
-        self.intid = InterruptId::NI;
+        self.pending.clear();
+        self.mask = 0;
     }
 }
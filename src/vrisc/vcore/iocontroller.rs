@@ -1,14 +1,17 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     sync::{mpsc::Sender, Arc, RwLock},
+    thread,
 };
 
-use crate::utils::shared::{Addressable, SharedPointer};
+use crate::utils::shared::{shared_name, Addressable, SharedPointer};
 
 use super::dma::{DMADevice, DMAStatus, DirectMemoryAccess};
 
 /// ## 核心IO控制器
 pub struct IOController {
+    instance: String,
+
     /// ## 请求端口
     ///
     /// (port: u16, lock: bool)
@@ -34,65 +37,151 @@ pub struct IOController {
     /// 通过这个Sender把分配的端口发送给某个核心，
     /// vec中每个Sender对应一个核心的Reciever。
     pub port_deliver: Vec<Sender<PortRequest>>,
+
+    /// 下一个要分配的端口号
+    next_port: u16,
 }
 
 pub enum PortRequest {
     Link(u16),
     Interrupt(u16),
+    /// 核间中断，内部为发送中断的核心号
+    Ipi(usize),
 }
 
+/// 核间中断发送给低32位指定的核心，写入固定端口3的数据中第32-33位为目标方式
+pub const IPI_TARGET: u64 = 0;
+/// 核间中断发送给自己
+pub const IPI_SELF: u64 = 1;
+/// 核间中断发送给除自己以外的所有核心
+pub const IPI_OTHERS: u64 = 2;
+/// 核间中断发送给包括自己在内的所有核心
+pub const IPI_ALL: u64 = 3;
+
 unsafe impl Send for IOController {}
 unsafe impl Sync for IOController {}
 
 impl IOController {
-    pub fn new(delivers: Vec<Sender<PortRequest>>) -> Self {
-        Self {
-            reqport: SharedPointer::<u32>::new(String::from("VcoreIORequestPort"), 1).unwrap(),
-            intport: SharedPointer::<IOPortBuffer>::new(String::from("VcoreInterruptPort"), 1)
-                .unwrap(),
+    /// 创建io请求端口和中断端口，共享内存创建失败时返回错误信息
+    pub fn new(instance: &str, delivers: Vec<Sender<PortRequest>>) -> Result<Self, String> {
+        Ok(Self {
+            instance: instance.to_string(),
+            reqport: SharedPointer::<u32>::create(shared_name(instance, "VcoreIORequestPort"), 1)?,
+            intport: SharedPointer::<IOPortBuffer>::create(
+                shared_name(instance, "VcoreInterruptPort"),
+                1,
+            )?,
             ports: HashMap::new(),
             port_deliver: delivers,
-        }
+            next_port: 256,
+        })
     }
 
     pub fn thr_dispatch_ioreq(&mut self) {
         self.reqport.write(0, 0);
-        let mut port_id = 256u16;
         loop {
-            for sender in self.port_deliver.iter_mut() {
-                if let Some(port) = self.intport.core_get() {
-                    sender.send(PortRequest::Interrupt(port as u16)).unwrap();
-                }
-                if (*self.reqport >> 16) == 0 {
-                    continue;
-                }
-                *self.reqport = (port_id as u32) + (*self.reqport & 0xffff0000);
-                while (*self.reqport >> 16) != 0 {
-                    assert!(true); // 使while循环反复求值而不是死循环
-                }
-                *self.reqport = 0 + (*self.reqport & 0xffff0000);
-                sender.send(PortRequest::Link(port_id)).unwrap();
-                self.ports.insert(
-                    port_id,
-                    SharedPointer::new(format!("VcoreIOPort{}", port_id), 1).unwrap(),
-                );
-                if port_id == u16::MAX {
-                    port_id = 256;
-                } else {
-                    port_id += 1;
-                }
+            if let Err(err) = self.dispatch_ioreq() {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    /// ## 处理一轮io请求
+    ///
+    /// 将中断端口中的设备中断和请求端口中的连接请求分发给各个核心，
+    /// 不阻塞（除非有设备正在请求端口），供单线程运行的`Machine`轮询使用。
+    ///
+    /// 无法为设备创建端口时返回错误信息，此时设备仍在等待分配端口。
+    pub fn dispatch_ioreq(&mut self) -> Result<(), String> {
+        for core in 0..self.port_deliver.len() {
+            if let Some(port) = self.intport.core_get() {
+                self.port_deliver[core]
+                    .send(PortRequest::Interrupt(port as u16))
+                    .unwrap();
+            }
+            if (*self.reqport >> 16) == 0 {
+                continue;
+            }
+            let port_id = self.next_port;
+            // 设备收到端口号后立即连接端口，因此先创建端口
+            self.port(port_id)?;
+            *self.reqport = (port_id as u32) + (*self.reqport & 0xffff0000);
+            while (*self.reqport >> 16) != 0 {
+                assert!(true); // 使while循环反复求值而不是死循环
             }
+            *self.reqport = 0 + (*self.reqport & 0xffff0000);
+            self.port_deliver[core]
+                .send(PortRequest::Link(port_id))
+                .unwrap();
+            if port_id == u16::MAX {
+                self.next_port = 256;
+            } else {
+                self.next_port += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// 下一个要分配给设备的端口号
+    pub fn next_port(&self) -> u16 {
+        self.next_port
+    }
+
+    pub fn set_next_port(&mut self, port: u16) {
+        self.next_port = port;
+    }
+
+    /// ## 设备端口
+    ///
+    /// 端口不存在时创建，创建失败时返回错误信息
+    pub fn port(&mut self, port: u16) -> Result<&mut SharedPointer<IOPortBuffer>, String> {
+        match self.ports.entry(port) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(SharedPointer::create(
+                shared_name(&self.instance, &format!("VcoreIOPort{}", port)),
+                1,
+            )?)),
         }
     }
 
     pub fn do_solid_ports_services(
-        ports: &mut Vec<Vec<SharedPointer<IOPortBuffer>>>,
+        ports: &mut [Vec<SharedPointer<IOPortBuffer>>],
         mut startflgs: Vec<SharedPointer<(bool, u64)>>,
         dma_controller: Arc<RwLock<DirectMemoryAccess>>,
+        delivers: Vec<Sender<PortRequest>>,
     ) {
         let mut dma_current = 0;
         let mut dma_opstatus = DMAStatus::None;
-        for core in ports {
+        loop {
+            Self::solid_ports_service(
+                ports,
+                &mut startflgs,
+                &dma_controller,
+                &mut dma_current,
+                &mut dma_opstatus,
+                &delivers,
+            );
+            thread::yield_now();
+        }
+    }
+
+    /// ## 处理一轮固定端口服务
+    ///
+    /// `dma_current`和`dma_opstatus`是dma管理端口的状态，需要在多轮调用之间保存。
+    ///
+    /// `delivers`是各个核心的io请求管道，用于发送核间中断。
+    ///
+    /// 返回本轮通过固定端口1启动的核心，每一项为(发出请求的核心, 被启动的核心, ip)。
+    pub fn solid_ports_service(
+        ports: &mut [Vec<SharedPointer<IOPortBuffer>>],
+        startflgs: &mut [SharedPointer<(bool, u64)>],
+        dma_controller: &RwLock<DirectMemoryAccess>,
+        dma_current: &mut u64,
+        dma_opstatus: &mut DMAStatus,
+        delivers: &[Sender<PortRequest>],
+    ) -> Vec<(usize, usize, u64)> {
+        let mut starts = Vec::new();
+        for (source, core) in ports.iter_mut().enumerate() {
             // port 0: 设备连接端口
             // 不在这里实现
             // port 1: 多核唤醒
@@ -103,54 +192,77 @@ impl IOController {
                     (d1, data)
                 };
                 startflgs[core as usize].write(0, (true, ip));
+                starts.push((source, core as usize, ip));
             }
             // port 2: dma管理
             if let Some(data) = core[2].device_get() {
                 match data {
                     0 => {
-                        dma_current = dma_controller.write().unwrap().create_new();
-                        core[2].device_push(dma_current);
+                        *dma_current = dma_controller.write().unwrap().create_new();
+                        core[2].device_push(*dma_current);
                     }
                     1 => {
-                        dma_opstatus = DMAStatus::SetCurrentDMAId;
+                        *dma_opstatus = DMAStatus::SetCurrentDMAId;
                     }
                     2 => {
-                        dma_opstatus = DMAStatus::SetDMAStart;
+                        *dma_opstatus = DMAStatus::SetDMAStart;
                     }
                     3 => {
-                        dma_opstatus = DMAStatus::SetDMALength;
+                        *dma_opstatus = DMAStatus::SetDMALength;
                     }
                     4 => {
-                        dma_opstatus = DMAStatus::SetDMARead;
+                        *dma_opstatus = DMAStatus::SetDMARead;
                     }
                     5 => {
-                        dma_opstatus = DMAStatus::SetDMAWrite;
+                        *dma_opstatus = DMAStatus::SetDMAWrite;
                     }
                     6 => {
-                        dma_controller.write().unwrap().remove(dma_current);
+                        dma_controller.write().unwrap().remove(*dma_current);
                     }
-                    data => match dma_opstatus {
+                    data => match *dma_opstatus {
                         DMAStatus::SetCurrentDMAId => {
-                            dma_current = data;
+                            *dma_current = data;
                         }
                         DMAStatus::SetDMAStart => {
-                            dma_controller.write().unwrap().set_start(dma_current, data);
+                            dma_controller
+                                .write()
+                                .unwrap()
+                                .set_start(*dma_current, data);
                         }
                         DMAStatus::SetDMALength => dma_controller
                             .write()
                             .unwrap()
-                            .set_length(dma_current, data),
+                            .set_length(*dma_current, data),
                         DMAStatus::SetDMARead => {
-                            dma_controller.write().unwrap().set_read(dma_current, data);
+                            dma_controller.write().unwrap().set_read(*dma_current, data);
                         }
                         DMAStatus::SetDMAWrite => {
-                            dma_controller.write().unwrap().set_write(dma_current, data);
+                            dma_controller
+                                .write()
+                                .unwrap()
+                                .set_write(*dma_current, data);
                         }
                         _ => (),
                     },
                 }
             }
+            // port 3: 核间中断
+            // 低32位为目标核心号，第32-33位为目标方式，见`IPI_TARGET`等
+            if let Some(data) = core[3].device_get() {
+                let targets: Vec<usize> = match (data >> 32) & 3 {
+                    IPI_TARGET => vec![(data & 0xffff_ffff) as usize],
+                    IPI_SELF => vec![source],
+                    IPI_OTHERS => (0..delivers.len()).filter(|&c| c != source).collect(),
+                    _ => (0..delivers.len()).collect(),
+                };
+                for target in targets {
+                    if let Some(sender) = delivers.get(target) {
+                        let _ = sender.send(PortRequest::Ipi(source));
+                    }
+                }
+            }
         }
+        starts
     }
 }
 
@@ -161,22 +273,29 @@ pub struct IODevice {
 }
 
 impl IODevice {
-    pub fn new() -> Self {
+    /// 向实例`instance`请求一个端口并连接
+    pub fn new(instance: &str) -> Self {
         let mut reqport =
-            SharedPointer::<u32>::bind(String::from("VcoreIORequestPort"), 1).unwrap();
+            SharedPointer::<u32>::bind(shared_name(instance, "VcoreIORequestPort"), 1).unwrap();
         reqport.write(0, 1 << 16);
         while *reqport & 0xffff == 0 {
             assert!(true);
         }
         let port_id = (*reqport & 0xffff) as u16;
         reqport.write(0, 0);
-        let io_port =
-            SharedPointer::<IOPortBuffer>::bind(format!("VcoreIOPort{}", port_id), 1).unwrap();
+        let io_port = SharedPointer::<IOPortBuffer>::bind(
+            shared_name(instance, &format!("VcoreIOPort{}", port_id)),
+            1,
+        )
+        .unwrap();
         Self {
             port_id,
             io_port,
-            int_port: SharedPointer::<IOPortBuffer>::bind(String::from("VcoreInterruptPort"), 1)
-                .unwrap(),
+            int_port: SharedPointer::<IOPortBuffer>::bind(
+                shared_name(instance, "VcoreInterruptPort"),
+                1,
+            )
+            .unwrap(),
         }
     }
 }
@@ -186,26 +305,14 @@ pub struct CharacterDevice {
 }
 
 impl CharacterDevice {
-    pub fn new() -> Self {
+    pub fn new(instance: &str) -> Self {
         Self {
-            dev: IODevice::new(),
+            dev: IODevice::new(instance),
         }
     }
 
     pub fn input(&mut self, content: &[u8]) {
-        let mut count = 0;
-        let mut data = 0u64;
-        for byte in content {
-            data |= (*byte as u64) << (count * 8);
-            count += 1;
-            if count == 8 {
-                count = 0;
-                self.dev.io_port.device_push(data);
-            }
-        }
-        if count != 0 {
-            self.dev.io_port.device_push(data);
-        }
+        self.dev.io_port.device_write(content);
         self.dev.int_port.device_push(self.dev.port_id as u64);
     }
 
@@ -230,9 +337,9 @@ pub struct BlockDevice {
 }
 
 impl BlockDevice {
-    pub fn new() -> Self {
+    pub fn new(instance: &str) -> Self {
         Self {
-            dev: CharacterDevice::new(),
+            dev: CharacterDevice::new(instance),
             dma: None,
         }
     }
@@ -277,6 +384,56 @@ impl IOPortBuffer {
         }
     }
 
+    /// 设备写入一段数据，每8字节按小端打包为一个u64，不足8字节的部分补0
+    pub fn device_write(&mut self, content: &[u8]) {
+        for chunk in content.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            self.device_push(u64::from_le_bytes(bytes));
+        }
+    }
+
+    /// ## 查看设备写入的数据
+    ///
+    /// 返回从位置`from`到设备当前写入位置之间的数据（不取出）以及设备当前的写入位置，
+    /// 用于记录设备输入
+    pub fn device_written(&self, from: usize) -> (Vec<u64>, usize) {
+        let mut data = Vec::new();
+        let mut i = from % 4096;
+        while i != self.irear {
+            data.push(self.ibuffer[i]);
+            i = (i + 1) % 4096;
+        }
+        (data, self.irear)
+    }
+
+    /// 输入队列（设备写入、核心尚未读出）和输出队列（核心写入、设备尚未读出）中的数据
+    pub fn contents(&self) -> (Vec<u64>, Vec<u64>) {
+        let queue = |buffer: &[u64; 4096], front: usize, rear: usize| {
+            let mut data = Vec::new();
+            let mut i = front;
+            while i != rear {
+                data.push(buffer[i]);
+                i = (i + 1) % 4096;
+            }
+            data
+        };
+        (
+            queue(&self.ibuffer, self.ifront, self.irear),
+            queue(&self.obuffer, self.ofront, self.orear),
+        )
+    }
+
+    /// 清空两个队列后分别写入`input`和`output`，与`contents`相对
+    pub fn set_contents(&mut self, input: &[u64], output: &[u64]) {
+        self.ifront = 0;
+        self.irear = 0;
+        self.ofront = 0;
+        self.orear = 0;
+        input.iter().for_each(|&data| self.device_push(data));
+        output.iter().for_each(|&data| self.core_push(data));
+    }
+
     pub fn core_get(&mut self) -> Option<u64> {
         if self.ifront == self.irear {
             return None;
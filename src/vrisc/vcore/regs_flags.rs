@@ -13,6 +13,8 @@ pub enum FlagRegFlag {
     InterruptEnabled = 8,
     PagingEnabled = 9,
     Privilege = 10,
+    /// 置位时中断进入会将flag和ip压栈，见`Vcore::interrupt_jump`
+    InterruptStack = 11,
 
     UserSpace = 63,
 }
@@ -57,8 +59,40 @@ impl ConditionCode {
             _ => ConditionCode::None,
         }
     }
+
+    /// 按`suffix`的结果解析，返回条件码的值
+    pub fn parse(suffix: &str) -> Option<u8> {
+        (0..=ConditionCode::NonSmaller as u8)
+            .find(|&cond| ConditionCode::new(cond).suffix() == suffix)
+    }
+
+    /// 汇编中条件转移指令的后缀，`None`（总是满足）没有后缀
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ConditionCode::None => "",
+            ConditionCode::Zero => "z",
+            ConditionCode::Signed => "s",
+            ConditionCode::Overflow => "o",
+            ConditionCode::Equal => "e",
+            ConditionCode::NonEqual => "ne",
+            ConditionCode::Higher => "h",
+            ConditionCode::Lower => "l",
+            ConditionCode::NonHigher => "nh",
+            ConditionCode::NonLower => "nl",
+            ConditionCode::Bigger => "bg",
+            ConditionCode::Smaller => "sm",
+            ConditionCode::NonBigger => "nbg",
+            ConditionCode::NonSmaller => "nsm",
+        }
+    }
 }
 
+/// ## 栈指针寄存器
+///
+/// 约定x15为栈指针，`push`、`pop`、`call`、`ret`和中断压栈都使用它，
+/// 栈向低地址增长，sp指向栈顶元素
+pub const SP: usize = 15;
+
 /// vrisc寄存器
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct Registers {
@@ -103,3 +137,32 @@ impl Registers {
         self.flagdump = 0;
     }
 }
+
+/// ## vrisc浮点寄存器
+///
+/// 浮点扩展指令集使用的寄存器组，与`Registers`分开存放
+///
+/// fcsr的最低2位为舍入模式：0就近舍入，1向0舍入，2向负无穷舍入，3向正无穷舍入
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct FloatRegisters {
+    pub f: [u64; 16],
+    pub fcsr: u64,
+}
+
+impl FloatRegisters {
+    pub fn new() -> Self {
+        FloatRegisters {
+            f: [0; 16],
+            fcsr: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.f.copy_from_slice(&[0; 16]);
+        self.fcsr = 0;
+    }
+
+    pub fn rounding_mode(&self) -> u64 {
+        self.fcsr & 3
+    }
+}
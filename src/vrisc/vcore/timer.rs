@@ -0,0 +1,164 @@
+/// ## 可编程定时器
+///
+/// 每个核心一个，通过固定端口4编程。写入端口4的数据中第60-63位为命令，低60位为参数：
+///
+/// | 命令 | 作用 |
+/// | --- | --- |
+/// | 0 | 停止定时器 |
+/// | 1 | 单次触发，参数微秒后产生一次Clock中断 |
+/// | 2 | 周期触发，每参数微秒产生一次Clock中断 |
+/// | 3 | 单次触发，再执行参数条指令后产生一次Clock中断 |
+/// | 4 | 周期触发，每执行参数条指令产生一次Clock中断 |
+/// | 5 | 读取微秒计数器，结果可从端口4读入 |
+/// | 6 | 读取指令计数器，结果可从端口4读入 |
+///
+/// 两个计数器都从核心开始运行时单调递增。周期为0时等同于停止定时器。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timer {
+    mode: TimerMode,
+    unit: TimerUnit,
+    period: u64,
+    /// 下一次触发时计数器的值
+    deadline: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    Stopped,
+    OneShot,
+    Periodic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerUnit {
+    Microsecond,
+    Instruction,
+}
+
+/// 定时器所在的固定端口
+pub const TIMER_PORT: u16 = 4;
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    /// 已停止的定时器
+    pub fn new() -> Self {
+        Timer {
+            mode: TimerMode::Stopped,
+            unit: TimerUnit::Microsecond,
+            period: 0,
+            deadline: 0,
+        }
+    }
+
+    /// 从0开始，每`period`微秒触发一次的定时器
+    pub fn periodic(period: u64) -> Self {
+        Timer {
+            mode: TimerMode::Periodic,
+            unit: TimerUnit::Microsecond,
+            period,
+            deadline: period,
+        }
+    }
+
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    /// 编码为4个u64，用于保存快照
+    pub fn to_raw(&self) -> [u64; 4] {
+        let mode = match self.mode {
+            TimerMode::Stopped => 0,
+            TimerMode::OneShot => 1,
+            TimerMode::Periodic => 2,
+        };
+        let unit = match self.unit {
+            TimerUnit::Microsecond => 0,
+            TimerUnit::Instruction => 1,
+        };
+        [mode, unit, self.period, self.deadline]
+    }
+
+    /// 从`to_raw`的结果恢复，编码无效时返回None
+    pub fn from_raw(raw: [u64; 4]) -> Option<Self> {
+        let mode = match raw[0] {
+            0 => TimerMode::Stopped,
+            1 => TimerMode::OneShot,
+            2 => TimerMode::Periodic,
+            _ => return None,
+        };
+        let unit = match raw[1] {
+            0 => TimerUnit::Microsecond,
+            1 => TimerUnit::Instruction,
+            _ => return None,
+        };
+        Some(Timer {
+            mode,
+            unit,
+            period: raw[2],
+            deadline: raw[3],
+        })
+    }
+
+    fn counter(&self, usec: u64, insts: u64) -> u64 {
+        match self.unit {
+            TimerUnit::Microsecond => usec,
+            TimerUnit::Instruction => insts,
+        }
+    }
+
+    /// 写入端口的数据是否为读取微秒计数器的命令
+    pub fn reads_usec(data: u64) -> bool {
+        data >> 60 == 5
+    }
+
+    /// ## 执行写入端口的命令
+    ///
+    /// `usec`和`insts`是当前两个计数器的值，命令为读取计数器时返回要写回端口的值
+    pub fn command(&mut self, data: u64, usec: u64, insts: u64) -> Option<u64> {
+        let arg = data & 0x0fff_ffff_ffff_ffff;
+        let (mode, unit) = match data >> 60 {
+            0 => (TimerMode::Stopped, self.unit),
+            1 => (TimerMode::OneShot, TimerUnit::Microsecond),
+            2 => (TimerMode::Periodic, TimerUnit::Microsecond),
+            3 => (TimerMode::OneShot, TimerUnit::Instruction),
+            4 => (TimerMode::Periodic, TimerUnit::Instruction),
+            5 => return Some(usec),
+            6 => return Some(insts),
+            _ => return None,
+        };
+        self.mode = if mode == TimerMode::Periodic && arg == 0 {
+            TimerMode::Stopped
+        } else {
+            mode
+        };
+        self.unit = unit;
+        self.period = arg;
+        self.deadline = self.counter(usec, insts).saturating_add(arg);
+        None
+    }
+
+    /// ## 判断定时器是否到期
+    ///
+    /// 到期时单次触发的定时器停止，周期触发的定时器设置下一次到期的时间，
+    /// 落后超过一个周期时不补发错过的中断
+    pub fn hit(&mut self, usec: u64, insts: u64) -> bool {
+        let now = self.counter(usec, insts);
+        if self.mode == TimerMode::Stopped || now < self.deadline {
+            return false;
+        }
+        if self.mode == TimerMode::OneShot {
+            self.mode = TimerMode::Stopped;
+        } else {
+            self.deadline += self.period;
+            if self.deadline <= now {
+                self.deadline = now + self.period;
+            }
+        }
+        true
+    }
+}
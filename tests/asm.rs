@@ -0,0 +1,189 @@
+use vcore::{
+    machine::Machine,
+    vrisc::{
+        asm::assemble,
+        disasm::{disassemble, Instruction},
+    },
+};
+
+fn text(bytes: &[u8], address: u64) -> String {
+    Instruction::decode(bytes, address).to_string()
+}
+
+/// 单条指令汇编后再反汇编得到相同的写法
+#[test]
+fn instructions() {
+    for source in [
+        "nop",
+        "add.q x3, x1, x2",
+        "inc.w x5",
+        "shl.d x4, x3",
+        "cmp x1, x2",
+        "not x1, x2",
+        "ldi.b x1, 0x7f",
+        "ldi.q x1, 0xabcd",
+        "ldm.q x3, [x2]",
+        "stm.q [x2], x1",
+        "in x3, x4",
+        "in x3, 0x213",
+        "out 0x213, x3",
+        "out x4, x3",
+        "push x15",
+        "ir 2",
+        "initext 1",
+        "imask.get x4",
+        "imask.set x4",
+        "jc.e 0x40",
+        "call abs16 0x2000",
+        "cc.sm rel16 0x110",
+        "jc.nsm abs64 0x123456789",
+        "loop x2, 0xf8",
+        "ret",
+    ] {
+        let image = assemble(&format!(".org 0x100\n{}", source)).unwrap();
+        assert_eq!(text(&image[0x100..], 0x100), source);
+    }
+}
+
+/// 省略大小后缀为.q，助记符和寄存器不区分大小写，`sp`是`x15`
+#[test]
+fn defaults() {
+    let image = assemble("ADD X3, x1, X2\npush sp\nldi x1, 1").unwrap();
+    let mut expected = vec![0x01, 0x21, 0x33, 0x25, 0x0f, 0x20, 0x13, 1];
+    expected.extend([0; 7]);
+    assert_eq!(image, expected);
+}
+
+/// 标签、向前引用和常量
+#[test]
+fn labels() {
+    let source = "
+        .equ base, 0x40
+        start:  jc abs16 end    ; 向前引用
+                loop x1, start
+        .org base
+        end: ret
+    ";
+    let image = assemble(source).unwrap();
+    assert_eq!(image.len(), 0x41);
+    assert_eq!(&image[..4], [0x10, 0x00, 0x40, 0x00]);
+    assert_eq!(text(&image[10..], 10), "loop x1, 0x0");
+    assert_eq!(image[0x40], 0x18);
+}
+
+#[test]
+fn expressions() {
+    let source = "
+        .equ four, 2 * 2
+        .qword (1 << four) + 3 * 2 - -1, ~0, 'a', $, 7 / 2 % 3
+        .byte 0x0f & 0x3c | 0b1 ^ 0o3, -1, '\\n', ';'
+        here: .word here + 1
+    ";
+    let image = assemble(source).unwrap();
+    let qword = |i: usize| u64::from_le_bytes(image[8 * i..8 * i + 8].try_into().unwrap());
+    assert_eq!(qword(0), 23);
+    assert_eq!(qword(1), u64::MAX);
+    assert_eq!(qword(2), 'a' as u64);
+    assert_eq!(qword(3), 24);
+    assert_eq!(qword(4), 0);
+    assert_eq!(&image[40..44], [0x0c | (1 ^ 3), 0xff, b'\n', b';']);
+    assert_eq!(&image[44..], [45, 0]);
+}
+
+#[test]
+fn directives() {
+    let source = r#"
+        .ascii "a;b"
+        .asciz "\t\"\x41\\"
+        .align 8
+        .dword 0x12345678
+        .zero 3
+    "#;
+    let image = assemble(source).unwrap();
+    assert_eq!(&image[..8], b"a;b\t\"A\\\0");
+    assert_eq!(&image[8..12], 0x12345678u32.to_le_bytes());
+    assert_eq!(&image[12..], [0; 3]);
+}
+
+/// 错误信息带有行号
+#[test]
+fn errors() {
+    let error = |source: &str| assemble(source).unwrap_err();
+    assert!(error("nop\nfoo x1").starts_with("第2行: 未知指令\"foo\""));
+    assert!(error("a: nop\na: nop").starts_with("第2行: \"a\"重复定义"));
+    assert!(error("jc missing").starts_with("第1行: \"missing\"未定义"));
+    assert!(error(".org later\nlater:").starts_with("第1行: \"later\"未定义"));
+    assert!(error(".org 4\n.org 2").starts_with("第2行: 不能向回移动"));
+    assert!(error(".byte 256").starts_with("第1行: \"256\"超出1字节"));
+    assert!(error("ldi.b x1, 0x100").starts_with("第1行: 操作数超出范围"));
+    assert!(error(".org 0x10000\njc rel16 0").starts_with("第2行: 操作数超出范围"));
+    assert!(error("add x1, x2").starts_with("第1行: add需要3个操作数"));
+    assert!(error("inc x16").starts_with("第1行: \"x16\"不是寄存器"));
+    assert!(error("jc.zz 0").starts_with("第1行: 未知的条件\"zz\""));
+    assert!(error(".qword 1 / 0").starts_with("第1行: 除数为0"));
+    assert!(error(".qword (1").starts_with("第1行: 缺少\")\""));
+}
+
+/// 任意字节序列的反汇编结果重新汇编后，每条指令与原来的解码结果相同
+#[test]
+fn round_trip() {
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut bytes = Vec::new();
+    for _ in 0..0x10000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let byte = match seed % 4 {
+            0 => (seed >> 8) as u8 % 0x40,
+            1 => 0,
+            _ => (seed >> 8) as u8,
+        };
+        bytes.push(byte);
+    }
+    let address = 0x8000;
+    let instructions = disassemble(&bytes, address);
+    let mut source = format!(".org {:#x}\n", address);
+    for inst in instructions.iter() {
+        source.push_str(&format!("{}\n", inst));
+    }
+    let image = assemble(&source).unwrap();
+    assert_eq!(image.len(), address as usize + bytes.len());
+    // 重新编码会清除忽略的位，这可能改变后面的`.byte`能否解码，所以按原来的边界逐条比较
+    for inst in instructions.iter() {
+        let start = inst.address as usize;
+        let re = Instruction::decode(&image[start..start + inst.len()], inst.address);
+        match inst.operation {
+            Some(operation) => assert_eq!(re.operation, Some(operation), "{}", inst),
+            None => assert_eq!(re.bytes, inst.bytes),
+        }
+    }
+}
+
+/// 汇编的程序在核心中运行
+#[test]
+fn run() {
+    let source = "
+        .equ n, 10
+        start:  ldi sp, stack
+                ldi x1, n
+                ldi x2, 0
+        again:  call accumulate
+                dec x1
+                loop x1, again
+                ldi x3, message
+                ldm.b x4, [x3]
+                nop
+        accumulate:
+                add x2, x2, x1  ; x2 += x1
+                ret
+        message: .asciz \"Hi\"
+                .align 8
+                .zero 0x100
+        stack:
+    ";
+    let mut machine = Machine::new("asm-run", 64 * 1024, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.run(10_000).unwrap();
+    assert_eq!(machine.core(0).regs.x[2], 55);
+    assert_eq!(machine.core(0).regs.x[4], 'H' as u64);
+}
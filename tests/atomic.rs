@@ -0,0 +1,71 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use vcore::{machine::Machine, utils::memory::Memory};
+
+mod common;
+
+use common::{jc, ldi, qword, MEMORY};
+
+const CORES: usize = 4;
+const ROUNDS: u64 = 2000;
+
+const LOCK: u64 = 0x1000;
+const COUNTER: u64 = 0x1008;
+const DONE: u64 = 0x1010;
+
+/// 每个核心用`cas`自旋获取锁，在锁内用普通的`ldm`、`add`、`stm`递增计数器，
+/// 用`xchg`释放锁，完成后用`xadd`递增完成计数
+fn program() -> Vec<u8> {
+    let mut p = Vec::new();
+    p.extend([0x3d, 2]); // initext atomic
+    ldi(&mut p, 1, LOCK);
+    ldi(&mut p, 2, COUNTER);
+    ldi(&mut p, 3, ROUNDS);
+    ldi(&mut p, 5, 1);
+    ldi(&mut p, 6, 0);
+    let spin = p.len() as u64;
+    ldi(&mut p, 4, 0);
+    p.extend([0x56, 0x41, 0x35]); // cas [x1], x4, x5
+    jc(&mut p, 5, spin); // NonEqual
+    p.extend([0x21, 0x72, 3]); // ldm x7, [x2]
+    p.extend([0x01, 0x57, 0x37]); // add x7, x7, x5
+    p.extend([0x22, 0x27, 3]); // stm x7, [x2]
+    p.extend([0x58, 0x41, 0x30]); // xchg [x1], x4
+    p.extend([0x02, 0x53, 0x33]); // sub x3, x3, x5
+    p.extend([0x09, 0x63]); // cmp x3, x6
+    jc(&mut p, 5, spin); // NonEqual
+    ldi(&mut p, 8, DONE);
+    p.extend([0x57, 0x58, 0x30]); // xadd [x8], x5
+    p.push(0x00); // nop
+    p
+}
+
+#[test]
+fn lock_protected_counter() {
+    let mut machine = Machine::new("atomic-test", MEMORY, CORES, true).unwrap();
+    machine.load(0, &program());
+    for id in 1..CORES {
+        machine.start_core(id, 0);
+    }
+    let memory = Memory::bind("atomic-test", MEMORY).unwrap();
+    let running = machine.spawn();
+
+    let start = Instant::now();
+    while qword(&memory, DONE) != CORES as u64 {
+        assert!(
+            start.elapsed() < Duration::from_secs(120),
+            "cores did not finish"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    let machine = running.stop().unwrap();
+
+    assert_eq!(qword(&memory, COUNTER), CORES as u64 * ROUNDS);
+    assert_eq!(qword(&memory, LOCK), 0);
+    for id in 0..CORES {
+        assert!(machine.instruction_count(id) > 0);
+    }
+}
@@ -0,0 +1,197 @@
+use vcore::machine::Machine;
+
+mod common;
+
+use common::MEMORY;
+
+const PAGE: u64 = 16 * 1024;
+
+/// 从`base`开始组装的一段代码
+struct Code {
+    base: u64,
+    bytes: Vec<u8>,
+}
+
+impl Code {
+    fn new(base: u64) -> Self {
+        Code {
+            base,
+            bytes: Vec::new(),
+        }
+    }
+
+    fn here(&self) -> u64 {
+        self.base + self.bytes.len() as u64
+    }
+
+    /// `ldi x<reg>, imm`，64位立即数
+    fn ldi(&mut self, reg: u8, imm: u64) -> &mut Self {
+        common::ldi(&mut self.bytes, reg, imm);
+        self
+    }
+
+    /// `inc x<reg>`，64位
+    fn inc(&mut self, reg: u8) -> &mut Self {
+        self.bytes.extend([0x03, 0x30 | reg]);
+        self
+    }
+
+    /// `dec x<reg>`，64位
+    fn dec(&mut self, reg: u8) -> &mut Self {
+        self.bytes.extend([0x04, 0x30 | reg]);
+        self
+    }
+
+    /// `cmp x<r1>, x<r2>`
+    fn cmp(&mut self, r1: u8, r2: u8) -> &mut Self {
+        self.bytes.extend([0x09, (r2 << 4) | r1]);
+        self
+    }
+
+    /// 以64位绝对地址转移
+    fn jump_abs(&mut self, opcode: u8, cond: u8, target: u64) -> &mut Self {
+        self.bytes.extend([opcode, (cond << 4) | 2]);
+        self.bytes.extend(target.to_le_bytes());
+        self
+    }
+
+    /// 以32位相对偏移转移
+    fn jump_rel(&mut self, opcode: u8, cond: u8, target: u64) -> &mut Self {
+        let offset = target.wrapping_sub(self.here()) as i32;
+        self.bytes.extend([opcode, (cond << 4) | 4]);
+        self.bytes.extend(offset.to_le_bytes());
+        self.bytes.extend([0; 4]);
+        self
+    }
+
+    /// `loop x<reg>, target`，偏移为原码
+    fn lp(&mut self, reg: u8, target: u64) -> &mut Self {
+        let offset = target as i64 - self.here() as i64;
+        let offset = if offset < 0 {
+            (1 << 31) | (-offset as u32)
+        } else {
+            offset as u32
+        };
+        self.bytes.extend([0x13, reg]);
+        self.bytes.extend(offset.to_le_bytes());
+        self
+    }
+
+    fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes.extend(bytes);
+        self
+    }
+
+    /// ## 填充直到`addr`
+    ///
+    /// `nop`会使核心停止，间隔足够时用`jc`跳过填充，否则用`inc x4`填充
+    fn pad_to(&mut self, addr: u64) -> &mut Self {
+        if addr - self.here() >= 10 {
+            self.jump_rel(JC, ALWAYS, addr);
+        }
+        while self.here() + 2 <= addr {
+            self.inc(4);
+        }
+        assert_eq!(self.here(), addr);
+        self
+    }
+}
+
+const JC: u8 = 0x10;
+const CC: u8 = 0x11;
+const CALL: u8 = 0x17;
+const ALWAYS: u8 = 0;
+const NON_EQUAL: u8 = 5;
+
+/// 从地址0跳到`code`处运行，直到核心进入nop状态
+fn run(instance: &str, code: &Code) -> Machine {
+    let mut machine = Machine::new(instance, MEMORY, 1, true).unwrap();
+    let mut entry = Code::new(0);
+    entry.jump_abs(JC, ALWAYS, code.base);
+    machine.load(0, &entry.bytes);
+    machine.load(code.base, &code.bytes);
+    machine.run(100_000).unwrap();
+    machine
+}
+
+/// 循环体从第一页末尾开始，相对转移指令在第二页，向后跳过页框边界
+#[test]
+fn backward_relative_jump_across_page() {
+    let mut code = Code::new(PAGE - 0x20);
+    code.ldi(1, 100).ldi(0, 0);
+    let body = code.here();
+    code.inc(2).dec(1).cmp(1, 0).pad_to(PAGE + 4);
+    code.jump_rel(JC, NON_EQUAL, body).raw(&[0x00]);
+
+    let machine = run("branch-jc", &code);
+    assert_eq!(machine.core(0).regs.x[2], 100);
+    assert_eq!(machine.core(0).regs.x[1], 0);
+}
+
+/// 向后转移的指令本身横跨页框边界
+#[test]
+fn backward_jump_straddling_page() {
+    let mut code = Code::new(PAGE - 0x20);
+    code.ldi(1, 50).ldi(0, 0);
+    let body = code.here();
+    code.inc(2).dec(1).cmp(1, 0).pad_to(PAGE - 4);
+    code.jump_rel(JC, NON_EQUAL, body).raw(&[0x00]);
+
+    let machine = run("branch-straddle", &code);
+    assert_eq!(machine.core(0).regs.x[2], 50);
+}
+
+/// `loop`使用相对于本条指令的偏移，向后跳过页框边界
+#[test]
+fn backward_loop_across_page() {
+    let mut code = Code::new(PAGE - 0x10);
+    code.ldi(1, 30);
+    let body = code.here();
+    code.inc(2).dec(1).pad_to(PAGE + 2);
+    code.lp(1, body).raw(&[0x00]);
+
+    let machine = run("branch-loop", &code);
+    assert_eq!(machine.core(0).regs.x[2], 30);
+}
+
+/// 第二页中的相对`call`向后调用第一页中的函数，`ret`返回第二页
+#[test]
+fn backward_relative_call_across_page() {
+    let mut code = Code::new(PAGE - 0x10);
+    let function = code.here();
+    code.inc(2).raw(&[0x18, 0x00]); // ret
+    code.pad_to(PAGE + 0x10);
+    let entry = code.here();
+    code.ldi(15, 3 * PAGE);
+    code.jump_rel(CALL, ALWAYS, function);
+    code.jump_rel(CALL, ALWAYS, function).raw(&[0x00]);
+
+    let mut machine = Machine::new("branch-call", MEMORY, 1, true).unwrap();
+    let mut start = Code::new(0);
+    start.jump_abs(JC, ALWAYS, entry);
+    machine.load(0, &start.bytes);
+    machine.load(code.base, &code.bytes);
+    machine.run(1000).unwrap();
+    assert_eq!(machine.core(0).regs.x[2], 2);
+    assert_eq!(machine.core(0).regs.x[15], 3 * PAGE);
+}
+
+/// 相对`cc`将返回地址存入ipdump，`r`返回到`cc`之后
+#[test]
+fn relative_cc_returns_after_call() {
+    let mut code = Code::new(PAGE - 0x10);
+    let function = code.here();
+    code.inc(2).raw(&[0x12, 0x00]); // r
+    code.pad_to(PAGE + 0x10);
+    let entry = code.here();
+    code.jump_rel(CC, ALWAYS, function).inc(3).raw(&[0x00]);
+
+    let mut machine = Machine::new("branch-cc", MEMORY, 1, true).unwrap();
+    let mut start = Code::new(0);
+    start.jump_abs(JC, ALWAYS, entry);
+    machine.load(0, &start.bytes);
+    machine.load(code.base, &code.bytes);
+    machine.run(1000).unwrap();
+    assert_eq!(machine.core(0).regs.x[2], 1);
+    assert_eq!(machine.core(0).regs.x[3], 1);
+}
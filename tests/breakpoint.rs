@@ -0,0 +1,206 @@
+#![cfg(feature = "debugger")]
+
+use vcore::{
+    debugger::{
+        breakpoint::{BreakAddress, Breakpoint, Compare, Condition},
+        debug::{Regs, VdbApi},
+    },
+    machine::Machine,
+    vrisc::{
+        asm::assemble,
+        vcore::{
+            regs_flags::{FloatRegisters, Registers},
+            DebugMode,
+        },
+    },
+};
+
+/// 虚拟地址断点与ip比较，物理地址断点与hot_ip比较
+#[test]
+fn address() {
+    let regs = Registers::new();
+    let fregs = FloatRegisters::new();
+    let virt = Breakpoint::new(BreakAddress::Virtual(0x1000), None);
+    let phys = Breakpoint::new(BreakAddress::Physical(0x1000), None);
+    assert!(virt.matches(0x1000, 0x5000, &regs, &fregs));
+    assert!(!virt.matches(0x5000, 0x1000, &regs, &fregs));
+    assert!(phys.matches(0x5000, 0x1000, &regs, &fregs));
+    assert!(!phys.matches(0x1000, 0x5000, &regs, &fregs));
+}
+
+/// 条件断点只在比较成立时生效
+#[test]
+fn condition() {
+    let mut regs = Registers::new();
+    let mut fregs = FloatRegisters::new();
+    let x3 = Breakpoint::new(
+        BreakAddress::Virtual(0x100),
+        Some(Condition {
+            register: Regs::X(3),
+            compare: Compare::parse(">=").unwrap(),
+            value: 10,
+        }),
+    );
+    regs.x[3] = 9;
+    assert!(!x3.matches(0x100, 0x100, &regs, &fregs));
+    regs.x[3] = 10;
+    assert!(x3.matches(0x100, 0x100, &regs, &fregs));
+    assert!(!x3.matches(0x108, 0x108, &regs, &fregs));
+
+    let fcsr = Breakpoint::new(
+        BreakAddress::Virtual(0x100),
+        Some(Condition {
+            register: Regs::Fcsr,
+            compare: Compare::Ne,
+            value: 0,
+        }),
+    );
+    assert!(!fcsr.matches(0x100, 0x100, &regs, &fregs));
+    fregs.fcsr = 2;
+    assert!(fcsr.matches(0x100, 0x100, &regs, &fregs));
+    assert_eq!(Compare::parse("=>"), None);
+}
+
+/// 调用三次`func`，每次x1加1
+const CALLS: &str = "
+        ldi sp, 0x800
+        call abs16 func
+        call abs16 func
+        call abs16 func
+        nop
+.org 0x100
+func:   inc x1
+        ret
+";
+
+fn debug_machine(instance: &str) -> Machine {
+    let mut machine = Machine::new(instance, 4096, 1, true).unwrap();
+    machine.load(0, &assemble(CALLS).unwrap());
+    machine.set_debug();
+    machine
+}
+
+fn list(machine: &mut Machine, n: usize) -> (Breakpoint, bool) {
+    match machine
+        .debug_request(0, VdbApi::ListBreakpoint(n, None))
+        .unwrap()
+    {
+        VdbApi::ListBreakpoint(_, Some(listed)) => listed,
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+}
+
+/// 断点使核心停在指令执行之前，恢复运行后不会立刻在原处再次触发
+#[test]
+fn stop_and_resume() {
+    let mut machine = debug_machine("breakpoint-resume");
+    let breakpoint = Breakpoint::new(BreakAddress::Virtual(0x100), None);
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::AddBreakpoint(breakpoint))
+            .unwrap(),
+        VdbApi::BreakpointAdded(1)
+    );
+    machine.run(100).unwrap();
+    // 执行了ldi和call，inc还没有执行
+    assert_eq!(machine.instruction_count(0), 2);
+    assert_eq!(machine.core(0).regs.x[1], 0);
+    assert_eq!(machine.core(0).debug_mode, DebugMode::Step);
+    let (listed, stopped) = list(&mut machine, 0);
+    assert_eq!((listed.id, listed.hits, stopped), (1, 1, true));
+
+    // 单步执行断点处的指令
+    assert_eq!(
+        machine.debug_request(0, VdbApi::Continue).unwrap(),
+        VdbApi::Ok
+    );
+    assert_eq!(machine.core(0).regs.x[1], 1);
+    assert_eq!(list(&mut machine, 0).0.hits, 1);
+
+    // 恢复运行，在第二次调用时停下
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::DebugMode(DebugMode::None))
+            .unwrap(),
+        VdbApi::Ok
+    );
+    machine.run(100).unwrap();
+    assert_eq!(machine.instruction_count(0), 5);
+    assert_eq!(machine.core(0).regs.x[1], 1);
+    assert_eq!(
+        list(&mut machine, 0),
+        (Breakpoint { hits: 2, ..listed }, true)
+    );
+
+    // 停在断点处时直接恢复运行，不会重复触发同一个断点
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::DebugMode(DebugMode::None))
+            .unwrap(),
+        VdbApi::Ok
+    );
+    assert_eq!(machine.core(0).regs.x[1], 2);
+    machine.run(100).unwrap();
+    assert_eq!(machine.core(0).regs.x[1], 2);
+    assert_eq!(list(&mut machine, 0).0.hits, 3);
+
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::DeleteBreakpoint(1))
+            .unwrap(),
+        VdbApi::Ok
+    );
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::DebugMode(DebugMode::None))
+            .unwrap(),
+        VdbApi::Ok
+    );
+    machine.run(100).unwrap();
+    assert_eq!(machine.core(0).regs.x[1], 3);
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::ListBreakpoint(0, None))
+            .unwrap(),
+        VdbApi::NoBreakpoint
+    );
+}
+
+/// 条件不成立时断点不触发，也不增加命中次数
+#[test]
+fn conditional_stop() {
+    let mut machine = debug_machine("breakpoint-condition");
+    let breakpoint = Breakpoint::new(
+        BreakAddress::Virtual(0x100),
+        Some(Condition {
+            register: Regs::X(1),
+            compare: Compare::Eq,
+            value: 2,
+        }),
+    );
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::AddBreakpoint(breakpoint))
+            .unwrap(),
+        VdbApi::BreakpointAdded(1)
+    );
+    machine.run(100).unwrap();
+    assert_eq!(machine.core(0).regs.x[1], 2);
+    assert_eq!(machine.instruction_count(0), 8);
+    assert_eq!(list(&mut machine, 0).0.hits, 1);
+
+    assert_eq!(
+        machine
+            .debug_request(0, VdbApi::DebugMode(DebugMode::None))
+            .unwrap(),
+        VdbApi::Ok
+    );
+    machine.run(100).unwrap();
+    assert_eq!(machine.core(0).regs.x[1], 3);
+    assert_eq!(machine.core(0).debug_mode, DebugMode::None);
+    let (listed, stopped) = list(&mut machine, 0);
+    assert_eq!(
+        (listed.hits, listed.condition, stopped),
+        (1, breakpoint.condition, false)
+    );
+}
@@ -0,0 +1,105 @@
+//! 集成测试共用的手工编码的指令和虚拟机的搭建
+//!
+//! 每个测试只用到其中的一部分
+#![allow(dead_code)]
+
+use vcore::{
+    machine::Machine,
+    utils::{memory::Memory, shared::Addressable},
+    vrisc::asm::assemble,
+};
+
+pub const MEMORY: usize = 64 * 1024;
+
+pub const IVT: u64 = 0x2000;
+pub const CLOCK_HANDLER: u64 = 0x300;
+pub const DEVICE_HANDLER: u64 = 0x340;
+pub const RETURN: u64 = 0x380;
+/// 设备中断处理程序读取的端口
+pub const PORT: u16 = 256;
+
+/// ## 汇编源码的中断向量表
+///
+/// 位于0x800，InaccessibleAddress转到`fault`，DeviceCommunication转到`device`，
+/// 之后的处理程序从0x1000开始
+const VECTORS: &str = "
+        .org 0x800
+        .qword 0, fault, 0, 0, 0, 0, 0, 0, 0, device
+        .org 0x1000
+";
+
+/// `ldi x<reg>, imm`，64位立即数
+pub fn ldi(program: &mut Vec<u8>, reg: u8, imm: u64) {
+    program.extend([0x20, (reg << 4) | 3]);
+    program.extend(imm.to_le_bytes());
+}
+
+/// `jc <cond>, target`，64位绝对地址
+pub fn jc(program: &mut Vec<u8>, cond: u8, target: u64) {
+    program.extend([0x10, (cond << 4) | 2]);
+    program.extend(target.to_le_bytes());
+}
+
+/// `loop x<reg>, body`，`body`为之前的位置
+pub fn loop_back(program: &mut Vec<u8>, reg: u8, body: usize) {
+    let offset = (program.len() - body) as u32;
+    program.extend([0x13, reg]);
+    program.extend(((1 << 31) | offset).to_le_bytes());
+}
+
+/// 向端口`port`写入`data`，使用x1和x2
+pub fn out(program: &mut Vec<u8>, data: u64, port: u64) {
+    ldi(program, 1, data);
+    ldi(program, 2, port);
+    program.extend([0x24, 0x01, 0x02, 0x00]); // out x1, x2
+}
+
+/// 加载位于`IVT`的中断向量表并打开中断
+pub fn enable_interrupt(program: &mut Vec<u8>) {
+    ldi(program, 0, IVT);
+    program.extend([0x34, 0x00]); // livt x0
+    program.push(0x30); // ei
+}
+
+/// 递增x5 `rounds`次后进入nop状态
+pub fn count(program: &mut Vec<u8>, rounds: u64) {
+    ldi(program, 6, rounds);
+    let body = program.len();
+    program.extend([0x03, 0x35, 0x04, 0x36]); // inc x5; dec x6
+    loop_back(program, 6, body);
+    program.push(0x00);
+}
+
+/// ## 中断处理程序
+///
+/// 时钟中断的处理程序计数到x3，设备中断的处理程序从`PORT`读入一个数据到x8，
+/// 进入nop状态后唤醒核心的中断直接返回
+pub fn install_handlers(machine: &mut Machine) {
+    let mut device = Vec::new();
+    ldi(&mut device, 9, PORT as u64);
+    device.extend([0x23, 0x08, 0x09, 0x00]); // in x8, x9
+    device.extend([0x14, 0x01]); // ir 1
+
+    machine.load(CLOCK_HANDLER, &[0x03, 0x33, 0x14, 0x01]); // inc x3; ir 1
+    machine.load(DEVICE_HANDLER, &device);
+    machine.load(RETURN, &[0x14, 0x01]);
+    machine.load(IVT + 8 * 2, &RETURN.to_le_bytes());
+    machine.load(IVT + 8 * 3, &CLOCK_HANDLER.to_le_bytes());
+    machine.load(IVT + 8 * 9, &DEVICE_HANDLER.to_le_bytes());
+}
+
+/// ## 从汇编源码启动单核虚拟机
+///
+/// `program`从地址0开始，需要自己用`livt`载入0x800处的中断向量表，
+/// `handlers`中定义`fault`和`device`两个处理程序
+pub fn boot(instance: &str, program: &str, handlers: &str) -> Machine {
+    let source = format!("{}{}{}", program, VECTORS, handlers);
+    let mut machine = Machine::new(instance, MEMORY, 1, true).unwrap();
+    machine.load(0, &assemble(&source).unwrap());
+    machine
+}
+
+/// 读出`addr`处的8字节
+pub fn qword(memory: &Memory, addr: u64) -> u64 {
+    u64::from_le_bytes(memory.borrow().slice(addr, 8).try_into().unwrap())
+}
@@ -0,0 +1,56 @@
+use vcore::{
+    machine::{
+        event::{Event, TimedEvent},
+        Machine,
+    },
+    utils::shared::Addressable,
+};
+
+mod common;
+
+use common::{count, enable_interrupt, install_handlers, out, MEMORY, PORT};
+
+const CORE1: u64 = 0x400;
+
+fn run(instance: &str) -> Machine {
+    let mut core0 = Vec::new();
+    enable_interrupt(&mut core0);
+    out(&mut core0, (2 << 60) | 20, 4); // 每20微秒一次时钟中断
+    out(&mut core0, 1 | (CORE1 << 32), 1); // 启动核心1
+    count(&mut core0, 20000);
+    let mut core1 = Vec::new();
+    count(&mut core1, 15000);
+
+    let mut machine = Machine::new(instance, MEMORY, 2, true).unwrap();
+    machine.set_deterministic(true);
+    machine.load(0, &core0);
+    machine.load(CORE1, &core1);
+    install_handlers(&mut machine);
+    machine.schedule(TimedEvent::new(0, 10, Event::Link(PORT)));
+    machine.schedule(TimedEvent::new(
+        0,
+        5000,
+        Event::Input(PORT, b"vcore!".to_vec()),
+    ));
+    machine.run(1_000_000).unwrap();
+    machine
+}
+
+/// 相同的程序和事件两次运行得到完全相同的状态
+#[test]
+fn identical_runs() {
+    let a = run("deterministic-a");
+    let b = run("deterministic-b");
+    for id in 0..2 {
+        assert_eq!(a.core(id).regs, b.core(id).regs);
+        assert_eq!(a.instruction_count(id), b.instruction_count(id));
+    }
+    assert_eq!(
+        a.memory().borrow().slice(0, MEMORY as u64),
+        b.memory().borrow().slice(0, MEMORY as u64)
+    );
+    // 虚拟时间的时钟中断和定时注入的设备输入都已生效
+    assert!(a.core(0).regs.x[3] > 0);
+    assert_eq!(a.core(0).regs.x[8].to_le_bytes()[..6], *b"vcore!");
+    assert_eq!(a.core(1).regs.x[5], 15000);
+}
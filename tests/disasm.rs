@@ -0,0 +1,161 @@
+use vcore::vrisc::{
+    base::BASE,
+    disasm::{disassemble, BranchMode, Instruction, Operation, Port, MNEMONICS},
+};
+
+fn text(bytes: &[u8], address: u64) -> String {
+    Instruction::decode(bytes, address).to_string()
+}
+
+#[test]
+fn mnemonics() {
+    for (opcode, mnemonic) in MNEMONICS.iter().enumerate() {
+        assert_eq!(mnemonic.is_some(), BASE[opcode].is_some(), "{:#x}", opcode);
+    }
+}
+
+#[test]
+fn operands() {
+    assert_eq!(text(&[0x00], 0), "nop");
+    assert_eq!(text(&[0x01, 0x21, 0x33], 0), "add.q x3, x1, x2");
+    assert_eq!(text(&[0x03, 0x15], 0), "inc.w x5");
+    assert_eq!(text(&[0x05, 0x43, 0x02], 0), "shl.d x4, x3");
+    assert_eq!(text(&[0x09, 0x21], 0), "cmp x1, x2");
+    assert_eq!(text(&[0x20, 0x10, 0x7f], 0), "ldi.b x1, 0x7f");
+    let mut ldi = vec![0x20, 0x13];
+    ldi.extend(0xabcdu64.to_le_bytes());
+    assert_eq!(text(&ldi, 0), "ldi.q x1, 0xabcd");
+    assert_eq!(text(&[0x21, 0x32, 0x03], 0), "ldm.q x3, [x2]");
+    assert_eq!(text(&[0x22, 0x21, 0x03], 0), "stm.q [x2], x1");
+    assert_eq!(text(&[0x23, 0x03, 0x04, 0x00], 0), "in x3, x4");
+    assert_eq!(text(&[0x24, 0x13, 0x02, 0x00], 0), "out 0x213, x3");
+    assert_eq!(text(&[0x25, 0x0f], 0), "push x15");
+    assert_eq!(text(&[0x14, 0x02], 0), "ir 2");
+    assert_eq!(text(&[0x3d, 0x01], 0), "initext 1");
+    assert_eq!(text(&[0x3f, 0x14], 0), "imask.get x4");
+}
+
+#[test]
+fn branches() {
+    let mut jc = vec![0x10, 0x44];
+    jc.extend((-0x10i32).to_le_bytes());
+    jc.resize(10, 0);
+    assert_eq!(text(&jc, 0x100), "jc.e 0xf0");
+    let inst = Instruction::decode(&jc, 0x100);
+    assert_eq!(inst.len(), 10);
+    assert_eq!(inst.operation.unwrap().target(), Some(0xf0));
+
+    let call = [0x17, 0x00, 0x00, 0x20, 0, 0, 0, 0, 0, 0];
+    assert_eq!(text(&call, 0x100), "call abs16 0x2000");
+    let cc = [0x11, 0xb3, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+    assert_eq!(text(&cc, 0x100), "cc.sm rel16 0x110");
+
+    // 第31位为符号位的原码偏移
+    assert_eq!(
+        text(&[0x13, 0x02, 0x08, 0, 0, 0x80], 0x100),
+        "loop x2, 0xf8"
+    );
+}
+
+/// 不合法的寻址方式、大小字段和截断的指令都作为`.byte`
+#[test]
+fn invalid() {
+    assert_eq!(text(&[0x0e], 0), ".byte 0x0e");
+    assert_eq!(text(&[0x10, 0x06, 0, 0, 0, 0, 0, 0, 0, 0], 0), ".byte 0x10");
+    assert_eq!(text(&[0x21, 0x32, 0x04], 0), ".byte 0x21");
+    assert_eq!(text(&[0x20, 0x13, 0x01], 0), ".byte 0x20");
+    assert_eq!(text(&[0x3f, 0x24], 0), ".byte 0x3f");
+
+    let listing: Vec<String> = disassemble(&[0x01, 0x21], 0x10)
+        .iter()
+        .map(|inst| format!("{:x} {}", inst.address, inst))
+        .collect();
+    assert_eq!(listing, ["10 .byte 0x01", "11 .byte 0x21"]);
+}
+
+/// 与核心一样忽略不使用的位，重新编码后是规范的形式
+#[test]
+fn ignored_bits() {
+    let jc = [0x10, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x20, 0x01];
+    let inst = Instruction::decode(&jc, 0);
+    assert_eq!(inst.to_string(), "jc abs32 0x100");
+    assert_eq!(inst.len(), 10);
+    assert_eq!(
+        inst.operation.unwrap().encode(0).unwrap(),
+        [0x10, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(text(&[0x25, 0xf3], 0), "push x3");
+    assert_eq!(text(&[0x23, 0x03, 0x54, 0xff], 0), "in x3, x4");
+    // 原码的-0
+    assert_eq!(text(&[0x13, 0x02, 0, 0, 0, 0x80], 0x40), "loop x2, 0x40");
+}
+
+/// 任意字节序列反汇编后字节不丢失，每条指令重新编码后解码得到相同的指令
+#[test]
+fn round_trip() {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut bytes = Vec::new();
+    for _ in 0..0x10000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        // 偏向小的操作码和0，使更多字节能被解码
+        let byte = match seed % 4 {
+            0 => (seed >> 8) as u8 % 0x40,
+            1 => 0,
+            _ => (seed >> 8) as u8,
+        };
+        bytes.push(byte);
+    }
+    let address = 0x8000;
+    let instructions = disassemble(&bytes, address);
+    let mut decoded = 0;
+    let mut offset = 0;
+    for inst in instructions.iter() {
+        assert_eq!(inst.address, address + offset as u64);
+        assert_eq!(inst.bytes, bytes[offset..offset + inst.len()]);
+        if let Some(operation) = inst.operation {
+            let bytes = operation.encode(inst.address).unwrap();
+            assert_eq!(bytes.len(), inst.len());
+            assert_eq!(
+                Operation::decode(&bytes, inst.address),
+                Some((operation, inst.len()))
+            );
+            decoded += 1;
+        }
+        offset += inst.len();
+    }
+    assert_eq!(offset, bytes.len());
+    assert!(decoded > 1000);
+}
+
+#[test]
+fn encode_range() {
+    let near = Operation::Branch {
+        opcode: 0x10,
+        cond: 0,
+        mode: BranchMode::Rel16,
+        target: 0x8000,
+    };
+    assert!(near.encode(0x100).is_some());
+    assert_eq!(near.encode(0x10_0000), None);
+    let ldi = Operation::Ldi {
+        size: 0,
+        reg: 1,
+        imm: 0x100,
+    };
+    assert_eq!(ldi.encode(0), None);
+    let port = Operation::Io {
+        opcode: 0x23,
+        reg: 2,
+        port: Port::Immediate(0x0102),
+    };
+    assert_eq!(port.encode(0), None);
+
+    // 32位原码偏移可以跨越地址0
+    let back = Operation::Loop {
+        reg: 1,
+        target: u64::MAX - 0xf,
+    };
+    assert_eq!(back.encode(0x10).unwrap(), [0x13, 0x01, 0x20, 0, 0, 0x80]);
+}
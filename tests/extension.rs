@@ -0,0 +1,128 @@
+use vcore::{
+    machine::Machine,
+    vrisc::{
+        asm::assemble,
+        base::{i_destext, i_initext},
+        vcore::{
+            intcontroller::{InterruptId, Message},
+            regs_flags::FlagRegFlag,
+        },
+    },
+};
+
+const INITEXT: u8 = 0x3d;
+const DESTEXT: u8 = 0x3e;
+
+/// `initext`和`destext`加载、卸载扩展指令集，`cpuid`报告可用、已加载的扩展指令集和它们的信息
+///
+/// InvalidInstruction的处理程序计数到x4，跳过x8字节的出错指令后返回
+#[test]
+fn load_and_unload() {
+    let source = "
+                ldi x0, 0x800
+                livt x0
+                ei
+                ldi x8, 2
+                initext 0
+                ldi x1, 6
+                ldi x2, 7
+                .byte 0x40, 0x21, 0x03  ; mul.b x3, x1, x2
+                initext 0               ; 已经加载
+                ldi x0, 5
+                cpuid
+                ldi x6, 0
+                add x6, x6, x0
+                ldi x7, 0
+                add x7, x7, x1
+                destext 0
+                destext 0               ; 已经卸载
+                ldi x8, 3
+                .byte 0x40, 0x21, 0x03  ; 卸载后不再可用
+                ldi x8, 2
+                initext 4               ; 不存在
+                ldi x0, 5
+                cpuid
+                ldi x9, 0
+                add x9, x9, x1
+                ldi x0, 6
+                ldi x1, 3
+                cpuid
+                nop
+        invalid:
+                inc x4
+                sipdump x5
+                add x5, x5, x8
+                lipdump x5
+                ir 1
+        .org 0x800
+                .qword 0, 0, 0, 0, invalid
+    ";
+    let mut machine = Machine::new("extension", 4096, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.run(200).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!(regs.x[3], 42);
+    assert_eq!(regs.x[4], 4);
+    assert_eq!(regs.imsg, Message::Instruction(INITEXT).encode());
+    // 可用的扩展指令集和加载了math之后、全部卸载之后的位图
+    assert_eq!((regs.x[6], regs.x[7], regs.x[9]), (0xf, 1, 0));
+    // string扩展指令集的起始操作码、指令数和名字
+    assert_eq!(regs.x[0], 0x5a);
+    assert_eq!(regs.x[1], 3);
+    assert_eq!(regs.x[2].to_le_bytes(), *b"string\0\0");
+    assert_eq!(machine.core(0).extensions, 0);
+}
+
+/// `cpuid`查询不存在的扩展指令集时返回0
+#[test]
+fn unknown_extension_info() {
+    let source = "
+                ldi x0, 6
+                ldi x1, 64
+                ldi x2, 0xdead
+                cpuid
+                nop
+    ";
+    let mut machine = Machine::new("extension-unknown", 4096, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[0], regs.x[1], regs.x[2]), (0, 0, 0));
+}
+
+/// 超出8位的id不会被截断成已有的扩展指令集
+#[test]
+fn extension_id_not_truncated() {
+    let source = "
+                ldi x0, 6
+                ldi x1, 0x100
+                ldi x2, 0xdead
+                cpuid
+                nop
+    ";
+    let mut machine = Machine::new("extension-truncated", 4096, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[0], regs.x[1], regs.x[2]), (0, 0, 0));
+}
+
+/// 用户态执行`initext`和`destext`产生WrongPrivilege，不改变已加载的扩展指令集
+#[test]
+fn privilege() {
+    let mut machine = Machine::new("extension-privilege", 4096, 1, true).unwrap();
+    let core = machine.core_mut(0);
+    assert_eq!(i_initext(&[INITEXT, 2], core), 2);
+    core.regs.flag |= 1 << FlagRegFlag::Privilege as u64;
+    assert_eq!(i_initext(&[INITEXT, 0], core), 0);
+    assert_eq!(
+        core.intctler.take().unwrap().id,
+        InterruptId::WrongPrivilege
+    );
+    assert_eq!(i_destext(&[DESTEXT, 2], core), 0);
+    assert_eq!(
+        core.intctler.take().unwrap().id,
+        InterruptId::WrongPrivilege
+    );
+    assert_eq!(core.extensions, 1 << 2);
+}
@@ -0,0 +1,190 @@
+use vcore::{
+    machine::Machine,
+    vrisc::{
+        asm::assemble,
+        float::{ROUND_DOWN, ROUND_NEAREST, ROUND_UP, ROUND_ZERO},
+    },
+};
+
+const FADD: u8 = 0x48;
+const FCMP: u8 = 0x4d;
+const FCVT: u8 = 0x4f;
+const FCVTFI: u8 = 0x50;
+const FCVTIF: u8 = 0x51;
+
+const OVERFLOW: u64 = 1 << 2;
+const EQUAL: u64 = 1 << 3;
+const BIGGER: u64 = 1 << 6;
+const SMALLER: u64 = 1 << 7;
+
+/// ## 执行一条浮点指令
+///
+/// 以舍入模式`mode`、f1=`f1`、f2=`f2`、x1=`x1`执行`inst`，返回执行后的机器
+fn run(mode: u64, f1: u64, f2: u64, x1: u64, inst: [u8; 3]) -> Machine {
+    let source = format!(
+        "       initext 1
+                ldi x15, {:#x}
+                .byte 0x55, 0x0f        ; fwcsr x15
+                ldi x15, {:#x}
+                .byte 0x52, 0x1f        ; fmvxf f1, x15
+                ldi x15, {:#x}
+                .byte 0x52, 0x2f        ; fmvxf f2, x15
+                ldi x1, {:#x}
+                .byte {:#x}, {:#x}, {:#x}
+                nop",
+        mode, f1, f2, x1, inst[0], inst[1], inst[2]
+    );
+    let mut machine = Machine::new(&format!("float-{:x}", inst[0]), 4096, 1, true).unwrap();
+    machine.load(0, &assemble(&source).unwrap());
+    machine.run(100).unwrap();
+    machine
+}
+
+fn f32s(x: f32) -> u64 {
+    x.to_bits() as u64
+}
+
+fn f64s(x: f64) -> u64 {
+    x.to_bits()
+}
+
+/// `fadd.s f3, f1, f2`在各个舍入模式下的结果
+fn fadd32(mode: u64, a: f32, b: f32) -> f32 {
+    let machine = run(mode, f32s(a), f32s(b), 0, [FADD, 0x21, 0x03]);
+    f32::from_bits(machine.core(0).fregs.f[3] as u32)
+}
+
+/// 结果不能精确表示时按fcsr舍入
+#[test]
+fn rounding_modes() {
+    let ulp = f32::EPSILON;
+    let above = 1.0 + ulp;
+    // 1 + 0.75ulp
+    let b = ulp * 0.75;
+    assert_eq!(fadd32(ROUND_NEAREST, 1.0, b), above);
+    assert_eq!(fadd32(ROUND_ZERO, 1.0, b), 1.0);
+    assert_eq!(fadd32(ROUND_DOWN, 1.0, b), 1.0);
+    assert_eq!(fadd32(ROUND_UP, 1.0, b), above);
+    assert_eq!(fadd32(ROUND_NEAREST, -1.0, -b), -above);
+    assert_eq!(fadd32(ROUND_ZERO, -1.0, -b), -1.0);
+    assert_eq!(fadd32(ROUND_DOWN, -1.0, -b), -above);
+    assert_eq!(fadd32(ROUND_UP, -1.0, -b), -1.0);
+    // 恰好在中间时取偶数
+    assert_eq!(fadd32(ROUND_NEAREST, 1.0, ulp / 2.0), 1.0);
+    assert_eq!(fadd32(ROUND_NEAREST, above, ulp / 2.0), 1.0 + 2.0 * ulp);
+    // 溢出时向0和向负无穷舍入得到最大的有限值
+    assert_eq!(fadd32(ROUND_NEAREST, f32::MAX, f32::MAX), f32::INFINITY);
+    assert_eq!(fadd32(ROUND_ZERO, f32::MAX, f32::MAX), f32::MAX);
+    assert_eq!(fadd32(ROUND_DOWN, f32::MAX, f32::MAX), f32::MAX);
+}
+
+/// `fcmp`设置的标志，与NaN比较时无序
+#[test]
+fn compare() {
+    let fcmp = |a: f64, b: f64| {
+        let machine = run(ROUND_NEAREST, f64s(a), f64s(b), 0, [FCMP, 0x21, 0x10]);
+        machine.core(0).regs.flag & (OVERFLOW | EQUAL | BIGGER | SMALLER)
+    };
+    assert_eq!(fcmp(1.0, 2.0), SMALLER);
+    assert_eq!(fcmp(2.0, 1.0), BIGGER);
+    assert_eq!(fcmp(0.0, -0.0), EQUAL);
+    assert_eq!(fcmp(f64::INFINITY, f64::MAX), BIGGER);
+    assert_eq!(fcmp(f64::NAN, 1.0), OVERFLOW);
+    assert_eq!(fcmp(1.0, f64::NAN), OVERFLOW);
+    assert_eq!(fcmp(f64::NAN, f64::NAN), OVERFLOW);
+
+    // f32只比较低32位
+    let machine = run(ROUND_NEAREST, f32s(1.5), f32s(1.5), 0, [FCMP, 0x21, 0x00]);
+    assert_eq!(machine.core(0).regs.flag & EQUAL, EQUAL);
+}
+
+/// 浮点数转整数，超出范围时饱和并设置Overflow标志
+#[test]
+fn float_to_int() {
+    // fcvtfi x2, f1，`format`为inst[2]：第0位f64，第1位有符号，第4、5位整数宽度
+    let fcvtfi = |mode: u64, v: f64, format: u8| {
+        let machine = run(mode, f64s(v), 0, 0, [FCVTFI, 0x21, format | 1]);
+        let regs = &machine.core(0).regs;
+        (regs.x[2], regs.flag & OVERFLOW != 0)
+    };
+    const I32: u8 = 0x22;
+    const U8: u8 = 0x00;
+    const I64: u8 = 0x32;
+    const U64: u8 = 0x30;
+    assert_eq!(fcvtfi(ROUND_NEAREST, 1e20, I32), (0x7fff_ffff, true));
+    assert_eq!(fcvtfi(ROUND_NEAREST, -1e20, I32), (0x8000_0000, true));
+    assert_eq!(
+        fcvtfi(ROUND_NEAREST, -2147483648.0, I32),
+        (0x8000_0000, false)
+    );
+    assert_eq!(fcvtfi(ROUND_NEAREST, f64::NAN, I32), (0, true));
+    assert_eq!(fcvtfi(ROUND_NEAREST, -1.0, U8), (0, true));
+    assert_eq!(fcvtfi(ROUND_NEAREST, 255.4, U8), (255, false));
+    // 255.5就近舍入为256，超出u8
+    assert_eq!(fcvtfi(ROUND_NEAREST, 255.5, U8), (255, true));
+    assert_eq!(fcvtfi(ROUND_ZERO, 255.5, U8), (255, false));
+    assert_eq!(fcvtfi(ROUND_NEAREST, 2.5, U8), (2, false));
+    assert_eq!(fcvtfi(ROUND_UP, 2.5, U8), (3, false));
+    assert_eq!(fcvtfi(ROUND_DOWN, -2.5, I32), (0xffff_fffd, false));
+    assert_eq!(fcvtfi(ROUND_NEAREST, 9.3e18, I64), (i64::MAX as u64, true));
+    assert_eq!(fcvtfi(ROUND_NEAREST, -9.3e18, I64), (1 << 63, true));
+    assert_eq!(fcvtfi(ROUND_NEAREST, 1.9e19, U64), (u64::MAX, true));
+    assert_eq!(
+        fcvtfi(ROUND_NEAREST, 1.8e19, U64),
+        (18_000_000_000_000_000_000, false)
+    );
+
+    // f32的源操作数
+    let machine = run(ROUND_NEAREST, f32s(-3.75), 0, 0, [FCVTFI, 0x21, 0x32]);
+    assert_eq!(machine.core(0).regs.x[2], -4i64 as u64);
+}
+
+/// 整数转浮点数按舍入模式舍入
+#[test]
+fn int_to_float() {
+    // fcvtif f2, x1
+    let fcvtif = |mode: u64, x: u64, format: u8| run(mode, 0, 0, x, [FCVTIF, 0x21, format]);
+    let f32_of = |machine: Machine| f32::from_bits(machine.core(0).fregs.f[2] as u32);
+    let f64_of = |machine: Machine| f64::from_bits(machine.core(0).fregs.f[2]);
+    let below = ((1u64 << 24) - 1) << 40;
+    assert_eq!(
+        f32_of(fcvtif(ROUND_NEAREST, u64::MAX, 0x30)),
+        18446744073709551616.0
+    );
+    assert_eq!(f32_of(fcvtif(ROUND_ZERO, u64::MAX, 0x30)), below as f32);
+    assert_eq!(f32_of(fcvtif(ROUND_DOWN, u64::MAX, 0x30)), below as f32);
+    // 有符号时按宽度符号扩展，高于宽度的位被忽略
+    assert_eq!(f32_of(fcvtif(ROUND_NEAREST, 0x1_80, 0x02)), -128.0);
+    assert_eq!(f64_of(fcvtif(ROUND_NEAREST, 0x1_80, 0x01)), 128.0);
+    assert_eq!(
+        f64_of(fcvtif(ROUND_NEAREST, 1 << 63, 0x33)),
+        -9223372036854775808.0
+    );
+    // 2^53 + 1不能用f64精确表示
+    let odd = (1u64 << 53) + 1;
+    assert_eq!(
+        f64_of(fcvtif(ROUND_NEAREST, odd, 0x31)),
+        (1u64 << 53) as f64
+    );
+    assert_eq!(
+        f64_of(fcvtif(ROUND_UP, odd, 0x31)),
+        ((1u64 << 53) + 2) as f64
+    );
+}
+
+/// `fcvt`的格式在`inst[2]`的高4位
+#[test]
+fn convert_format() {
+    let fcvt = |mode: u64, f1: u64, format: u8| {
+        run(mode, f1, 0, 0, [FCVT, 0x21, format]).core(0).fregs.f[2]
+    };
+    assert_eq!(fcvt(ROUND_NEAREST, f64s(1.5), 0x00), f32s(1.5));
+    assert_eq!(fcvt(ROUND_NEAREST, f64s(1e300), 0x00), f32s(f32::INFINITY));
+    assert_eq!(fcvt(ROUND_ZERO, f64s(1e300), 0x00), f32s(f32::MAX));
+    assert_eq!(fcvt(ROUND_NEAREST, f32s(0.1), 0x10), f64s(0.1f32 as f64));
+    // 低4位被忽略
+    assert_eq!(fcvt(ROUND_NEAREST, f32s(0.1), 0x13), f64s(0.1f32 as f64));
+    // 格式不合法时产生InvalidInstruction，不执行
+    let machine = run(ROUND_NEAREST, f64s(1.5), 0, 0, [FCVT, 0x21, 0x20]);
+    assert_eq!(machine.core(0).fregs.f[2], 0);
+}
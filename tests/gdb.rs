@@ -0,0 +1,80 @@
+#![cfg(feature = "debugger")]
+
+use std::io::{self, Cursor, Read, Write};
+
+use vcore::debugger::{
+    debug::Regs,
+    gdb::{checksum, encode_packet, read_packet, register, target_xml, Incoming, GDB_REGISTERS},
+};
+
+/// 从`input`读取，写入的内容记录在`output`
+struct Pipe {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Pipe {
+    fn new(input: &[u8]) -> Self {
+        Self {
+            input: Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn encode() {
+    assert_eq!(checksum(b"OK"), 0x9a);
+    assert_eq!(encode_packet(b"OK"), b"$OK#9a");
+    assert_eq!(encode_packet(b""), b"$#00");
+    // `}`后跟异或0x20的字节
+    assert_eq!(encode_packet(b"a#b"), b"$a}\x03b#43");
+}
+
+/// 跳过确认，校验和错误时回复`-`，正确时回复`+`
+#[test]
+fn read() {
+    let mut pipe = Pipe::new(b"+$g#00$g#67\x03");
+    assert_eq!(
+        read_packet(&mut pipe).unwrap(),
+        Some(Incoming::Packet(b"g".to_vec()))
+    );
+    assert_eq!(pipe.output, b"-+");
+    assert_eq!(read_packet(&mut pipe).unwrap(), Some(Incoming::Interrupt));
+    assert_eq!(read_packet(&mut pipe).unwrap(), None);
+
+    // 包未结束时连接关闭
+    let mut pipe = Pipe::new(b"$qSupported");
+    assert_eq!(read_packet(&mut pipe).unwrap(), None);
+}
+
+/// 寄存器号与target description一致
+#[test]
+fn registers() {
+    assert_eq!(register(0), Some(Regs::X(0)));
+    assert_eq!(register(16), Some(Regs::Ip));
+    assert_eq!(register(25), Some(Regs::F(0)));
+    assert_eq!(register(41), Some(Regs::Fcsr));
+    assert_eq!(register(GDB_REGISTERS), None);
+
+    let xml = target_xml();
+    assert_eq!(xml.matches("<reg ").count(), GDB_REGISTERS);
+    assert!(xml.contains("<reg name=\"ip\" bitsize=\"64\" type=\"code_ptr\" regnum=\"16\"/>"));
+    assert!(xml.contains("<feature name=\"org.vrisc.float\">"));
+}
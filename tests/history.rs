@@ -0,0 +1,125 @@
+#![cfg(feature = "debugger")]
+
+use vcore::{
+    debugger::{
+        debug::VdbApi,
+        history::{Checkpoint, History, CHECKPOINT_INTERVAL, MAX_CHECKPOINTS},
+    },
+    machine::{snapshot::CoreState, Machine},
+    utils::shared::Addressable,
+    vrisc::{
+        asm::assemble,
+        vcore::{
+            intcontroller::{InterruptId, Pending},
+            DebugMode,
+        },
+    },
+};
+
+fn checkpoint(state: &CoreState, count: u64) -> Checkpoint {
+    let mut state = state.clone();
+    state.inst_count = count;
+    Checkpoint::new(state)
+}
+
+fn device(port: u64) -> Pending {
+    Pending {
+        id: InterruptId::DeviceCommunication,
+        msg: port,
+    }
+}
+
+/// 检查点按间隔保存，超过数量上限时丢弃最早的检查点和之前的中断
+#[test]
+fn checkpoints_and_interrupts() {
+    let machine = Machine::new("history", 4096, 1, true).unwrap();
+    let state = machine.snapshot().cores[0].clone();
+    let mut history = History::new();
+    assert!(history.due(1));
+    history.checkpoint(checkpoint(&state, 1));
+    assert!(!history.due(CHECKPOINT_INTERVAL));
+    assert!(history.due(CHECKPOINT_INTERVAL + 1));
+    history.interrupt(5, device(256));
+    for i in 1..MAX_CHECKPOINTS as u64 {
+        history.checkpoint(checkpoint(&state, 1 + i * CHECKPOINT_INTERVAL));
+    }
+    assert_eq!(history.earliest(), Some(1));
+    history.interrupt(2 * CHECKPOINT_INTERVAL, device(257));
+
+    history.checkpoint(checkpoint(
+        &state,
+        1 + MAX_CHECKPOINTS as u64 * CHECKPOINT_INTERVAL,
+    ));
+    assert_eq!(history.earliest(), Some(1 + CHECKPOINT_INTERVAL));
+    assert_eq!(
+        history.interrupts(0, u64::MAX),
+        [(2 * CHECKPOINT_INTERVAL, device(257))]
+    );
+    assert!(history.before(CHECKPOINT_INTERVAL).is_none());
+    let before = history.before(2 * CHECKPOINT_INTERVAL + 1).unwrap();
+    assert_eq!(before.state.inst_count, 1 + 2 * CHECKPOINT_INTERVAL);
+
+    // 回到之前的指令后，之后的历史被丢弃
+    history.truncate(2 * CHECKPOINT_INTERVAL);
+    assert_eq!(history.earliest(), Some(1 + CHECKPOINT_INTERVAL));
+    assert!(history.due(2 * CHECKPOINT_INTERVAL + 1));
+    assert_eq!(history.interrupts(0, u64::MAX).len(), 1);
+    history.clear();
+    assert_eq!(history.earliest(), None);
+}
+
+/// 反向单步回到之前的寄存器和内存，只恢复核心自己写入过的页
+#[test]
+fn reverse_step() {
+    let source = "
+        ldi x1, 0x3ffc              ; 跨越两页
+        ldi x2, 0x1122334455667788
+        stm.q [x1], x2
+        ldi x2, 0xaabb
+        stm.q [x1], x2
+        nop
+    ";
+    let mut machine = Machine::new("history-reverse", 64 * 1024, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.set_debug();
+    let request = |machine: &mut Machine, api| machine.debug_request(0, api).unwrap();
+    let stored = |machine: &Machine| {
+        let memory = machine.memory().borrow();
+        u64::from_le_bytes(memory.slice(0x3ffc, 8).try_into().unwrap())
+    };
+    assert_eq!(
+        request(&mut machine, VdbApi::DebugMode(DebugMode::Step)),
+        VdbApi::Ok
+    );
+    for _ in 0..5 {
+        assert_eq!(request(&mut machine, VdbApi::Continue), VdbApi::Ok);
+    }
+    assert_eq!(machine.instruction_count(0), 5);
+    assert_eq!(stored(&machine), 0xaabb);
+    // 核心没有写入过的页由其它核心或设备写入
+    machine
+        .memory_mut()
+        .borrow_mut()
+        .write_slice(0x8000, &[0x5a]);
+
+    assert_eq!(request(&mut machine, VdbApi::ReverseStep), VdbApi::Ok);
+    assert_eq!(machine.instruction_count(0), 4);
+    assert_eq!(machine.core(0).regs.x[2], 0xaabb);
+    assert_eq!(stored(&machine), 0x1122334455667788);
+
+    for _ in 0..2 {
+        assert_eq!(request(&mut machine, VdbApi::ReverseStep), VdbApi::Ok);
+    }
+    assert_eq!(machine.instruction_count(0), 2);
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[1], regs.x[2]), (0x3ffc, 0x1122334455667788));
+    assert_eq!(stored(&machine), 0);
+    assert_eq!(*machine.memory().borrow().at(0x8000), 0x5a);
+
+    // 再次向前执行得到相同的结果
+    for _ in 0..3 {
+        assert_eq!(request(&mut machine, VdbApi::Continue), VdbApi::Ok);
+    }
+    assert_eq!(stored(&machine), 0xaabb);
+    assert_eq!(machine.core(0).regs.x[2], 0xaabb);
+}
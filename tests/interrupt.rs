@@ -0,0 +1,179 @@
+use vcore::{
+    machine::Machine,
+    vrisc::{
+        asm::assemble,
+        vcore::intcontroller::{Access, InterruptController, InterruptId, Message},
+    },
+};
+
+fn drain(intctler: &mut InterruptController) -> Vec<(InterruptId, u64)> {
+    let mut delivered = Vec::new();
+    while let Some(intid) = intctler.interrupted() {
+        let pending = intctler.take().unwrap();
+        assert_eq!(pending.id, intid);
+        delivered.push((pending.id, pending.msg));
+    }
+    delivered
+}
+
+/// 时钟中断不会覆盖之前产生的设备中断和它的消息
+#[test]
+fn clock_does_not_overwrite_device() {
+    let mut intctler = InterruptController::new();
+    intctler.interrupt_with(InterruptId::DeviceCommunication, 7);
+    intctler.interrupt(InterruptId::Clock);
+    assert_eq!(
+        drain(&mut intctler),
+        [
+            (InterruptId::DeviceCommunication, 7),
+            (InterruptId::Clock, 0),
+        ]
+    );
+}
+
+/// 异常先于设备中断，设备中断按产生的顺序各自带着消息进入，时钟中断合并
+#[test]
+fn priority_and_order() {
+    let mut intctler = InterruptController::new();
+    intctler.interrupt(InterruptId::Clock);
+    intctler.interrupt_with(InterruptId::DeviceCommunication, 3);
+    intctler.interrupt(InterruptId::Clock);
+    intctler.interrupt_with(InterruptId::DeviceCommunication, 5);
+    intctler.interrupt_with(InterruptId::PageOrTableUnreadable, 0x4000);
+    intctler.interrupt_with(InterruptId::PageOrTableUnreadable, 0x8000);
+    assert_eq!(
+        drain(&mut intctler),
+        [
+            (InterruptId::PageOrTableUnreadable, 0x4000),
+            (InterruptId::DeviceCommunication, 3),
+            (InterruptId::DeviceCommunication, 5),
+            (InterruptId::Clock, 0),
+        ]
+    );
+}
+
+/// 被屏蔽的中断保持待处理，异常不能被屏蔽
+#[test]
+fn masking() {
+    let mut intctler = InterruptController::new();
+    intctler.set_mask((1 << InterruptId::Clock as u64) | (1 << InterruptId::WrongPrivilege as u64));
+    intctler.interrupt(InterruptId::Clock);
+    assert_eq!(intctler.interrupted(), None);
+    intctler.interrupt(InterruptId::WrongPrivilege);
+    assert_eq!(drain(&mut intctler), [(InterruptId::WrongPrivilege, 0)]);
+    assert_eq!(intctler.pending().count(), 1);
+    intctler.set_mask(0);
+    assert_eq!(drain(&mut intctler), [(InterruptId::Clock, 0)]);
+}
+
+/// 同一种异常在进入之前只保留第一次和它的消息，进入之后再次产生时带上新的消息
+#[test]
+fn exception_merge() {
+    let mut intctler = InterruptController::new();
+    let first = Message::Access(0x4000, Access::Read).encode();
+    let second = Message::Access(0x8000, Access::Write).encode();
+    intctler.interrupt_with(InterruptId::InaccessibleAddress, first);
+    intctler.interrupt_with(InterruptId::InaccessibleAddress, second);
+    intctler.interrupt_with(InterruptId::InvalidInstruction, 1);
+    assert_eq!(intctler.pending().count(), 2);
+    assert_eq!(intctler.take().unwrap().msg, first);
+    intctler.interrupt_with(InterruptId::InaccessibleAddress, second);
+    assert_eq!(
+        drain(&mut intctler),
+        [
+            (InterruptId::InvalidInstruction, 1),
+            (InterruptId::InaccessibleAddress, second),
+        ]
+    );
+}
+
+/// 被屏蔽的设备中断和核间中断按消息合并，待处理队列不会无限增长
+#[test]
+fn device_bounded() {
+    let mut intctler = InterruptController::new();
+    intctler.set_mask(
+        (1 << InterruptId::DeviceCommunication as u64) | (1 << InterruptId::InterProcessor as u64),
+    );
+    for _ in 0..100 {
+        intctler.interrupt_with(InterruptId::DeviceCommunication, 3);
+        intctler.interrupt_with(InterruptId::InterProcessor, Message::Core(1).encode());
+    }
+    intctler.interrupt_with(InterruptId::DeviceCommunication, 5);
+    intctler.interrupt_with(InterruptId::InterProcessor, Message::Core(2).encode());
+    assert_eq!(intctler.pending().count(), 4);
+    assert_eq!(intctler.interrupted(), None);
+
+    intctler.set_mask(0);
+    let device = intctler.take().unwrap();
+    assert_eq!(
+        (device.id, device.msg),
+        (InterruptId::DeviceCommunication, 3)
+    );
+    // 进入之后同一个端口再次产生的中断重新排队
+    intctler.interrupt_with(InterruptId::DeviceCommunication, 3);
+    assert_eq!(
+        drain(&mut intctler),
+        [
+            (InterruptId::InterProcessor, Message::Core(1).encode()),
+            (InterruptId::DeviceCommunication, 5),
+            (InterruptId::InterProcessor, Message::Core(2).encode()),
+            (InterruptId::DeviceCommunication, 3),
+        ]
+    );
+}
+
+/// imsg的编码
+#[test]
+fn message_encoding() {
+    assert_eq!(
+        Message::Access(0x8000_0000_0000_4008, Access::Write).encode(),
+        0x8100_0000_0000_4008
+    );
+    assert_eq!(
+        Message::Access(0x4000, Access::Execute).encode(),
+        0x0200_0000_0000_4000
+    );
+    assert_eq!(Message::Instruction(0x3f).encode(), 0x0300_0000_0000_003f);
+    assert_eq!(Message::Port(9).encode(), 9);
+    assert_eq!(Message::None.encode(), 0);
+}
+
+/// ## 转存的ip
+///
+/// 在两条指令之间进入的中断返回到下一条指令，异常返回到出错的指令并重新执行，
+/// 处理程序的第一条指令不会被跳过
+#[test]
+fn interrupt_return_address() {
+    let source = "
+                ldi x0, 0x800
+                livt x0
+                ei
+                ldi x2, 0x20000
+                jc abs16 body
+        .org 0x100
+        body:   inc x1
+                inc x1
+                ldm x3, [x2]        ; 0x104，内存以外的地址
+                nop
+        fault:  sipdump x5
+                ldi x2, 0x800
+                ir 1
+        device: sipdump x6
+                ir 1
+        .org 0x800
+                .qword 0x1234, fault, 0, 0, 0, 0, 0, 0, 0, device
+    ";
+    let mut machine = Machine::new("interrupt-return", 64 * 1024, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    while machine.core(0).regs.x[1] != 1 {
+        machine.step().unwrap();
+    }
+    machine
+        .core_mut(0)
+        .intctler
+        .interrupt_with(InterruptId::DeviceCommunication, 0);
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[6], regs.x[1]), (0x102, 2));
+    assert_eq!((regs.x[5], regs.x[3]), (0x104, 0x1234));
+}
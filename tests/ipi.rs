@@ -0,0 +1,77 @@
+use vcore::machine::Machine;
+
+mod common;
+
+use common::{enable_interrupt, jc, ldi, IVT, MEMORY};
+
+const WAIT: u64 = 0x100;
+const SENDER: u64 = 0x200;
+const HANDLER: u64 = 0x300;
+const INTER_PROCESSOR: u64 = 11;
+
+/// 向固定端口3写入`data`发送核间中断，然后进入nop状态
+fn send_ipi(program: &mut Vec<u8>, data: u64) {
+    ldi(program, 1, data);
+    ldi(program, 2, 3);
+    program.extend([0x24, 0x01, 0x02, 0x00]); // out x1, x2
+    program.push(0x00);
+}
+
+/// 核心`from`执行`sender`，其余核心打开中断后自旋等待，
+/// 核间中断处理程序递增x3后进入nop状态
+fn machine(instance: &str, cores: usize, from: usize, sender: &[u8]) -> Machine {
+    let mut machine = Machine::new(instance, MEMORY, cores, true).unwrap();
+    machine.load(SENDER, sender);
+
+    let mut wait = Vec::new();
+    enable_interrupt(&mut wait);
+    let spin = WAIT + wait.len() as u64;
+    jc(&mut wait, 0, spin); // always
+    machine.load(WAIT, &wait);
+
+    let entry = |id: usize| if id == from { SENDER } else { WAIT };
+    // 核心0从地址0开始运行
+    let mut boot = Vec::new();
+    jc(&mut boot, 0, entry(0)); // always
+    machine.load(0, &boot);
+    machine.load(HANDLER, &[0x03, 0x33, 0x00]); // inc x3; nop
+    machine.load(IVT + 8 * INTER_PROCESSOR, &HANDLER.to_le_bytes());
+    for id in 1..cores {
+        machine.start_core(id, entry(id));
+    }
+    machine.run(10_000).unwrap();
+    machine
+}
+
+#[test]
+fn ipi_to_target() {
+    let mut sender = Vec::new();
+    send_ipi(&mut sender, 2);
+    let machine = machine("ipi-target", 3, 1, &sender);
+    assert_eq!(machine.core(0).regs.x[3], 0);
+    assert_eq!(machine.core(1).regs.x[3], 0);
+    assert_eq!(machine.core(2).regs.x[3], 1);
+    // imsg为发送核间中断的核心号
+    assert_eq!(machine.core(2).regs.imsg, 1);
+}
+
+#[test]
+fn ipi_broadcast() {
+    let mut sender = Vec::new();
+    send_ipi(&mut sender, 2 << 32);
+    let machine = machine("ipi-broadcast", 4, 0, &sender);
+    for id in 1..4 {
+        assert_eq!(machine.core(id).regs.x[3], 1);
+    }
+    assert_eq!(machine.core(0).regs.x[3], 0);
+}
+
+#[test]
+fn self_ipi() {
+    let mut sender = Vec::new();
+    enable_interrupt(&mut sender);
+    send_ipi(&mut sender, 1 << 32);
+    let machine = machine("ipi-self", 2, 0, &sender);
+    assert_eq!(machine.core(0).regs.x[3], 1);
+    assert_eq!(machine.core(1).regs.x[3], 0);
+}
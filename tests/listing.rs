@@ -0,0 +1,116 @@
+use vcore::vrisc::{
+    disasm::{BranchMode, Operation},
+    listing::Listing,
+};
+
+const BASE: u64 = 0x1000;
+
+fn branch(opcode: u8, target: u64) -> Operation {
+    Operation::Branch {
+        opcode,
+        cond: 0,
+        mode: BranchMode::Rel32,
+        target,
+    }
+}
+
+/// 依次编码从`BASE`开始的指令
+fn assemble(program: &[Operation]) -> Vec<u8> {
+    let mut rom = Vec::new();
+    for operation in program {
+        rom.extend(operation.encode(BASE + rom.len() as u64).unwrap());
+    }
+    rom
+}
+
+/// ```text
+/// 0x1000 call 0x1020
+/// 0x100a call 0x1030
+/// 0x1014 jc   0x1014
+/// 0x101e nop
+/// 0x101f nop
+/// 0x1020 call 0x1030
+/// 0x102a ret
+/// 0x102b nop × 5
+/// 0x1030 loop x1, 0x1030
+/// 0x1036 ret
+/// ```
+fn rom() -> Vec<u8> {
+    let mut program = vec![
+        branch(0x17, 0x1020),
+        branch(0x17, 0x1030),
+        branch(0x10, 0x1014),
+        Operation::Plain(0),
+        Operation::Plain(0),
+        branch(0x17, 0x1030),
+        Operation::Plain(0x18),
+    ];
+    program.extend([Operation::Plain(0); 5]);
+    program.extend([
+        Operation::Loop {
+            reg: 1,
+            target: 0x1030,
+        },
+        Operation::Plain(0x18),
+    ]);
+    assemble(&program)
+}
+
+#[test]
+fn labels_and_calls() {
+    let listing = Listing::new(&rom(), BASE);
+    let labels: Vec<(u64, &str)> = listing
+        .labels
+        .iter()
+        .map(|(&addr, label)| (addr, label.as_str()))
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            (0x1000, "start"),
+            (0x1014, "loc_1014"),
+            (0x1020, "sub_1020"),
+            (0x1030, "sub_1030"),
+        ]
+    );
+
+    let mut graph = Vec::new();
+    listing.write_call_graph(&mut graph).unwrap();
+    assert_eq!(
+        String::from_utf8(graph).unwrap(),
+        "start -> sub_1020, sub_1030\nsub_1020 -> sub_1030\nsub_1030\n"
+    );
+}
+
+#[test]
+fn write() {
+    let listing = Listing::new(&rom(), BASE);
+    let mut out = Vec::new();
+    listing.write(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "start:");
+    assert_eq!(
+        lines[1],
+        "    00001000  17 04 20 00 00 00 00 00 00 00  call sub_1020"
+    );
+    assert!(lines.contains(&"    00001014  10 04 00 00 00 00 00 00 00 00  jc loc_1014"));
+    // 5个nop中间的3个被省略
+    let nops = lines.iter().filter(|line| line.ends_with("nop")).count();
+    assert_eq!(nops, 4);
+    assert_eq!(lines.iter().filter(|line| **line == "    *").count(), 1);
+    assert!(lines.contains(&"    00001030  13 01 00 00 00 00              loop x1, sub_1030"));
+}
+
+/// 镜像外和指令中间的目标不作为标签
+#[test]
+fn outside() {
+    let rom = assemble(&[branch(0x17, 0x2000), branch(0x10, 0x1003)]);
+    let listing = Listing::new(&rom, BASE);
+    assert_eq!(listing.labels.len(), 1);
+    assert_eq!(
+        listing.calls[&BASE].iter().copied().collect::<Vec<_>>(),
+        [0x2000]
+    );
+    assert_eq!(listing.name(0x2000), "0x2000");
+}
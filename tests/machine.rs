@@ -0,0 +1,42 @@
+use vcore::{
+    api::interface::Addressable,
+    machine::{Machine, StepResult},
+    vrisc::asm::assemble,
+};
+
+/// 创建虚拟机，单步执行并检查寄存器和内存
+#[test]
+fn boot_and_step() {
+    let source = "
+            ldi x1, 0x20
+            ldi x2, 0x1234
+            stm.q [x1], x2
+            inc x2
+            nop
+    ";
+    let mut machine = Machine::new("machine-boot", 4096, 2, true).unwrap();
+    assert_eq!(machine.total_core(), 2);
+    machine.load(0, &assemble(source).unwrap());
+
+    assert_eq!(machine.step_core(0).unwrap(), StepResult::Executed);
+    assert_eq!(machine.core(0).regs.x[1], 0x20);
+    assert_eq!(machine.instruction_count(0), 1);
+    // core#1没有被启动
+    assert_eq!(machine.step_core(1).unwrap(), StepResult::Waiting);
+
+    assert_eq!(machine.step().unwrap(), 1);
+    assert_eq!(machine.run(100).unwrap(), 3);
+    assert_eq!(machine.core(0).regs.x[2], 0x1235);
+    assert_eq!(machine.instruction_count(0), 5);
+    assert_eq!(machine.step_core(0).unwrap(), StepResult::Idle);
+    let memory = machine.memory().borrow();
+    assert_eq!(memory.slice(0x20, 8), 0x1234u64.to_le_bytes());
+}
+
+/// 没有核心或共享内存无法创建时返回错误
+#[test]
+fn invalid_config() {
+    assert!(Machine::new("machine-no-core", 4096, 0, true).is_err());
+    assert!(Machine::new("machine-no-memory", 0, 1, true).is_err());
+    assert!(Machine::new("machine/invalid", 4096, 1, true).is_err());
+}
@@ -0,0 +1,108 @@
+use vcore::{
+    machine::Machine,
+    vrisc::{asm::assemble, vcore::intcontroller::Message},
+};
+
+const MUL: u8 = 0x40;
+const IMUL: u8 = 0x41;
+const MULH: u8 = 0x42;
+const IMULH: u8 = 0x43;
+const DIV: u8 = 0x44;
+const IDIV: u8 = 0x45;
+const REM: u8 = 0x46;
+const IREM: u8 = 0x47;
+
+const OVERFLOW: u64 = 1 << 2;
+
+/// 以x1=`a`、x2=`b`执行`x3 = x1 op x2`，x3原为`0xdead`，返回x3和Overflow标志
+fn math(opcode: u8, width: u8, a: u64, b: u64) -> (u64, bool) {
+    let source = format!(
+        "       ldi x1, {:#x}
+                ldi x2, {:#x}
+                ldi x3, 0xdead
+                initext 0
+                .byte {:#x}, 0x21, {:#x}
+                nop",
+        a,
+        b,
+        opcode,
+        width << 4 | 3
+    );
+    let mut machine = Machine::new(&format!("math-{:x}-{}", opcode, width), 4096, 1, true).unwrap();
+    machine.load(0, &assemble(&source).unwrap());
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    (regs.x[3], regs.flag & OVERFLOW != 0)
+}
+
+#[test]
+fn multiply() {
+    // 每种宽度只取低位，结果超出宽度时溢出
+    assert_eq!(math(MUL, 0, 0x10, 0x0f), (0xf0, false));
+    assert_eq!(math(MUL, 0, 0x10, 0x10), (0x00, true));
+    assert_eq!(math(MUL, 1, 0x1234, 0x3), (0x369c, false));
+    assert_eq!(math(MUL, 2, 0xffff_ffff, 2), (0xffff_fffe, true));
+    assert_eq!(math(MUL, 3, 1 << 32, 1 << 32), (0, true));
+    // 高于宽度的位被忽略
+    assert_eq!(math(MUL, 0, 0x1_03, 0x1_05), (0x0f, false));
+
+    assert_eq!(math(IMUL, 0, 0xff, 0x7f), (0x81, false));
+    assert_eq!(math(IMUL, 0, 0x80, 0xff), (0x80, true));
+    assert_eq!(math(IMUL, 1, 0xfffe, 0x0003), (0xfffa, false));
+    assert_eq!(math(IMUL, 3, u64::MAX, u64::MAX), (1, false));
+    assert_eq!(math(IMUL, 3, 1 << 62, 2), (1 << 63, true));
+
+    assert_eq!(math(MULH, 0, 0x80, 0x04), (0x02, false));
+    assert_eq!(math(MULH, 3, u64::MAX, u64::MAX), (u64::MAX - 1, false));
+    assert_eq!(math(IMULH, 0, 0x80, 0x04), (0xfe, false));
+    assert_eq!(math(IMULH, 2, 0xffff_ffff, 0xffff_ffff), (0, false));
+}
+
+#[test]
+fn divide() {
+    assert_eq!(math(DIV, 0, 0xff, 0x10), (0x0f, false));
+    assert_eq!(math(DIV, 1, 0xffff, 0x100), (0xff, false));
+    assert_eq!(math(DIV, 3, u64::MAX, 3), (0x5555_5555_5555_5555, false));
+    assert_eq!(math(REM, 2, 100, 7), (2, false));
+    assert_eq!(math(REM, 3, u64::MAX, 10), (5, false));
+
+    // 有符号除法向0取整，余数与被除数同号
+    assert_eq!(math(IDIV, 0, 0xf9, 0x02), (0xfd, false));
+    assert_eq!(math(IREM, 0, 0xf9, 0x02), (0xff, false));
+    assert_eq!(math(IDIV, 2, 7, 0xffff_fffe), (0xffff_fffd, false));
+    assert_eq!(math(IREM, 2, 7, 0xffff_fffe), (1, false));
+    // 最小值除以-1
+    assert_eq!(math(IDIV, 0, 0x80, 0xff), (0x80, true));
+    assert_eq!(math(IDIV, 3, 1 << 63, u64::MAX), (1 << 63, true));
+    assert_eq!(math(IREM, 1, 0x8000, 0xffff), (0, false));
+}
+
+/// 除数为0时产生DivideByZero，不写入结果，返回后重新执行
+#[test]
+fn divide_by_zero() {
+    let source = "
+                ldi x0, 0x800
+                livt x0
+                ei
+                ldi x1, 100
+                ldi x3, 0xdead
+                initext 0
+                .byte 0x44, 0x21, 0x33      ; div x3, x1, x2（0x23）
+                nop
+        handler:
+                sipdump x5
+                ldi x6, 0
+                add x6, x6, x3
+                ldi x2, 7
+                ir 1
+        .org 0x800
+                .qword 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, handler
+    ";
+    let mut machine = Machine::new("math-divide-by-zero", 4096, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!(regs.imsg, Message::Instruction(DIV).encode());
+    assert_eq!((regs.x[5], regs.x[6]), (0x23, 0xdead));
+    assert_eq!(regs.x[3], 14);
+}
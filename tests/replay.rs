@@ -0,0 +1,127 @@
+use std::{env, fs, thread, time::Duration};
+
+use vcore::{
+    api::device::char::CharacterDevice,
+    machine::{
+        event::{Event, Recorder, TimedEvent},
+        Machine,
+    },
+    utils::shared::Addressable,
+};
+
+mod common;
+
+use common::{enable_interrupt, install_handlers, jc, out, MEMORY, PORT};
+
+/// 打开中断、每20微秒产生一次时钟中断，然后不断递增x5
+fn machine(instance: &str) -> Machine {
+    let mut p = Vec::new();
+    enable_interrupt(&mut p);
+    out(&mut p, (2 << 60) | 20, 4);
+    let spin = p.len() as u64;
+    p.extend([0x03, 0x35]); // inc x5
+    jc(&mut p, 0, spin); // always
+
+    let mut machine = Machine::new(instance, MEMORY, 1, false).unwrap();
+    machine.load(0, &p);
+    install_handlers(&mut machine);
+    machine
+}
+
+/// 记录使用真实时间和外部设备的运行，重放后得到完全相同的状态
+#[test]
+fn replay_recorded_run() {
+    let log = env::temp_dir().join("vcore-replay-test.log");
+    let mut recorded = machine("replay-record");
+    recorded.set_recorder(Recorder::create(&log).unwrap());
+    let device = thread::spawn(|| {
+        let mut dev = CharacterDevice::new("replay-record");
+        dev.input(b"vcore!");
+    });
+    while !device.is_finished() {
+        recorded.run(1000).unwrap();
+    }
+    device.join().unwrap();
+    recorded.run(100_000).unwrap();
+    let count = recorded.instruction_count(0);
+
+    let events = TimedEvent::load(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+    assert!(events.iter().any(|e| e.event == Event::Clock));
+    assert!(events.iter().any(|e| e.event == Event::Link(PORT)));
+
+    let mut replayed = machine("replay-replay");
+    replayed.replay(events);
+    replayed.run(count).unwrap();
+    assert_eq!(replayed.instruction_count(0), count);
+    assert_eq!(recorded.core(0).regs, replayed.core(0).regs);
+    assert_eq!(
+        recorded.memory().borrow().slice(0, MEMORY as u64),
+        replayed.memory().borrow().slice(0, MEMORY as u64)
+    );
+    assert!(replayed.core(0).regs.x[3] > 0);
+    assert_eq!(replayed.core(0).regs.x[8].to_le_bytes()[..6], *b"vcore!");
+}
+
+/// 读取的微秒计数器被记录下来，重放时读到相同的值而不是虚拟时间
+#[test]
+fn replay_timer_read() {
+    let mut p = Vec::new();
+    out(&mut p, 5 << 60, 4);
+    p.extend([0x23, 0x07, 0x02, 0x00]); // in x7, x2
+    p.push(0x00);
+
+    let log = env::temp_dir().join("vcore-replay-time-test.log");
+    let mut recorded = Machine::new("replay-time-record", MEMORY, 1, true).unwrap();
+    recorded.load(0, &p);
+    recorded.set_recorder(Recorder::create(&log).unwrap());
+    recorded.run(2).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    recorded.run(100).unwrap();
+    let usec = recorded.core(0).regs.x[7];
+    assert!(usec >= 5000);
+
+    let events = TimedEvent::load(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+    assert_eq!(events, [TimedEvent::new(0, 3, Event::Time(usec))]);
+
+    let mut replayed = Machine::new("replay-time-replay", MEMORY, 1, true).unwrap();
+    replayed.load(0, &p);
+    replayed.replay(events);
+    replayed.run(100).unwrap();
+    assert_eq!(replayed.core(0).regs.x[7], usec);
+}
+
+/// 写入记录文件失败时`run`返回错误而不是panic
+#[cfg(target_os = "linux")]
+#[test]
+fn record_failure() {
+    let mut p = Vec::new();
+    out(&mut p, 5 << 60, 4);
+    p.extend([0x23, 0x07, 0x02, 0x00]); // in x7, x2
+    p.push(0x00);
+
+    let mut machine = Machine::new("replay-full", MEMORY, 1, true).unwrap();
+    machine.load(0, &p);
+    machine.set_recorder(Recorder::create("/dev/full").unwrap());
+    assert!(machine.run(100).is_err());
+    // 读取微秒计数器的一轮中已经执行的in不会撤销，之后不再继续运行
+    assert_eq!(machine.instruction_count(0), 4);
+}
+
+/// 所有事件都可以写成文本再解析回来
+#[test]
+fn event_text_round_trip() {
+    let events = [
+        TimedEvent::new(0, 10, Event::Link(256)),
+        TimedEvent::new(1, 20, Event::Input(257, b"vcore!\0\0".to_vec())),
+        TimedEvent::new(0, 30, Event::Clock),
+        TimedEvent::new(0, 40, Event::Start(1, 0x400)),
+        TimedEvent::new(2, 50, Event::Dma(0x8000, vec![1, 2, 3])),
+        TimedEvent::new(0, 60, Event::Time(123_456)),
+    ];
+    for event in events {
+        let text = event.to_string();
+        assert_eq!(TimedEvent::parse(&text), Ok(Some(event)));
+    }
+}
@@ -0,0 +1,82 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+
+use clap::Parser;
+use vcore::{config::Config, machine::Machine, utils::shared};
+
+/// 实例`instance`在/dev/shm中的共享内存
+fn segments(instance: &str) -> Vec<String> {
+    let prefix = shared::shared_name(instance, "Vcore");
+    fs::read_dir("/dev/shm")
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(&prefix))
+        .collect()
+}
+
+/// drop虚拟机时删除它创建的所有共享内存
+#[test]
+fn machine_drop() {
+    let machine = Machine::new("shared-drop", 4096, 2, true).unwrap();
+    assert!(!segments("shared-drop").is_empty());
+    drop(machine);
+    assert_eq!(segments("shared-drop"), Vec::<String>::new());
+}
+
+/// `cleanup`删除没有被drop的实例残留的共享内存
+#[test]
+fn cleanup() {
+    let machine = Machine::new("shared-cleanup", 4096, 2, true).unwrap();
+    let count = segments("shared-cleanup").len();
+    assert!(count > 0);
+    // 模拟崩溃的实例
+    std::mem::forget(machine);
+    assert_eq!(shared::cleanup("shared-cleanup"), count);
+    assert_eq!(segments("shared-cleanup"), Vec::<String>::new());
+    assert_eq!(shared::cleanup("shared-cleanup"), 0);
+}
+
+/// 同一实例的共享内存已经存在时创建失败，不会连接到旧的共享内存上
+#[test]
+fn collision() {
+    let machine = Machine::new("shared-collision", 4096, 1, true).unwrap();
+    let count = segments("shared-collision").len();
+    assert!(Machine::new("shared-collision", 4096, 1, true).is_err());
+    // 失败的创建不删除已经存在的共享内存
+    assert_eq!(segments("shared-collision").len(), count);
+    drop(machine);
+    Machine::new("shared-collision", 4096, 1, true).unwrap();
+}
+
+/// `cleanup`只删除此实例的共享内存，不删除标识以此实例标识开头的其它实例的共享内存
+#[test]
+fn cleanup_exact_names() {
+    let other = Machine::new("shared-exact.Vcore1", 4096, 1, true).unwrap();
+    let count = segments("shared-exact.Vcore1").len();
+    assert_eq!(shared::cleanup("shared-exact"), 0);
+    assert_eq!(segments("shared-exact.Vcore1").len(), count);
+    drop(other);
+
+    assert!(shared::is_segment("VcoreCore12StartFlg"));
+    assert!(shared::is_segment("VcoreIOPort3C1"));
+    assert!(shared::is_segment("VcoreIOPort256"));
+    assert!(!shared::is_segment("VcoreCoreStartFlg"));
+    assert!(!shared::is_segment("Vcore1.VcoreVriscMainMemory"));
+}
+
+/// 不能作为共享内存名前缀的实例标识在解析参数时被拒绝
+#[test]
+fn instance_check() {
+    assert!(shared::check_instance("vm0").is_ok());
+    assert!(shared::check_instance("").is_ok());
+    assert!(shared::check_instance("vm/0").is_err());
+    assert!(shared::check_instance("vm\0").is_err());
+    assert!(shared::check_instance(&"v".repeat(shared::MAX_INSTANCE_LEN + 1)).is_err());
+    let parse = |instance: &str| {
+        Config::try_parse_from(["vcore", "--cleanup", "--instance", instance]).map(|c| c.instance)
+    };
+    assert_eq!(parse("vm0").unwrap(), "vm0");
+    assert!(parse("vm/0").is_err());
+}
@@ -0,0 +1,104 @@
+use std::{env, fs};
+
+use vcore::machine::{
+    event::{Event, TimedEvent},
+    snapshot::{PortQueues, Snapshot, SNAPSHOT_MAGIC, SNAPSHOT_VERSION},
+    Machine,
+};
+
+mod common;
+
+use common::{count, enable_interrupt, install_handlers, out, MEMORY, PORT};
+
+const CORE1: u64 = 0x400;
+
+/// 两个核心、虚拟时间的时钟中断、已加载的扩展指令集和端口中尚未读出的设备输入
+fn machine(instance: &str) -> Machine {
+    let mut core0 = Vec::new();
+    enable_interrupt(&mut core0);
+    core0.extend([0x3d, 0x00]); // initext math
+    out(&mut core0, (2 << 60) | 20, 4);
+    out(&mut core0, 1 | (CORE1 << 32), 1);
+    count(&mut core0, 20000);
+    let mut core1 = Vec::new();
+    count(&mut core1, 15000);
+
+    let mut machine = Machine::new(instance, MEMORY, 2, true).unwrap();
+    machine.set_deterministic(true);
+    machine.load(0, &core0);
+    machine.load(CORE1, &core1);
+    // 每次设备中断只读出一个数据
+    install_handlers(&mut machine);
+    machine.schedule(TimedEvent::new(0, 10, Event::Link(PORT)));
+    machine.schedule(TimedEvent::new(
+        0,
+        5000,
+        Event::Input(PORT, b"vcore snapshot!!restore!".to_vec()),
+    ));
+    machine
+}
+
+/// 从快照恢复的虚拟机与原来的虚拟机继续运行得到完全相同的状态
+#[test]
+fn restore_continues_identically() {
+    let path = env::temp_dir().join("vcore-snapshot-test.snap");
+    let mut original = machine("snapshot-original");
+    original.run(20_000).unwrap();
+    original.snapshot().save(&path).unwrap();
+    let snapshot = Snapshot::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(snapshot, original.snapshot());
+
+    let mut restored = Machine::restore("snapshot-restored", &snapshot).unwrap();
+    restored.set_deterministic(true);
+    assert_eq!(restored.core(0).extensions, 1);
+    assert_eq!(restored.snapshot(), snapshot);
+
+    original.run(100_000).unwrap();
+    restored.run(100_000).unwrap();
+    assert_eq!(original.snapshot(), restored.snapshot());
+    assert_eq!(restored.core(1).regs.x[5], 15000);
+    assert!(restored.core(0).regs.x[3] > 0);
+    // 设备输入的后两个数据仍在端口中
+    let (_, queues) = &restored.snapshot().ports[0];
+    assert_eq!(queues.input.len(), 2);
+}
+
+/// 拒绝其它版本的快照
+#[test]
+fn reject_other_version() {
+    let path = env::temp_dir().join("vcore-snapshot-version.snap");
+    let mut data = SNAPSHOT_MAGIC.to_vec();
+    data.extend(2u64.to_le_bytes());
+    fs::write(&path, data).unwrap();
+    let err = Snapshot::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(err.contains("版本"));
+}
+
+/// 文件中的内存大小超过文件本身时报错而不是按它分配内存
+#[test]
+fn reject_truncated_memory() {
+    let path = env::temp_dir().join("vcore-snapshot-truncated.snap");
+    let mut data = SNAPSHOT_MAGIC.to_vec();
+    data.extend(SNAPSHOT_VERSION.to_le_bytes());
+    data.extend(u64::MAX.to_le_bytes());
+    data.extend([0; 16]);
+    fs::write(&path, data).unwrap();
+    let err = Snapshot::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(err.contains("内存大小"));
+}
+
+/// 固定端口属于不存在的核心时拒绝恢复
+#[test]
+fn reject_bad_core_index() {
+    let path = env::temp_dir().join("vcore-snapshot-core.snap");
+    let mut snapshot = machine("snapshot-core").snapshot();
+    snapshot.solid_ports.push((2, 4, PortQueues::default()));
+    assert!(Machine::restore("snapshot-core-restored", &snapshot).is_err());
+    snapshot.save(&path).unwrap();
+    let err = Snapshot::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(err.contains("核心2"));
+}
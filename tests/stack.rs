@@ -0,0 +1,142 @@
+use vcore::{
+    machine::Machine,
+    utils::shared::Addressable,
+    vrisc::vcore::intcontroller::{Access, InterruptId, Message},
+};
+
+mod common;
+
+use common::qword;
+
+/// ## 中断处理程序
+///
+/// InaccessibleAddress的处理程序记下返回地址x5和出错时的sp x6，把sp移到0x8000后重新执行；
+/// 设备中断的处理程序计数到x4，记下sp x9后用`ir 2`出栈返回
+const HANDLERS: &str = "
+fault:  sipdump x5
+        ldi x6, 0
+        add x6, x6, sp
+        ldi sp, 0x8000
+        ir 1
+device: inc x4
+        ldi x9, 0
+        add x9, x9, sp
+        ir 2
+";
+
+/// 载入中断向量表，以`flag`和sp=`sp`从0x100开始执行`body`
+fn boot(instance: &str, flag: u64, sp: u64, body: &str) -> Machine {
+    let program = format!(
+        "       ldi x0, 0x800
+                livt x0
+                ldi sp, {:#x}
+                ldi x7, {:#x}
+                lflagdump x7
+                ldi x8, 0x100
+                lipdump x8
+                ir 1
+        .org 0x100
+                {}
+                nop",
+        sp, flag, body
+    );
+    common::boot(instance, &program, HANDLERS)
+}
+
+/// 在两条指令之间产生设备中断
+fn interrupt_after(machine: &mut Machine, x1: u64) {
+    while machine.core(0).regs.x[1] != x1 {
+        machine.step().unwrap();
+    }
+    machine
+        .core_mut(0)
+        .intctler
+        .interrupt_with(InterruptId::DeviceCommunication, 0);
+}
+
+/// `push`、`pop`、`call`和`ret`，栈顶的8字节跨越两页
+#[test]
+fn push_pop_call_ret() {
+    let mut machine = boot(
+        "stack-call",
+        0,
+        0x4004,
+        "ldi x1, 0x1122334455667788
+        push x1
+        call abs16 func
+        pop x2
+        nop
+func:   ldi x3, 7
+        ret",
+    );
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!(
+        (regs.x[2], regs.x[3], regs.x[15]),
+        (0x1122334455667788, 7, 0x4004)
+    );
+    assert_eq!(qword(machine.memory(), 0x3ffc), 0x1122334455667788);
+    // call压入的返回地址是pop的地址
+    assert_eq!(qword(machine.memory(), 0x3ff4), 0x116);
+}
+
+/// 跨越到内存以外的`push`不写入任何字节，sp不变，处理后重新执行
+#[test]
+fn push_fault() {
+    let mut machine = boot(
+        "stack-push-fault",
+        1 << 8,
+        0x10004,
+        "ldi x1, 0x1122334455667788
+        push x1",
+    );
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[5], regs.x[6]), (0x10a, 0x10004));
+    assert_eq!(regs.imsg, Message::Access(0x10000, Access::Write).encode());
+    assert_eq!(*machine.memory().borrow().at(0xfffc), 0);
+    assert_eq!(regs.x[15], 0x7ff8);
+    assert_eq!(qword(machine.memory(), 0x7ff8), 0x1122334455667788);
+}
+
+/// InterruptStack置位时进入中断将返回地址和flag压栈，`ir 2`出栈返回
+#[test]
+fn interrupt_stack() {
+    let mut machine = boot(
+        "stack-interrupt",
+        (1 << 8) | (1 << 11),
+        0x4004,
+        "inc x1
+        inc x1",
+    );
+    interrupt_after(&mut machine, 1);
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[1], regs.x[4]), (2, 1));
+    assert_eq!((regs.x[9], regs.x[15]), (0x3ff4, 0x4004));
+    assert_eq!(qword(machine.memory(), 0x3ff4), 0x102);
+    let flag = qword(machine.memory(), 0x3ffc);
+    assert_eq!(flag & 0x900, 0x900);
+    assert_eq!(regs.flag & 0x900, 0x900);
+}
+
+/// 中断压栈失败时改为进入压栈产生的异常，被中断的中断保持待处理
+#[test]
+fn interrupt_stack_fault() {
+    let mut machine = boot(
+        "stack-interrupt-fault",
+        (1 << 8) | (1 << 11),
+        0x10004,
+        "inc x1
+        inc x1",
+    );
+    interrupt_after(&mut machine, 1);
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[5], regs.x[6]), (0x102, 0x10004));
+    assert_eq!(*machine.memory().borrow().at(0xfffc), 0);
+    // 移动sp后返回，设备中断再次进入并压栈成功
+    assert_eq!((regs.x[1], regs.x[4]), (2, 1));
+    assert_eq!((regs.x[9], regs.x[15]), (0x7ff0, 0x8000));
+    assert_eq!(qword(machine.memory(), 0x7ff0), 0x102);
+}
@@ -0,0 +1,146 @@
+use vcore::{
+    machine::Machine, utils::shared::Addressable, vrisc::vcore::intcontroller::InterruptId,
+};
+
+mod common;
+
+/// ## 中断处理程序
+///
+/// InaccessibleAddress的处理程序把x1减去0x4000，相当于映射了缺失的页，
+/// 设备中断的处理程序记下当时剩余的字节数x3。两者都计数到x4并返回重新执行被打断的指令
+const HANDLERS: &str = "
+fault:  inc x4
+        ldi x5, 0x4000
+        sub x1, x1, x5
+        ir 1
+device: inc x4
+        ldi x6, 0
+        add x6, x6, x3
+        ir 1
+";
+
+/// 开启中断后执行`body`，把0x2000开始的0x6000字节填为递增的数据
+fn boot(instance: &str, body: &str) -> Machine {
+    let program = format!(
+        "       ldi x0, 0x800
+                livt x0
+                ei
+                {}
+                nop",
+        body
+    );
+    let mut machine = common::boot(instance, &program, HANDLERS);
+    let data: Vec<u8> = (0..0x6000).map(|i| (i % 251) as u8).collect();
+    machine.load(0x2000, &data);
+    machine
+}
+
+fn memory(machine: &Machine, addr: u64, len: u64) -> Vec<u8> {
+    machine.memory().borrow().slice(addr, len).to_vec()
+}
+
+/// `movs`在目标跨入内存以外的页时产生异常，处理后从剩余的部分继续复制
+#[test]
+fn movs_fault() {
+    let mut machine = boot(
+        "string-movs-fault",
+        "ldi x1, 0xff80
+        ldi x2, 0x2000
+        ldi x3, 0x100
+        initext 3
+        .byte 0x5a, 0x21, 0x03  ; movs [x1], [x2], x3",
+    );
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!(regs.x[4], 1);
+    assert_eq!((regs.x[1], regs.x[2], regs.x[3]), (0xc080, 0x2100, 0));
+    assert_eq!(
+        memory(&machine, 0xff80, 0x80),
+        memory(&machine, 0x2000, 0x80)
+    );
+    assert_eq!(
+        memory(&machine, 0xc000, 0x80),
+        memory(&machine, 0x2080, 0x80)
+    );
+}
+
+/// `stos`同样只填充到出错的页之前
+#[test]
+fn stos_fault() {
+    let mut machine = boot(
+        "string-stos-fault",
+        "ldi x1, 0xffc0
+        ldi x2, 0xee
+        ldi x3, 0x80
+        initext 3
+        .byte 0x5b, 0x21, 0x03  ; stos [x1], x2, x3",
+    );
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!(regs.x[4], 1);
+    assert_eq!((regs.x[1], regs.x[3]), (0xc040, 0));
+    assert_eq!(memory(&machine, 0xffc0, 0x40), [0xee; 0x40]);
+    assert_eq!(
+        memory(&machine, 0xc000, 0x80),
+        [[0xee; 0x40], [0; 0x40]].concat()
+    );
+}
+
+/// 跨越多页的`movs`在页之间响应中断，返回后继续复制
+#[test]
+fn movs_interrupt() {
+    let mut machine = boot(
+        "string-movs-interrupt",
+        "ldi x1, 0xa000
+        ldi x2, 0x2000
+        ldi x3, 0x5000
+        initext 3
+        .byte 0x5a, 0x21, 0x03  ; movs [x1], [x2], x3",
+    );
+    // 执行到movs复制了第一段
+    while machine.core(0).regs.x[3] != 0x3000 {
+        machine.step().unwrap();
+    }
+    machine
+        .core_mut(0)
+        .intctler
+        .interrupt_with(InterruptId::DeviceCommunication, 0);
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[4], regs.x[6]), (1, 0x3000));
+    assert_eq!((regs.x[1], regs.x[2], regs.x[3]), (0xf000, 0x7000, 0));
+    assert_eq!(
+        memory(&machine, 0xa000, 0x5000),
+        memory(&machine, 0x2000, 0x5000)
+    );
+}
+
+/// `cmps`被中断后继续比较，停在第一个不同的字节
+#[test]
+fn cmps_interrupt() {
+    let mut machine = boot(
+        "string-cmps-interrupt",
+        "ldi x1, 0x2000
+        ldi x2, 0xa000
+        ldi x3, 0x6000
+        initext 3
+        .byte 0x5c, 0x21, 0x03  ; cmps [x1], [x2], x3",
+    );
+    let copy = memory(&machine, 0x2000, 0x6000);
+    machine.load(0xa000, &copy);
+    machine
+        .memory_mut()
+        .borrow_mut()
+        .write_slice(0xe010, &[0xff]);
+    while machine.core(0).regs.x[3] != 0x4000 {
+        machine.step().unwrap();
+    }
+    machine
+        .core_mut(0)
+        .intctler
+        .interrupt_with(InterruptId::DeviceCommunication, 0);
+    machine.run(100).unwrap();
+    let regs = &machine.core(0).regs;
+    assert_eq!((regs.x[4], regs.x[6]), (1, 0x4000));
+    assert_eq!((regs.x[1], regs.x[2], regs.x[3]), (0x6010, 0xe010, 0x1ff0));
+}
@@ -0,0 +1,62 @@
+use vcore::machine::Machine;
+
+mod common;
+
+use common::{enable_interrupt, install_handlers, ldi, loop_back, out, MEMORY};
+
+const STOP: u64 = 0;
+const PERIODIC_INSTRUCTIONS: u64 = 4 << 60;
+const ONESHOT_INSTRUCTIONS: u64 = 3 << 60;
+const READ_INSTRUCTIONS: u64 = 6 << 60;
+
+/// 向定时器端口写入命令
+fn timer(program: &mut Vec<u8>, command: u64) {
+    out(program, command, 4);
+}
+
+/// 打开中断，编程定时器后循环`rounds`次，停止定时器并读出指令计数器到x7
+fn run(instance: &str, command: u64, rounds: u64) -> Machine {
+    let mut p = Vec::new();
+    enable_interrupt(&mut p);
+    timer(&mut p, command);
+    ldi(&mut p, 6, rounds);
+    let body = p.len();
+    p.extend([0x04, 0x36]); // dec x6
+    loop_back(&mut p, 6, body);
+    timer(&mut p, STOP);
+    timer(&mut p, READ_INSTRUCTIONS);
+    p.extend([0x23, 0x07, 0x02, 0x00]); // in x7, x2
+    p.push(0x00);
+
+    let mut machine = Machine::new(instance, MEMORY, 1, true).unwrap();
+    machine.load(0, &p);
+    install_handlers(&mut machine);
+    machine.run(100_000).unwrap();
+    machine
+}
+
+/// 每100条指令产生一次时钟中断
+#[test]
+fn periodic_instruction_timer() {
+    let machine = run("timer-periodic", PERIODIC_INSTRUCTIONS | 100, 1000);
+    let count = machine.instruction_count(0);
+    assert_eq!(machine.core(0).regs.x[3], count / 100);
+    assert!((19..=21).contains(&machine.core(0).regs.x[3]));
+    // 读出的指令计数器不超过总的指令数
+    let read = machine.core(0).regs.x[7];
+    assert!(read > 2000 && read < count);
+}
+
+/// 单次触发只产生一次时钟中断
+#[test]
+fn oneshot_instruction_timer() {
+    let machine = run("timer-oneshot", ONESHOT_INSTRUCTIONS | 100, 1000);
+    assert_eq!(machine.core(0).regs.x[3], 1);
+}
+
+/// 停止的定时器（以及使用外部时钟时初始的定时器）不产生时钟中断
+#[test]
+fn stopped_timer() {
+    let machine = run("timer-stopped", STOP, 1000);
+    assert_eq!(machine.core(0).regs.x[3], 0);
+}
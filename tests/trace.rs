@@ -0,0 +1,144 @@
+use std::{env, fs, io::Cursor};
+
+use vcore::{
+    machine::{
+        trace::{register_name, MemoryAccess, TraceReader, Tracer, TRACE_MAGIC},
+        Machine,
+    },
+    vrisc::asm::assemble,
+};
+
+/// 写入、读取内存并执行一条原子指令
+const PROGRAM: &str = "
+        ldi x1, 0x1000
+        ldi x2, 0x2211
+        stm.q [x1], x2
+        ldm.w x3, [x1]
+        initext 2
+        .byte 0x57, 0x21, 0x30  ; xadd [x1], x2
+        nop
+";
+
+/// 运行`PROGRAM`并把跟踪写入文件，返回文件的内容
+fn trace(instance: &str, binary: bool) -> Vec<u8> {
+    let path = env::temp_dir().join(format!("vcore-{}.trace", instance));
+    let mut machine = Machine::new(instance, 64 * 1024, 1, true).unwrap();
+    machine.load(0, &assemble(PROGRAM).unwrap());
+    machine.set_tracer(Tracer::create(&path, binary).unwrap());
+    assert_eq!(machine.run(100).unwrap(), 7);
+    let trace = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    trace
+}
+
+#[test]
+fn text() {
+    let text = String::from_utf8(trace("trace-text", false)).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(
+        lines[0],
+        "0 0 ip=0x0 hot_ip=0x0 20130010000000000000 ldi.q x1, 0x1000 | x1=0x1000 |"
+    );
+    assert_eq!(
+        lines[2],
+        "0 2 ip=0x14 hot_ip=0x14 221203 stm.q [x1], x2 | | w[0x1000]=1122000000000000"
+    );
+    assert_eq!(
+        lines[3],
+        "0 3 ip=0x17 hot_ip=0x17 213101 ldm.w x3, [x1] | x3=0x2211 | r[0x1000]=1122"
+    );
+    assert!(lines[5].starts_with("0 5 ip=0x1c hot_ip=0x1c 572130 .byte 0x57 | "));
+    assert!(lines[6].ends_with("nop | |"));
+}
+
+/// 二进制跟踪读出后与文本跟踪相同
+#[test]
+fn binary() {
+    let binary = trace("trace-binary", true);
+    assert_eq!(binary[..8], TRACE_MAGIC);
+    let records = TraceReader::new(Cursor::new(&binary))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 7);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(record.core, 0);
+        assert_eq!(record.count, i as u64);
+        assert_eq!(record.ip, record.hot_ip);
+    }
+
+    // xadd读出原值、写入和，原值写入x2，x2恰好不变
+    let xadd = &records[5];
+    let old = 0x2211u64;
+    let new = old + old;
+    assert_eq!(
+        xadd.accesses,
+        [
+            MemoryAccess::new(false, 0x1000, old.to_le_bytes().to_vec()),
+            MemoryAccess::new(true, 0x1000, new.to_le_bytes().to_vec()),
+        ]
+    );
+    assert_eq!(xadd.changes, []);
+    assert_eq!(records[3].changes, [(3, 0x2211)]);
+    assert_eq!(register_name(3), "x3");
+
+    let text = String::from_utf8(trace("trace-binary-text", false)).unwrap();
+    for (record, line) in records.iter().zip(text.lines()) {
+        assert_eq!(record.to_string(), line);
+    }
+}
+
+/// 被截断的最后一条记录读取时出错，文件头不对时拒绝读取
+#[test]
+fn truncated() {
+    let binary = trace("trace-truncated", true);
+    let records: Vec<_> = TraceReader::new(Cursor::new(&binary[..binary.len() - 1]))
+        .unwrap()
+        .collect();
+    assert_eq!(records.len(), 7);
+    assert!(records[..6].iter().all(|record| record.is_ok()));
+    assert!(records[6].is_err());
+    assert!(TraceReader::new(Cursor::new(b"VCORESNP\x01")).is_err());
+}
+
+/// `stos`的写入出现在跟踪中
+#[test]
+fn stos() {
+    let source = "
+        ldi x1, 0x1000
+        ldi x2, 0x7a
+        ldi x3, 3
+        initext 3
+        .byte 0x5b, 0x21, 0x03  ; stos [x1], x2, x3
+        nop
+    ";
+    let path = env::temp_dir().join("vcore-trace-stos.trace");
+    let mut machine = Machine::new("trace-stos", 64 * 1024, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine.set_tracer(Tracer::create(&path, true).unwrap());
+    assert_eq!(machine.run(100).unwrap(), 6);
+    let records = TraceReader::open(&path)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(records[4].bytes, [0x5b, 0x21, 0x03]);
+    assert_eq!(
+        records[4].accesses,
+        [MemoryAccess::new(true, 0x1000, vec![0x7a; 3])]
+    );
+    assert_eq!(records[4].changes, [(1, 0x1003), (3, 0)]);
+}
+
+/// 文件头在`finish`之后才写出
+#[test]
+fn finish_flushes() {
+    let path = env::temp_dir().join("vcore-trace-finish.trace");
+    let tracer = Tracer::create(&path, true).unwrap();
+    assert!(fs::read(&path).unwrap().is_empty());
+    tracer.finish().unwrap();
+    let trace = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(trace.starts_with(&TRACE_MAGIC));
+}
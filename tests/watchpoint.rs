@@ -0,0 +1,145 @@
+#![cfg(feature = "debugger")]
+
+use vcore::{
+    debugger::watchpoint::{AccessKind, WatchAccess, Watchpoint, Watchpoints},
+    machine::Machine,
+    utils::shared::Addressable,
+    vrisc::asm::assemble,
+};
+
+mod common;
+
+use common::{ldi, MEMORY};
+
+/// 执行到核心共执行了`count`条指令
+fn run_to(machine: &mut Machine, count: u64) {
+    while machine.instruction_count(0) < count {
+        machine.step_core(0).unwrap();
+    }
+}
+
+/// `stm`和`ldm`分别触发写和读观察点，记录指令和新旧值
+#[test]
+fn read_and_write() {
+    let mut p = Vec::new();
+    ldi(&mut p, 1, 0xabcd);
+    ldi(&mut p, 2, 0x800);
+    p.extend([0x22, 0x21, 3]); // stm x1 -> [x2]
+    p.extend([0x21, 0x32, 3]); // ldm x3, [x2]
+    p.push(0x00);
+
+    let mut machine = Machine::new("watchpoint-rw", MEMORY, 1, true).unwrap();
+    machine.load(0, &p);
+    let watchpoints = &mut machine.core_mut(0).watchpoints;
+    let write = watchpoints
+        .add(Watchpoint::new(0x804, 4, WatchAccess::Write))
+        .unwrap();
+    let read = watchpoints
+        .add(Watchpoint::new(0x7f0, 0x20, WatchAccess::Read))
+        .unwrap();
+    run_to(&mut machine, 4);
+
+    let watchpoints = &machine.core(0).watchpoints;
+    let write = watchpoints.get(0).filter(|w| w.id == write).unwrap();
+    assert_eq!(write.hits, 1);
+    let hit = write.last.unwrap();
+    assert_eq!((hit.core, hit.ip, hit.opcode), (0, 20, 0x22));
+    assert_eq!(hit.kind, AccessKind::Write);
+    assert_eq!((hit.address, hit.old, hit.new), (0x804, 0, 0));
+
+    let read = watchpoints.get(1).filter(|w| w.id == read).unwrap();
+    assert_eq!(read.hits, 1);
+    let hit = read.last.unwrap();
+    assert_eq!((hit.ip, hit.opcode, hit.kind), (23, 0x21, AccessKind::Read));
+    assert_eq!((hit.address, hit.new), (0x800, 0xabcd));
+    assert_eq!(machine.core(0).regs.x[3], 0xabcd);
+}
+
+/// 核心以外对被观察内存的写入在`in`、`out`时被发现
+#[test]
+fn dma() {
+    let mut p = Vec::new();
+    ldi(&mut p, 2, 200);
+    p.extend([0x24, 0x01, 0x02, 0x00]); // out x1, x2
+    p.extend([0x24, 0x01, 0x02, 0x00]);
+    p.push(0x00);
+
+    let mut machine = Machine::new("watchpoint-dma", MEMORY, 1, true).unwrap();
+    machine.load(0, &p);
+    machine
+        .core_mut(0)
+        .watchpoints
+        .add(Watchpoint::new(0x900, 8, WatchAccess::ReadWrite))
+        .unwrap();
+    run_to(&mut machine, 2);
+    assert_eq!(machine.core(0).watchpoints.get(0).unwrap().hits, 0);
+    machine
+        .memory_mut()
+        .borrow_mut()
+        .write_slice(0x902, &[0x5a]);
+    run_to(&mut machine, 3);
+
+    let watchpoint = machine.core(0).watchpoints.get(0).unwrap();
+    assert_eq!(watchpoint.hits, 1);
+    let hit = watchpoint.last.unwrap();
+    assert_eq!((hit.ip, hit.kind), (14, AccessKind::Dma));
+    assert_eq!((hit.address, hit.old, hit.new), (0x902, 0, 0x5a));
+}
+
+/// 原子指令按实际交换的值触发观察点，`stos`在未开启跟踪时同样检查观察点
+#[test]
+fn atomic_and_stos() {
+    let source = "
+        ldi x1, 0x800
+        ldi x2, 5
+        initext 2
+        .byte 0x57, 0x21, 0x30  ; xadd [x1], x2
+        ldi x3, 0x900
+        ldi x4, 0x7a
+        ldi x5, 4
+        initext 3
+        .byte 0x5b, 0x43, 0x05  ; stos [x3], x4, x5
+        nop
+    ";
+    let mut machine = Machine::new("watchpoint-atomic", MEMORY, 1, true).unwrap();
+    machine.load(0, &assemble(source).unwrap());
+    machine
+        .memory_mut()
+        .borrow_mut()
+        .write_slice(0x800, &[0x10]);
+    let watchpoints = &mut machine.core_mut(0).watchpoints;
+    watchpoints
+        .add(Watchpoint::new(0x800, 8, WatchAccess::ReadWrite))
+        .unwrap();
+    watchpoints
+        .add(Watchpoint::new(0x902, 2, WatchAccess::Write))
+        .unwrap();
+    run_to(&mut machine, 9);
+
+    let watchpoints = &machine.core(0).watchpoints;
+    // 读出原值和写入新值各触发一次
+    let xadd = watchpoints.get(0).unwrap();
+    assert_eq!(xadd.hits, 2);
+    let hit = xadd.last.unwrap();
+    assert_eq!((hit.opcode, hit.kind), (0x57, AccessKind::Write));
+    assert_eq!((hit.address, hit.old, hit.new), (0x800, 0x10, 0x15));
+
+    let stos = watchpoints.get(1).unwrap();
+    assert_eq!(stos.hits, 1);
+    let hit = stos.last.unwrap();
+    assert_eq!((hit.opcode, hit.kind), (0x5b, AccessKind::Write));
+    assert_eq!((hit.address, hit.old, hit.new), (0x902, 0, 0x7a7a));
+}
+
+/// 到达地址空间末尾的观察点和访问不会溢出
+#[test]
+fn end_of_address_space() {
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.add(Watchpoint::new(u64::MAX - 7, u64::MAX, WatchAccess::Read));
+    watchpoints.read(u64::MAX - 3, &[1, 2, 3], (0, 0, 0x21));
+    assert!(watchpoints.take_triggered());
+    let hit = watchpoints.get(0).unwrap().last.unwrap();
+    assert_eq!((hit.address, hit.new), (u64::MAX - 3, 0x030201));
+    watchpoints.read(0x800, &[1; 8], (0, 0, 0x21));
+    assert!(!watchpoints.take_triggered());
+}